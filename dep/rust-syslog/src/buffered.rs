@@ -0,0 +1,193 @@
+//! Non-blocking logger front-end.
+//!
+//! `Logger::send_raw` writes to the socket on the caller's thread, which can
+//! stall latency-sensitive callers (e.g. PKCS#11 entry points) when the
+//! syslog daemon is slow. `AsyncLogger` decouples callers from the socket:
+//! messages are formatted and pushed into a bounded queue that a background
+//! flush thread drains, with a configurable policy for what happens when the
+//! queue is full.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+
+use {Logger, Severity};
+
+/// What to do when the queue is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued message to make room for the new one.
+    DropOldest,
+    /// Discard the new message.
+    DropNewest,
+    /// Block the caller until the flush thread makes room.
+    Block,
+}
+
+struct QueueState {
+    messages: VecDeque<Vec<u8>>,
+    /// True while the flush thread is writing a message it already popped.
+    in_flight: bool,
+    dropped: u64,
+    shutdown: bool,
+}
+
+struct Queue {
+    state: Mutex<QueueState>,
+    not_empty: Condvar,
+    not_full: Condvar,
+}
+
+/// A `Logger` wrapper whose send methods never block on the socket
+/// (except under `OverflowPolicy::Block` when the queue is full).
+pub struct AsyncLogger {
+    logger: Arc<Logger>,
+    queue: Arc<Queue>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    flusher: Option<JoinHandle<()>>,
+}
+
+impl AsyncLogger {
+    /// Wraps `logger`, spawning a flush thread that drains a queue of at
+    /// most `capacity` messages.
+    pub fn new(logger: Box<Logger>, capacity: usize, policy: OverflowPolicy) -> AsyncLogger {
+        let logger: Arc<Logger> = Arc::from(logger);
+        let queue = Arc::new(Queue {
+            state: Mutex::new(QueueState {
+                messages: VecDeque::new(),
+                in_flight: false,
+                dropped: 0,
+                shutdown: false,
+            }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        });
+
+        let flusher_logger = logger.clone();
+        let flusher_queue = queue.clone();
+        let flusher = thread::spawn(move || loop {
+            let message = {
+                let mut state = flusher_queue.state.lock().unwrap();
+                while state.messages.is_empty() && !state.shutdown {
+                    state = flusher_queue.not_empty.wait(state).unwrap();
+                }
+                match state.messages.pop_front() {
+                    Some(message) => {
+                        state.in_flight = true;
+                        message
+                    }
+                    None => return, // shutdown and drained
+                }
+            };
+            let _ = flusher_logger.send_raw(&message[..]);
+            let mut state = flusher_queue.state.lock().unwrap();
+            state.in_flight = false;
+            flusher_queue.not_full.notify_all();
+        });
+
+        AsyncLogger {
+            logger: logger,
+            queue: queue,
+            capacity: capacity,
+            policy: policy,
+            flusher: Some(flusher),
+        }
+    }
+
+    /// Formats a message with the logger's configured formatter and
+    /// enqueues it for the flush thread.
+    pub fn send(&self, severity: Severity, message: &str) -> Result<(), io::Error> {
+        self.enqueue(self.logger.format_message(severity, message).into_bytes())
+    }
+
+    fn enqueue(&self, message: Vec<u8>) -> Result<(), io::Error> {
+        let mut state = self.queue.state.lock().unwrap();
+        if state.shutdown {
+            return Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "logger is shut down",
+            ));
+        }
+        if state.messages.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::DropOldest => {
+                    state.messages.pop_front();
+                    state.dropped += 1;
+                }
+                OverflowPolicy::DropNewest => {
+                    state.dropped += 1;
+                    return Ok(());
+                }
+                OverflowPolicy::Block => {
+                    while state.messages.len() >= self.capacity && !state.shutdown {
+                        state = self.queue.not_full.wait(state).unwrap();
+                    }
+                }
+            }
+        }
+        state.messages.push_back(message);
+        self.queue.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Blocks until every queued message has been handed to the socket.
+    pub fn flush(&self) {
+        let mut state = self.queue.state.lock().unwrap();
+        while !state.messages.is_empty() || state.in_flight {
+            state = self.queue.not_full.wait(state).unwrap();
+        }
+    }
+
+    /// Number of messages discarded so far under a drop policy.
+    pub fn dropped(&self) -> u64 {
+        self.queue.state.lock().unwrap().dropped
+    }
+
+    pub fn emerg(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_EMERG, message)
+    }
+
+    pub fn alert(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_ALERT, message)
+    }
+
+    pub fn crit(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_CRIT, message)
+    }
+
+    pub fn err(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_ERR, message)
+    }
+
+    pub fn warning(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_WARNING, message)
+    }
+
+    pub fn notice(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_NOTICE, message)
+    }
+
+    pub fn info(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_INFO, message)
+    }
+
+    pub fn debug(&self, message: &str) -> Result<(), io::Error> {
+        self.send(Severity::LOG_DEBUG, message)
+    }
+}
+
+impl Drop for AsyncLogger {
+    fn drop(&mut self) {
+        {
+            let mut state = self.queue.state.lock().unwrap();
+            state.shutdown = true;
+        }
+        self.queue.not_empty.notify_all();
+        self.queue.not_full.notify_all();
+        if let Some(flusher) = self.flusher.take() {
+            let _ = flusher.join();
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Syslog facilities, as defined in RFC 3164 and `<syslog.h>`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[allow(non_camel_case_types)]
+pub enum Facility {
+    LOG_KERN     = 0  << 3,
+    LOG_USER     = 1  << 3,
+    LOG_MAIL     = 2  << 3,
+    LOG_DAEMON   = 3  << 3,
+    LOG_AUTH     = 4  << 3,
+    LOG_SYSLOG   = 5  << 3,
+    LOG_LPR      = 6  << 3,
+    LOG_NEWS     = 7  << 3,
+    LOG_UUCP     = 8  << 3,
+    LOG_CRON     = 9  << 3,
+    LOG_AUTHPRIV = 10 << 3,
+    LOG_FTP      = 11 << 3,
+    LOG_LOCAL0   = 16 << 3,
+    LOG_LOCAL1   = 17 << 3,
+    LOG_LOCAL2   = 18 << 3,
+    LOG_LOCAL3   = 19 << 3,
+    LOG_LOCAL4   = 20 << 3,
+    LOG_LOCAL5   = 21 << 3,
+    LOG_LOCAL6   = 22 << 3,
+    LOG_LOCAL7   = 23 << 3,
+}
+
+impl FromStr for Facility {
+    type Err = ();
+    fn from_str(s: &str) -> Result<Facility, ()> {
+        let result = match &s.to_lowercase()[..] {
+            "log_kern"     | "kern"     => Facility::LOG_KERN,
+            "log_user"     | "user"     => Facility::LOG_USER,
+            "log_mail"     | "mail"     => Facility::LOG_MAIL,
+            "log_daemon"   | "daemon"   => Facility::LOG_DAEMON,
+            "log_auth"     | "auth"     => Facility::LOG_AUTH,
+            "log_syslog"   | "syslog"   => Facility::LOG_SYSLOG,
+            "log_lpr"      | "lpr"      => Facility::LOG_LPR,
+            "log_news"     | "news"     => Facility::LOG_NEWS,
+            "log_uucp"     | "uucp"     => Facility::LOG_UUCP,
+            "log_cron"     | "cron"     => Facility::LOG_CRON,
+            "log_authpriv" | "authpriv" => Facility::LOG_AUTHPRIV,
+            "log_ftp"      | "ftp"      => Facility::LOG_FTP,
+            "log_local0"   | "local0"   => Facility::LOG_LOCAL0,
+            "log_local1"   | "local1"   => Facility::LOG_LOCAL1,
+            "log_local2"   | "local2"   => Facility::LOG_LOCAL2,
+            "log_local3"   | "local3"   => Facility::LOG_LOCAL3,
+            "log_local4"   | "local4"   => Facility::LOG_LOCAL4,
+            "log_local5"   | "local5"   => Facility::LOG_LOCAL5,
+            "log_local6"   | "local6"   => Facility::LOG_LOCAL6,
+            "log_local7"   | "local7"   => Facility::LOG_LOCAL7,
+            _ => return Err(()),
+        };
+        Ok(result)
+    }
+}
+
+impl fmt::Display for Facility {
+    /// Writes the lowercase short name accepted by `FromStr`, e.g. "daemon".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Facility::LOG_KERN => "kern",
+            Facility::LOG_USER => "user",
+            Facility::LOG_MAIL => "mail",
+            Facility::LOG_DAEMON => "daemon",
+            Facility::LOG_AUTH => "auth",
+            Facility::LOG_SYSLOG => "syslog",
+            Facility::LOG_LPR => "lpr",
+            Facility::LOG_NEWS => "news",
+            Facility::LOG_UUCP => "uucp",
+            Facility::LOG_CRON => "cron",
+            Facility::LOG_AUTHPRIV => "authpriv",
+            Facility::LOG_FTP => "ftp",
+            Facility::LOG_LOCAL0 => "local0",
+            Facility::LOG_LOCAL1 => "local1",
+            Facility::LOG_LOCAL2 => "local2",
+            Facility::LOG_LOCAL3 => "local3",
+            Facility::LOG_LOCAL4 => "local4",
+            Facility::LOG_LOCAL5 => "local5",
+            Facility::LOG_LOCAL6 => "local6",
+            Facility::LOG_LOCAL7 => "local7",
+        };
+        f.write_str(name)
+    }
+}
+
+impl TryFrom<u8> for Facility {
+    type Error = ();
+
+    /// Accepts the facility code as it appears in a priority value: the
+    /// RFC 3164 facility number already shifted left by three, which is
+    /// also what `facility as u8` yields. Codes with severity bits set,
+    /// or for facility numbers this enum does not define (12-15), are
+    /// rejected.
+    fn try_from(code: u8) -> Result<Facility, ()> {
+        if code & 0x07 != 0 {
+            return Err(());
+        }
+        let result = match code >> 3 {
+            0 => Facility::LOG_KERN,
+            1 => Facility::LOG_USER,
+            2 => Facility::LOG_MAIL,
+            3 => Facility::LOG_DAEMON,
+            4 => Facility::LOG_AUTH,
+            5 => Facility::LOG_SYSLOG,
+            6 => Facility::LOG_LPR,
+            7 => Facility::LOG_NEWS,
+            8 => Facility::LOG_UUCP,
+            9 => Facility::LOG_CRON,
+            10 => Facility::LOG_AUTHPRIV,
+            11 => Facility::LOG_FTP,
+            16 => Facility::LOG_LOCAL0,
+            17 => Facility::LOG_LOCAL1,
+            18 => Facility::LOG_LOCAL2,
+            19 => Facility::LOG_LOCAL3,
+            20 => Facility::LOG_LOCAL4,
+            21 => Facility::LOG_LOCAL5,
+            22 => Facility::LOG_LOCAL6,
+            23 => Facility::LOG_LOCAL7,
+            _ => return Err(()),
+        };
+        Ok(result)
+    }
+}
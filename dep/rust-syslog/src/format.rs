@@ -0,0 +1,273 @@
+//! Pluggable wire formats.
+//!
+//! A `Formatter` turns a message plus its logger-supplied context into the
+//! bytes that go on the wire. The built-in formats are `Rfc3164`, `Rfc5424`
+//! and `Plain`; users can implement the trait themselves (e.g. LEEF/CEF)
+//! without touching `Logger` internals. `Rfc3164` and `Rfc5424` live behind
+//! the cargo features of the same (lowercased) names, which also carry the
+//! `time` dependency.
+
+use std::fmt::{self, Write};
+
+use libc;
+#[cfg(feature = "time")]
+use time;
+
+use structured::{self, StructuredDataBuilder};
+use {Facility, Priority, Severity};
+
+/// Everything a formatter may need about a message besides its body.
+pub struct MessageContext<'a> {
+    pub severity: Severity,
+    pub facility: Facility,
+    pub hostname: Option<&'a str>,
+    pub process: &'a str,
+    pub pid: libc::pid_t,
+    /// The logging thread's id, when the logger was told to record it.
+    pub thread_id: Option<u64>,
+    pub message_id: Option<i32>,
+    pub structured_data: Option<&'a StructuredDataBuilder>,
+    /// The logging thread's mapped diagnostic context (see the `mdc`
+    /// module), snapshotted when the message was logged.
+    pub mdc: Vec<(String, String)>,
+    /// Message time, in UTC; formatters localize as their format requires.
+    /// Only present when a timestamped format is compiled in — the `Plain`
+    /// format (and GELF, which reads the clock itself) do without.
+    #[cfg(feature = "time")]
+    pub timestamp: time::Tm,
+}
+
+impl<'a> MessageContext<'a> {
+    pub fn priority(&self) -> Priority {
+        self.facility as u8 | self.severity as u8
+    }
+}
+
+pub trait Formatter: Send + Sync {
+    fn format(&self, ctx: &MessageContext, message: &str) -> String;
+
+    /// Formats into a reusable buffer. The default goes through `format`;
+    /// implementations can override it to skip the intermediate String.
+    fn format_into(&self, ctx: &MessageContext, buf: &mut String, message: fmt::Arguments) {
+        buf.push_str(&self.format(ctx, &message.to_string()));
+    }
+}
+
+/// The traditional BSD syslog format (RFC 3164).
+#[cfg(feature = "rfc3164")]
+pub struct Rfc3164;
+
+#[cfg(feature = "rfc3164")]
+impl Formatter for Rfc3164 {
+    fn format(&self, ctx: &MessageContext, message: &str) -> String {
+        let mut buf = String::new();
+        self.format_into(ctx, &mut buf, format_args!("{}", message));
+        buf
+    }
+
+    fn format_into(&self, ctx: &MessageContext, buf: &mut String, message: fmt::Arguments) {
+        let timestamp = rfc3164_timestamp(&ctx.timestamp.to_local());
+        let tag = sanitize_tag(ctx.process);
+        let _ = if let Some(hostname) = ctx.hostname {
+            write!(
+                buf,
+                "<{}>{} {} {}[{}]: {}",
+                ctx.priority(),
+                timestamp,
+                hostname,
+                tag,
+                ctx.pid,
+                message
+            )
+        } else {
+            write!(
+                buf,
+                "<{}>{} {}[{}]: {}",
+                ctx.priority(),
+                timestamp,
+                tag,
+                ctx.pid,
+                message
+            )
+        };
+        for &(ref key, ref value) in &ctx.mdc {
+            let _ = write!(buf, " {}={}", key, value);
+        }
+    }
+}
+
+/// RFC 3164 restricts TAG to at most 32 alphanumeric characters, and
+/// receivers commonly stop parsing it at the first character outside that
+/// set. Process names are not so well behaved — they can be full paths
+/// with dots and dashes — so strip any leading path, replace what a
+/// receiver would reject, and clamp the length.
+pub fn sanitize_tag(name: &str) -> String {
+    let base = name.rsplit('/').next().unwrap_or(name);
+    let tag: String = base
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .take(32)
+        .collect();
+    if tag.is_empty() {
+        return "unknown".to_owned();
+    }
+    tag
+}
+
+/// The RFC 3164 TIMESTAMP: an English month abbreviation and a
+/// space-padded day, regardless of the system locale. strftime's "%d"
+/// zero-pads and "%b" localizes the month, both of which confuse strict
+/// receivers.
+#[cfg(feature = "rfc3164")]
+pub fn rfc3164_timestamp(tm: &time::Tm) -> String {
+    const MONTHS: [&'static str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    format!(
+        "{} {:2} {:02}:{:02}:{:02}",
+        MONTHS[tm.tm_mon as usize % 12],
+        tm.tm_mday,
+        tm.tm_hour,
+        tm.tm_min,
+        tm.tm_sec
+    )
+}
+
+/// RFC 5424, with millisecond timestamps and NILVALUE for unknown fields.
+#[cfg(feature = "rfc5424")]
+pub struct Rfc5424;
+
+#[cfg(feature = "rfc5424")]
+impl Formatter for Rfc5424 {
+    fn format(&self, ctx: &MessageContext, message: &str) -> String {
+        let procid = if ctx.pid > 0 {
+            ctx.pid.to_string()
+        } else {
+            "-".to_owned()
+        };
+        let msgid = match ctx.message_id {
+            Some(id) => id.to_string(),
+            None => "-".to_owned(),
+        };
+        let mut data = match ctx.structured_data {
+            Some(data) => data.render(),
+            None => "-".to_owned(),
+        };
+        if let Some(tid) = ctx.thread_id {
+            let element = structured::render_raw_element(
+                "proc@kr",
+                &[("tid".to_owned(), tid.to_string())],
+            );
+            if data == "-" {
+                data = element;
+            } else {
+                data.push_str(&element);
+            }
+        }
+        if !ctx.mdc.is_empty() {
+            let element = structured::render_raw_element("mdc@kr", &ctx.mdc);
+            if data == "-" {
+                data = element;
+            } else {
+                data.push_str(&element);
+            }
+        }
+        format!(
+            "<{}>1 {}.{:03}Z {} {} {} {} {} {}",
+            ctx.priority(),
+            ctx.timestamp.strftime("%Y-%m-%dT%H:%M:%S").unwrap(),
+            ctx.timestamp.tm_nsec / 1_000_000,
+            ctx.hostname.unwrap_or("-"),
+            ctx.process,
+            procid,
+            msgid,
+            data,
+            message
+        )
+    }
+}
+
+/// Just the priority header and the message body.
+pub struct Plain;
+
+impl Formatter for Plain {
+    fn format(&self, ctx: &MessageContext, message: &str) -> String {
+        format!("<{}>{}", ctx.priority(), message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "rfc3164")]
+    fn tm(month: i32, day: i32) -> time::Tm {
+        time::Tm {
+            tm_sec: 5,
+            tm_min: 4,
+            tm_hour: 3,
+            tm_mday: day,
+            tm_mon: month,
+            tm_year: 119,
+            tm_wday: 0,
+            tm_yday: 0,
+            tm_isdst: 0,
+            tm_utcoff: 0,
+            tm_nsec: 0,
+        }
+    }
+
+    #[test]
+    fn sanitize_tag_strips_paths_and_invalid_chars() {
+        assert_eq!(sanitize_tag("sshd"), "sshd");
+        assert_eq!(sanitize_tag("/usr/sbin/my-app.bin"), "my_app_bin");
+        assert_eq!(sanitize_tag(&"a".repeat(40)), "a".repeat(32));
+        assert_eq!(sanitize_tag(""), "unknown");
+        assert_eq!(sanitize_tag("/"), "unknown");
+    }
+
+    #[cfg(feature = "rfc3164")]
+    #[test]
+    fn rfc3164_tag_is_sanitized() {
+        let ctx = MessageContext {
+            severity: Severity::LOG_INFO,
+            facility: Facility::LOG_USER,
+            hostname: None,
+            process: "/usr/libexec/ssh agent",
+            pid: 7,
+            thread_id: None,
+            message_id: None,
+            structured_data: None,
+            mdc: Vec::new(),
+            timestamp: time::now_utc(),
+        };
+        let formatted = Rfc3164.format(&ctx, "hi");
+        assert!(formatted.contains("ssh_agent[7]: hi"), "{}", formatted);
+    }
+
+    #[cfg(feature = "rfc3164")]
+    #[test]
+    fn rfc3164_timestamp_space_pads_single_digit_days() {
+        assert_eq!(rfc3164_timestamp(&tm(0, 7)), "Jan  7 03:04:05");
+    }
+
+    #[cfg(feature = "rfc3164")]
+    #[test]
+    fn rfc3164_timestamp_two_digit_days() {
+        assert_eq!(rfc3164_timestamp(&tm(0, 17)), "Jan 17 03:04:05");
+    }
+
+    #[cfg(feature = "rfc3164")]
+    #[test]
+    fn rfc3164_timestamp_all_months() {
+        let months = [
+            "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+        ];
+        for (i, month) in months.iter().enumerate() {
+            assert_eq!(
+                rfc3164_timestamp(&tm(i as i32, 12)),
+                format!("{} 12 03:04:05", month)
+            );
+        }
+    }
+}
@@ -0,0 +1,2325 @@
+//! Syslog
+//!
+//! This crate provides facilities to send log messages via syslog.
+//! It supports Unix sockets for local syslog, UDP and TCP for remote servers.
+//!
+//! Messages can be passed directly without modification, or in RFC 3164 or RFC 5424 format.
+//!
+//! The RFC 3164 and RFC 5424 formatters and the log-crate integration sit
+//! behind the default-on cargo features `rfc3164`, `rfc5424` and
+//! `log-integration`; embedders that only use the raw send paths (like the
+//! kr pkcs11 shim) can disable them and drop the `time` and `log`
+//! dependencies from their binaries.
+//!
+//! This is a vendored fork maintained for kr; upstream is
+//! <https://github.com/Geal/rust-syslog>.
+#![crate_type = "lib"]
+
+extern crate flate2;
+extern crate libc;
+#[cfg(feature = "log-integration")]
+extern crate log;
+extern crate native_tls;
+extern crate serde_json;
+#[cfg(feature = "time")]
+extern crate time;
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use native_tls::{Certificate, Identity, TlsConnector, TlsStream};
+
+use libc::getpid;
+#[cfg(feature = "log-integration")]
+use log::{Log, LogLevel, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+
+mod facility;
+pub use facility::Facility;
+
+pub mod format;
+pub use format::{Formatter, MessageContext};
+
+pub mod buffered;
+pub use buffered::{AsyncLogger, OverflowPolicy};
+
+pub mod gelf;
+
+pub mod mdc;
+
+pub mod registry;
+
+pub mod structured;
+pub use structured::{StructuredDataBuilder, StructuredDataError};
+
+pub type Priority = u8;
+
+#[allow(non_camel_case_types)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    LOG_EMERG,
+    LOG_ALERT,
+    LOG_CRIT,
+    LOG_ERR,
+    LOG_WARNING,
+    LOG_NOTICE,
+    LOG_INFO,
+    LOG_DEBUG,
+}
+
+impl fmt::Display for Severity {
+    /// Writes the lowercase short name accepted by `FromStr`, e.g. "err".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Severity::LOG_EMERG => "emerg",
+            Severity::LOG_ALERT => "alert",
+            Severity::LOG_CRIT => "crit",
+            Severity::LOG_ERR => "err",
+            Severity::LOG_WARNING => "warning",
+            Severity::LOG_NOTICE => "notice",
+            Severity::LOG_INFO => "info",
+            Severity::LOG_DEBUG => "debug",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for Severity {
+    type Err = ();
+
+    /// Accepts the `LOG_`-prefixed and bare names, plus the deprecated
+    /// aliases `<syslog.h>` documents ("panic", "error", "warn").
+    fn from_str(s: &str) -> Result<Severity, ()> {
+        let result = match &s.to_lowercase()[..] {
+            "log_emerg" | "emerg" | "panic" => Severity::LOG_EMERG,
+            "log_alert" | "alert" => Severity::LOG_ALERT,
+            "log_crit" | "crit" => Severity::LOG_CRIT,
+            "log_err" | "err" | "error" => Severity::LOG_ERR,
+            "log_warning" | "warning" | "warn" => Severity::LOG_WARNING,
+            "log_notice" | "notice" => Severity::LOG_NOTICE,
+            "log_info" | "info" => Severity::LOG_INFO,
+            "log_debug" | "debug" => Severity::LOG_DEBUG,
+            _ => return Err(()),
+        };
+        Ok(result)
+    }
+}
+
+impl TryFrom<u8> for Severity {
+    type Error = ();
+
+    /// Accepts the RFC 3164 severity number 0-7, which is also what
+    /// `severity as u8` yields.
+    fn try_from(code: u8) -> Result<Severity, ()> {
+        let result = match code {
+            0 => Severity::LOG_EMERG,
+            1 => Severity::LOG_ALERT,
+            2 => Severity::LOG_CRIT,
+            3 => Severity::LOG_ERR,
+            4 => Severity::LOG_WARNING,
+            5 => Severity::LOG_NOTICE,
+            6 => Severity::LOG_INFO,
+            7 => Severity::LOG_DEBUG,
+            _ => return Err(()),
+        };
+        Ok(result)
+    }
+}
+
+/// Splits a priority value as found in a message's `<N>` header back into
+/// its facility and severity, the inverse of `MessageContext::priority`.
+/// Fails on the facility numbers this crate does not define (12-15).
+pub fn decode_priority(priority: Priority) -> Result<(Facility, Severity), ()> {
+    let facility = Facility::try_from(priority & 0xf8)?;
+    let severity = Severity::try_from(priority & 0x07)?;
+    Ok((facility, severity))
+}
+
+pub enum LoggerBackend {
+    /// Unix datagram socket and the path it is connected to
+    Unix(Mutex<UnixDatagram>, PathBuf),
+    /// Unix stream socket and the path it is connected to, for daemons
+    /// whose socket is SOCK_STREAM (some syslog-ng configurations, macOS
+    /// /var/run/syslog); messages are newline-delimited on the stream
+    UnixStream(Mutex<UnixStream>, PathBuf),
+    Udp(UdpSocket, SocketAddr),
+    /// TCP stream and the server address it was connected to, kept so the
+    /// connection can be re-established (re-resolving DNS) after a drop
+    Tcp(Arc<Mutex<TcpStream>>, String),
+    /// RFC 5425: TLS over TCP with octet-counted framing
+    Tls(Arc<Mutex<TlsStream<TcpStream>>>),
+    /// systemd journal datagram socket, native journal protocol
+    Journald(Mutex<UnixDatagram>),
+    /// Graylog over UDP: payloads are gzipped and chunked by the encoder
+    GelfUdp(UdpSocket, SocketAddr, gelf::ChunkedGelfEncoder),
+    /// The process's stderr; the container-friendly last resort
+    Stderr,
+    /// An append-only log file at the given path
+    File(Mutex<File>, PathBuf),
+    /// Discards every message; the terminal fallback when no endpoint in
+    /// the configured chain is reachable
+    Null,
+}
+
+/// Where the systemd journal listens for native-protocol datagrams.
+const JOURNALD_PATH: &'static str = "/run/systemd/journal/socket";
+
+/// Budget for a coalesced `send_batch` UDP datagram: an ethernet MTU minus
+/// IPv6 and UDP headers, so batches survive the common path unfragmented.
+const UDP_BATCH_DATAGRAM_SIZE: usize = 1452;
+
+thread_local! {
+    /// Reused per-thread formatting buffer for `send_fmt`, so chatty call
+    /// sites do not allocate a fresh String per message.
+    static FORMAT_BUF: RefCell<String> = RefCell::new(String::with_capacity(256));
+}
+
+/// How messages are delimited on a TCP transport, per RFC 6587.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TcpFraming {
+    /// "MSG-LEN SP SYSLOG-MSG"; unambiguous, the default.
+    OctetCounted,
+    /// Messages terminated by the given trailer byte (usually `\n`),
+    /// for receivers that do not understand octet counting.
+    NonTransparent(u8),
+}
+
+/// What to do with a message body longer than the configured maximum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeLimitPolicy {
+    /// Cut the message at the limit and append a `[...]` marker.
+    Truncate,
+    /// Split into continuation messages tagged with a shared correlation
+    /// id, e.g. `[1234-7 2/3] ...`.
+    Split,
+}
+
+/// Controls how `send_raw` recovers when the syslog daemon restarts and the
+/// connected socket goes dead.
+#[derive(Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// How many reconnection attempts to make before giving up.
+    pub max_retries: u32,
+    /// Delay before the first reconnection attempt; doubles on each retry.
+    pub initial_backoff: Duration,
+    /// Upper bound on the backoff delay.
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// A snapshot of a Logger's I/O counters, from `Logger::stats`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoggerStats {
+    pub messages_sent: u64,
+    pub bytes_written: u64,
+    pub send_errors: u64,
+    pub reconnects: u64,
+    /// Messages suppressed by severity thresholds.
+    pub messages_dropped: u64,
+}
+
+struct Counters {
+    messages_sent: AtomicUsize,
+    bytes_written: AtomicUsize,
+    send_errors: AtomicUsize,
+    reconnects: AtomicUsize,
+    messages_dropped: AtomicUsize,
+}
+
+impl Counters {
+    fn new() -> Counters {
+        Counters {
+            messages_sent: ATOMIC_USIZE_INIT,
+            bytes_written: ATOMIC_USIZE_INIT,
+            send_errors: ATOMIC_USIZE_INIT,
+            reconnects: ATOMIC_USIZE_INIT,
+            messages_dropped: ATOMIC_USIZE_INIT,
+        }
+    }
+}
+
+/// TLS settings for the RFC 5425 transport.
+pub struct TlsConfig {
+    /// Server name presented for SNI and certificate validation.
+    pub domain: String,
+    /// Optional PEM file with additional trusted root certificates.
+    pub ca_file: Option<PathBuf>,
+    /// Optional PKCS#12 client identity and its password.
+    pub identity: Option<(PathBuf, String)>,
+}
+
+impl TlsConfig {
+    pub fn new(domain: &str) -> TlsConfig {
+        TlsConfig {
+            domain: domain.to_owned(),
+            ca_file: None,
+            identity: None,
+        }
+    }
+}
+
+/// Shorthand for the built-in formats, accepted by `Builder::format`.
+/// The timestamped variants exist only when their cargo feature is
+/// enabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    #[cfg(feature = "rfc3164")]
+    RFC3164,
+    #[cfg(feature = "rfc5424")]
+    RFC5424,
+    /// Priority header and message body only.
+    Plain,
+}
+
+impl LogFormat {
+    fn formatter(self) -> Box<Formatter> {
+        match self {
+            #[cfg(feature = "rfc3164")]
+            LogFormat::RFC3164 => Box::new(format::Rfc3164),
+            #[cfg(feature = "rfc5424")]
+            LogFormat::RFC5424 => Box::new(format::Rfc5424),
+            LogFormat::Plain => Box::new(format::Plain),
+        }
+    }
+}
+
+/// The construction-time default format: RFC 3164 when compiled in, the
+/// plain priority-and-body format otherwise.
+#[cfg(feature = "rfc3164")]
+fn default_formatter() -> Box<Formatter> {
+    Box::new(format::Rfc3164)
+}
+
+#[cfg(not(feature = "rfc3164"))]
+fn default_formatter() -> Box<Formatter> {
+    Box::new(format::Plain)
+}
+
+/// Identity of the logging process as emitted on the wire.
+pub struct ProcessInfo {
+    /// The RFC 5424 APP-NAME (RFC 3164 TAG) field.
+    pub name: String,
+    /// The PROCID field, in the platform's `pid_t`.
+    pub pid: libc::pid_t,
+    /// When set, RFC 5424 messages carry the calling thread's id as a
+    /// `proc@kr tid` structured-data param, so multi-threaded shims can
+    /// tell their threads apart.
+    pub include_thread_id: bool,
+}
+
+impl ProcessInfo {
+    /// Captures the running process's name and pid.
+    pub fn current() -> ProcessInfo {
+        ProcessInfo {
+            name: get_process_name().unwrap_or_else(|| "rust-syslog".to_owned()),
+            pid: unsafe { getpid() },
+            include_thread_id: false,
+        }
+    }
+
+    /// A validated identity; pids are positive by definition, so anything
+    /// else is rejected instead of emitted verbatim.
+    pub fn new(name: &str, pid: libc::pid_t) -> Result<ProcessInfo, io::Error> {
+        if pid <= 0 {
+            return Err(invalid_pid(pid));
+        }
+        Ok(ProcessInfo {
+            name: name.to_owned(),
+            pid: pid,
+            include_thread_id: false,
+        })
+    }
+}
+
+fn invalid_pid(pid: libc::pid_t) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid pid {}", pid),
+    )
+}
+
+/// Main logging structure
+pub struct Logger {
+    facility: Facility,
+    hostname: Option<String>,
+    process_info: ProcessInfo,
+    formatter: Box<Formatter>,
+    reconnect: ReconnectPolicy,
+    tcp_framing: TcpFraming,
+    min_severity: Option<Severity>,
+    target_severity: HashMap<String, Severity>,
+    max_message_size: Option<usize>,
+    size_limit_policy: SizeLimitPolicy,
+    write_timeout: Option<Duration>,
+    drop_on_timeout: bool,
+    last_error: Mutex<Option<io::Error>>,
+    counters: Counters,
+    pub s: LoggerBackend,
+}
+
+enum BackendConfig {
+    Unix { path: Option<PathBuf> },
+    Udp { local: SocketAddr, server: SocketAddr },
+    Tcp { server: String },
+    Tls { server: String, tls_config: TlsConfig },
+    Journald,
+    GelfUdp { local: SocketAddr, server: SocketAddr },
+    Stderr,
+    File(PathBuf),
+    Null,
+}
+
+impl BackendConfig {
+    /// A stable identity for connection sharing (see the `registry`
+    /// module).
+    fn shared_key(&self) -> String {
+        match *self {
+            BackendConfig::Unix { path: None } => "unix".to_owned(),
+            BackendConfig::Unix { path: Some(ref path) } => format!("unix:{}", path.display()),
+            BackendConfig::Udp { ref server, .. } => format!("udp:{}", server),
+            BackendConfig::Tcp { ref server } => format!("tcp:{}", server),
+            BackendConfig::Tls { ref server, .. } => format!("tls:{}", server),
+            BackendConfig::Journald => "journald".to_owned(),
+            BackendConfig::GelfUdp { ref server, .. } => format!("gelf:{}", server),
+            BackendConfig::Stderr => "stderr".to_owned(),
+            BackendConfig::File(ref path) => format!("file:{}", path.display()),
+            BackendConfig::Null => "null".to_owned(),
+        }
+    }
+}
+
+/// Configures and connects a `Logger`.
+///
+/// The free `unix`/`udp`/`tcp`/`tls` functions are thin wrappers around
+/// this; use the builder directly when overriding the app name, pid,
+/// format, framing or reconnect policy.
+pub struct Builder {
+    facility: Facility,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    pid: Option<libc::pid_t>,
+    include_thread_id: bool,
+    formatter: Box<Formatter>,
+    reconnect: ReconnectPolicy,
+    tcp_framing: TcpFraming,
+    min_severity: Option<Severity>,
+    max_message_size: Option<usize>,
+    size_limit_policy: SizeLimitPolicy,
+    write_timeout: Option<Duration>,
+    drop_on_timeout: bool,
+    backend: BackendConfig,
+}
+
+impl Builder {
+    /// Starts with LOG_USER, the local unix socket backend, RFC 3164
+    /// format (plain when that feature is off), and the process's own
+    /// name and pid.
+    pub fn new() -> Builder {
+        Builder {
+            facility: Facility::LOG_USER,
+            hostname: None,
+            app_name: None,
+            pid: None,
+            include_thread_id: false,
+            formatter: default_formatter(),
+            reconnect: ReconnectPolicy::default(),
+            tcp_framing: TcpFraming::OctetCounted,
+            min_severity: None,
+            max_message_size: None,
+            size_limit_policy: SizeLimitPolicy::Truncate,
+            write_timeout: None,
+            drop_on_timeout: false,
+            backend: BackendConfig::Unix { path: None },
+        }
+    }
+
+    pub fn facility(mut self, facility: Facility) -> Builder {
+        self.facility = facility;
+        self
+    }
+
+    pub fn hostname(mut self, hostname: String) -> Builder {
+        self.hostname = Some(hostname);
+        self
+    }
+
+    pub fn app_name(mut self, app_name: &str) -> Builder {
+        self.app_name = Some(app_name.to_owned());
+        self
+    }
+
+    pub fn pid(mut self, pid: libc::pid_t) -> Builder {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Tag RFC 5424 messages with the logging thread's id.
+    pub fn include_thread_id(mut self, include: bool) -> Builder {
+        self.include_thread_id = include;
+        self
+    }
+
+    /// Selects one of the built-in wire formats.
+    pub fn format(mut self, format: LogFormat) -> Builder {
+        self.formatter = format.formatter();
+        self
+    }
+
+    /// Installs a custom wire format.
+    pub fn formatter(mut self, formatter: Box<Formatter>) -> Builder {
+        self.formatter = formatter;
+        self
+    }
+
+    pub fn reconnect_policy(mut self, policy: ReconnectPolicy) -> Builder {
+        self.reconnect = policy;
+        self
+    }
+
+    pub fn tcp_framing(mut self, framing: TcpFraming) -> Builder {
+        self.tcp_framing = framing;
+        self
+    }
+
+    /// Suppress messages less severe than `severity`.
+    pub fn min_severity(mut self, severity: Severity) -> Builder {
+        self.min_severity = Some(severity);
+        self
+    }
+
+    /// Limit message bodies to `size` bytes, handled per `policy`. UDP
+    /// receivers commonly drop datagrams over 1-8KB, silently.
+    pub fn max_message_size(mut self, size: usize, policy: SizeLimitPolicy) -> Builder {
+        self.max_message_size = Some(size);
+        self.size_limit_policy = policy;
+        self
+    }
+
+    /// Bound every socket write so a stalled daemon cannot block logging
+    /// threads indefinitely.
+    pub fn write_timeout(mut self, timeout: Duration) -> Builder {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Treat a timed-out write as a dropped message instead of an error.
+    pub fn drop_on_timeout(mut self, drop: bool) -> Builder {
+        self.drop_on_timeout = drop;
+        self
+    }
+
+    /// Local syslog over a unix datagram socket (the default backend),
+    /// probing the usual socket locations.
+    pub fn unix(mut self) -> Builder {
+        self.backend = BackendConfig::Unix { path: None };
+        self
+    }
+
+    /// Local syslog over the unix socket at the given path, for daemons
+    /// listening somewhere other than the default locations.
+    pub fn unix_path<P: AsRef<Path>>(mut self, path: P) -> Builder {
+        self.backend = BackendConfig::Unix {
+            path: Some(path.as_ref().to_path_buf()),
+        };
+        self
+    }
+
+    /// UDP to a remote server, bound to the given local address.
+    pub fn udp(mut self, local: SocketAddr, server: SocketAddr) -> Builder {
+        self.backend = BackendConfig::Udp {
+            local: local,
+            server: server,
+        };
+        self
+    }
+
+    /// TCP to a remote server; the address is kept unresolved so
+    /// reconnection picks up DNS changes.
+    pub fn tcp(mut self, server: &str) -> Builder {
+        self.backend = BackendConfig::Tcp {
+            server: server.to_owned(),
+        };
+        self
+    }
+
+    /// TLS to a remote server, per RFC 5425.
+    pub fn tls(mut self, server: &str, tls_config: TlsConfig) -> Builder {
+        self.backend = BackendConfig::Tls {
+            server: server.to_owned(),
+            tls_config: tls_config,
+        };
+        self
+    }
+
+    /// The local systemd journal, using the native journal protocol so
+    /// severity, identifier and structured fields stay indexed.
+    pub fn journald(mut self) -> Builder {
+        self.backend = BackendConfig::Journald;
+        self
+    }
+
+    /// Graylog over chunked UDP; also selects the GELF wire format.
+    pub fn gelf_udp(mut self, local: SocketAddr, server: SocketAddr) -> Builder {
+        self.backend = BackendConfig::GelfUdp {
+            local: local,
+            server: server,
+        };
+        self.formatter = Box::new(gelf::Gelf);
+        self
+    }
+
+    /// The process's stderr, for environments with no reachable syslog
+    /// daemon (e.g. containers).
+    pub fn stderr(mut self) -> Builder {
+        self.backend = BackendConfig::Stderr;
+        self
+    }
+
+    /// A backend that discards every message.
+    pub fn null(mut self) -> Builder {
+        self.backend = BackendConfig::Null;
+        self
+    }
+
+    /// An append-only log file.
+    pub fn file<P: AsRef<Path>>(mut self, path: P) -> Builder {
+        self.backend = BackendConfig::File(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Like `connect`, but returns the process-wide shared logger for
+    /// this (facility, backend) pair; see the `registry` module.
+    pub fn connect_shared(self) -> Result<Arc<Logger>, io::Error> {
+        registry::connect_shared(self)
+    }
+
+    fn registry_key(&self) -> String {
+        format!("{}|{}", self.facility as u8, self.backend.shared_key())
+    }
+
+    /// Connects the configured backend and returns the logger.
+    pub fn connect(self) -> Result<Box<Logger>, io::Error> {
+        let backend = match self.backend {
+            BackendConfig::Unix { path: Some(path) } => connect_unix_socket(&path)?,
+            BackendConfig::Unix { path: None } => {
+                let mut connected = None;
+                for path in &["/dev/log", "/var/run/syslog"] {
+                    if Path::new(path).exists() {
+                        connected = Some(connect_unix_socket(path)?);
+                        break;
+                    }
+                }
+                connected.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "could not find /dev/log nor /var/run/syslog",
+                    )
+                })?
+            }
+            BackendConfig::Udp { local, server } => {
+                let socket = UdpSocket::bind(local)?;
+                LoggerBackend::Udp(socket, server)
+            }
+            BackendConfig::Tcp { server } => {
+                let stream = TcpStream::connect(&server[..])?;
+                LoggerBackend::Tcp(Arc::new(Mutex::new(stream)), server)
+            }
+            BackendConfig::Tls { server, tls_config } => {
+                let mut builder = TlsConnector::builder();
+                if let Some(ref ca_file) = tls_config.ca_file {
+                    let mut pem = Vec::new();
+                    File::open(ca_file)?.read_to_end(&mut pem)?;
+                    let cert = Certificate::from_pem(&pem).map_err(tls_error)?;
+                    builder.add_root_certificate(cert);
+                }
+                if let Some((ref identity_file, ref password)) = tls_config.identity {
+                    let mut pkcs12 = Vec::new();
+                    File::open(identity_file)?.read_to_end(&mut pkcs12)?;
+                    let identity = Identity::from_pkcs12(&pkcs12, password).map_err(tls_error)?;
+                    builder.identity(identity);
+                }
+                let connector = builder.build().map_err(tls_error)?;
+                let tcp_stream = TcpStream::connect(&server[..])?;
+                let stream = connector
+                    .connect(&tls_config.domain, tcp_stream)
+                    .map_err(tls_error)?;
+                LoggerBackend::Tls(Arc::new(Mutex::new(stream)))
+            }
+            BackendConfig::Journald => {
+                let sock = UnixDatagram::unbound()?;
+                sock.connect(JOURNALD_PATH)?;
+                LoggerBackend::Journald(Mutex::new(sock))
+            }
+            BackendConfig::GelfUdp { local, server } => {
+                let socket = UdpSocket::bind(local)?;
+                LoggerBackend::GelfUdp(socket, server, gelf::ChunkedGelfEncoder::new())
+            }
+            BackendConfig::Stderr => LoggerBackend::Stderr,
+            BackendConfig::File(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(&path)?;
+                LoggerBackend::File(Mutex::new(file), path)
+            }
+            BackendConfig::Null => LoggerBackend::Null,
+        };
+        if let Some(timeout) = self.write_timeout {
+            apply_write_timeout(&backend, timeout)?;
+        }
+        Ok(Box::new(Logger {
+            facility: self.facility,
+            hostname: self.hostname.or_else(detect_hostname),
+            process_info: ProcessInfo {
+                name: self
+                    .app_name
+                    .or_else(get_process_name)
+                    .unwrap_or_else(|| "rust-syslog".to_owned()),
+                pid: match self.pid {
+                    Some(pid) if pid <= 0 => return Err(invalid_pid(pid)),
+                    Some(pid) => pid,
+                    None => unsafe { getpid() },
+                },
+                include_thread_id: self.include_thread_id,
+            },
+            formatter: self.formatter,
+            reconnect: self.reconnect,
+            tcp_framing: self.tcp_framing,
+            min_severity: self.min_severity,
+            target_severity: HashMap::new(),
+            max_message_size: self.max_message_size,
+            size_limit_policy: self.size_limit_policy,
+            write_timeout: self.write_timeout,
+            drop_on_timeout: self.drop_on_timeout,
+            last_error: Mutex::new(None),
+            counters: Counters::new(),
+            s: backend,
+        }))
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+/// Returns a Logger using a unix socket to target local syslog
+/// (using /dev/log or /var/run/syslog)
+pub fn unix(facility: Facility) -> Result<Box<Logger>, io::Error> {
+    Builder::new().facility(facility).connect()
+}
+
+/// Returns a Logger writing to the local systemd journal with the native
+/// journal protocol
+pub fn journald(facility: Facility) -> Result<Box<Logger>, io::Error> {
+    Builder::new().facility(facility).journald().connect()
+}
+
+/// Returns a Logger using a UDP socket to a remote server
+pub fn udp<T: ToSocketAddrs>(
+    local: T,
+    server: T,
+    hostname: String,
+    facility: Facility,
+) -> Result<Box<Logger>, io::Error> {
+    Builder::new()
+        .facility(facility)
+        .hostname(hostname)
+        .udp(resolve_first(local)?, resolve_first(server)?)
+        .connect()
+}
+
+/// Returns a Logger using a UDP socket to a remote server, binding an
+/// ephemeral local port of the matching address family (0.0.0.0:0 or
+/// [::]:0) automatically
+pub fn udp_to<T: ToSocketAddrs>(
+    server: T,
+    hostname: String,
+    facility: Facility,
+) -> Result<Box<Logger>, io::Error> {
+    let server_addr = resolve_first(server)?;
+    let local: SocketAddr = match server_addr {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    };
+    Builder::new()
+        .facility(facility)
+        .hostname(hostname)
+        .udp(local, server_addr)
+        .connect()
+}
+
+/// Returns a Logger writing formatted messages to the process's stderr
+pub fn stderr(facility: Facility) -> Result<Box<Logger>, io::Error> {
+    Builder::new().facility(facility).stderr().connect()
+}
+
+/// Returns a Logger appending formatted messages to a file
+pub fn file<P: AsRef<Path>>(path: P, facility: Facility) -> Result<Box<Logger>, io::Error> {
+    Builder::new().facility(facility).file(path).connect()
+}
+
+/// Returns a Logger that discards every message, for callers that need a
+/// logger unconditionally even when nothing is reachable
+pub fn null(facility: Facility) -> Result<Box<Logger>, io::Error> {
+    Builder::new().facility(facility).null().connect()
+}
+
+/// Returns a Logger shipping GELF over chunked UDP to a Graylog server
+pub fn gelf_udp<T: ToSocketAddrs>(
+    server: T,
+    hostname: String,
+    facility: Facility,
+) -> Result<Box<Logger>, io::Error> {
+    let server_addr = resolve_first(server)?;
+    let local: SocketAddr = match server_addr {
+        SocketAddr::V4(_) => "0.0.0.0:0".parse().unwrap(),
+        SocketAddr::V6(_) => "[::]:0".parse().unwrap(),
+    };
+    Builder::new()
+        .facility(facility)
+        .hostname(hostname)
+        .gelf_udp(local, server_addr)
+        .connect()
+}
+
+/// Returns a Logger using a TCP connection to a remote server
+pub fn tcp<T: ToSocketAddrs + ToString>(
+    server: T,
+    hostname: String,
+    facility: Facility,
+) -> Result<Box<Logger>, io::Error> {
+    Builder::new()
+        .facility(facility)
+        .hostname(hostname)
+        .tcp(&server.to_string())
+        .connect()
+}
+
+/// Returns a Logger using a TLS session to a remote server, per RFC 5425.
+/// Messages are framed with octet counting; the server certificate is
+/// validated against the system roots plus any CA in the config, and the
+/// configured domain is used for SNI.
+pub fn tls<T: ToSocketAddrs + ToString>(
+    server: T,
+    tls_config: TlsConfig,
+    hostname: String,
+    facility: Facility,
+) -> Result<Box<Logger>, io::Error> {
+    Builder::new()
+        .facility(facility)
+        .hostname(hostname)
+        .tls(&server.to_string(), tls_config)
+        .connect()
+}
+
+/// A fully-specified message for `Logger::send_message`, letting one call
+/// override the logger's construction-time facility.
+pub struct Message<'a> {
+    pub severity: Severity,
+    /// Facility for this message only; the logger's own when None.
+    pub facility: Option<Facility>,
+    pub message: &'a str,
+}
+
+impl Logger {
+    /// The formatter's view of this logger and one message's metadata.
+    /// Sets mapped-diagnostic-context pairs for the current thread until
+    /// the returned guard is dropped; see the `mdc` module.
+    pub fn with_context(pairs: &[(&str, &str)]) -> mdc::MdcGuard {
+        mdc::scoped(pairs)
+    }
+
+    fn message_context<'a>(
+        &'a self,
+        severity: Severity,
+        facility: Option<Facility>,
+        message_id: Option<i32>,
+        data: Option<&'a StructuredDataBuilder>,
+    ) -> MessageContext<'a> {
+        MessageContext {
+            severity: severity,
+            facility: facility.unwrap_or(self.facility),
+            hostname: self.hostname.as_ref().map(|h| &h[..]),
+            process: &self.process_info.name,
+            pid: self.process_info.pid,
+            thread_id: if self.process_info.include_thread_id {
+                Some(thread_id())
+            } else {
+                None
+            },
+            message_id: message_id,
+            structured_data: data,
+            mdc: mdc::snapshot(),
+            #[cfg(feature = "time")]
+            timestamp: time::now_utc(),
+        }
+    }
+
+    /// Formats a message with the configured formatter without sending it,
+    /// for the async wrapper in `buffered`.
+    pub(crate) fn format_message(&self, severity: Severity, message: &str) -> String {
+        self.formatter
+            .format(&self.message_context(severity, None, None, None), message)
+    }
+
+    /// Formats a message according to RFC 3164
+    #[cfg(feature = "rfc3164")]
+    fn format_3164(&self, severity: Severity, message: &str) -> String {
+        format::Rfc3164.format(&self.message_context(severity, None, None, None), message)
+    }
+
+    /// Formats a message according to RFC 5424
+    #[cfg(feature = "rfc5424")]
+    fn format_5424(
+        &self,
+        severity: Severity,
+        message_id: Option<i32>,
+        data: &StructuredDataBuilder,
+        message: &str,
+    ) -> String {
+        format::Rfc5424.format(
+            &self.message_context(severity, None, message_id, Some(data)),
+            message,
+        )
+    }
+
+    #[cfg(all(test, feature = "rfc5424"))]
+    fn format_5424_at(
+        &self,
+        timestamp: time::Tm,
+        severity: Severity,
+        message_id: Option<i32>,
+        data: &StructuredDataBuilder,
+        message: &str,
+    ) -> String {
+        let mut ctx = self.message_context(severity, None, message_id, Some(data));
+        ctx.timestamp = timestamp;
+        format::Rfc5424.format(&ctx, message)
+    }
+
+    /// Sends a message with the logger's configured formatter
+    /// (RFC 3164 unless overridden through the builder)
+    pub fn send(&self, severity: Severity, message: &str) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        if let LoggerBackend::Journald(_) = self.s {
+            return self.send_journald(severity, message, None);
+        }
+        let ctx = self.message_context(severity, None, None, None);
+        if let Some(parts) = self.apply_size_limit(message) {
+            let mut sent = 0;
+            for part in &parts {
+                sent += self.send_raw(self.formatter.format(&ctx, part).as_bytes())?;
+            }
+            return Ok(sent);
+        }
+        self.send_raw(self.formatter.format(&ctx, message).as_bytes())
+    }
+
+    /// Like `send`, but takes `format_args!` output and formats it into a
+    /// reused thread-local buffer instead of allocating per message.
+    pub fn send_fmt(&self, severity: Severity, args: fmt::Arguments) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        if let LoggerBackend::Journald(_) = self.s {
+            return self.send_journald(severity, &args.to_string(), None);
+        }
+        FORMAT_BUF.with(|cell| {
+            let mut buf = cell.borrow_mut();
+            buf.clear();
+            self.formatter.format_into(
+                &self.message_context(severity, None, None, None),
+                &mut buf,
+                args,
+            );
+            self.send_raw(buf.as_bytes())
+        })
+    }
+
+    /// Sends a message under the given facility instead of the logger's
+    /// own, e.g. LOG_AUTHPRIV for security events from a LOG_USER logger.
+    pub fn send_with_facility(
+        &self,
+        severity: Severity,
+        facility: Facility,
+        message: &str,
+    ) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        let ctx = self.message_context(severity, Some(facility), None, None);
+        self.send_raw(self.formatter.format(&ctx, message).as_bytes())
+    }
+
+    /// Sends a message under the given APP-NAME/TAG instead of this
+    /// logger's process name, so a library (e.g. the pkcs11 shim) can log
+    /// under the host application it is loaded into. RFC 3164 output
+    /// sanitizes the tag the same way it does process names.
+    pub fn send_with_app_name(
+        &self,
+        severity: Severity,
+        app_name: &str,
+        message: &str,
+    ) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        let mut ctx = self.message_context(severity, None, None, None);
+        ctx.process = app_name;
+        self.send_raw(self.formatter.format(&ctx, message).as_bytes())
+    }
+
+    /// Sends a `Message`, honoring its per-message facility override.
+    pub fn send_message(&self, message: &Message) -> Result<usize, io::Error> {
+        match message.facility {
+            Some(facility) => self.send_with_facility(message.severity, facility, message.message),
+            None => self.send(message.severity, message.message),
+        }
+    }
+
+    /// Sends a message formatted as per RFC 3164
+    #[cfg(feature = "rfc3164")]
+    pub fn send_3164(&self, severity: Severity, message: &str) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        if let LoggerBackend::Journald(_) = self.s {
+            return self.send_journald(severity, message, None);
+        }
+        if let Some(parts) = self.apply_size_limit(message) {
+            let mut sent = 0;
+            for part in &parts {
+                sent += self.send_raw(self.format_3164(severity, part).as_bytes())?;
+            }
+            return Ok(sent);
+        }
+        let formatted = self.format_3164(severity, message).into_bytes();
+        self.send_raw(&formatted[..])
+    }
+
+    /// Sends an RFC 3164 message whose MSG part is the `@cee:` cookie
+    /// followed by the compact JSON encoding of `payload`. Receivers
+    /// running rsyslog's mmjsonparse index the fields as structured data,
+    /// without the rest of the pipeline moving to RFC 5424. The size
+    /// limit is deliberately not applied: splitting or truncating the
+    /// payload would leave unparseable JSON on the receiver.
+    #[cfg(feature = "rfc3164")]
+    pub fn send_cee(
+        &self,
+        severity: Severity,
+        payload: &serde_json::Value,
+    ) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        let message = format!("@cee:{}", payload);
+        if let LoggerBackend::Journald(_) = self.s {
+            return self.send_journald(severity, &message, None);
+        }
+        let formatted = self.format_3164(severity, &message).into_bytes();
+        self.send_raw(&formatted[..])
+    }
+
+    /// Sends a message formatted as per RFC 5424
+    #[cfg(feature = "rfc5424")]
+    pub fn send_5424(
+        &self,
+        severity: Severity,
+        message_id: Option<i32>,
+        data: &StructuredDataBuilder,
+        message: &str,
+    ) -> Result<usize, io::Error> {
+        if !self.enabled_for(severity, None) {
+            self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+            return Ok(0);
+        }
+        if let LoggerBackend::Journald(_) = self.s {
+            return self.send_journald(severity, message, Some(data));
+        }
+        if let Some(parts) = self.apply_size_limit(message) {
+            let mut sent = 0;
+            for part in &parts {
+                sent +=
+                    self.send_raw(self.format_5424(severity, message_id, data, part).as_bytes())?;
+            }
+            return Ok(sent);
+        }
+        let formatted = self
+            .format_5424(severity, message_id, data, message)
+            .into_bytes();
+        self.send_raw(&formatted[..])
+    }
+
+    /// Sends a message directly, without formatting. If the connection has
+    /// gone dead (e.g. the syslog daemon restarted), reconnects and retries
+    /// per the logger's `ReconnectPolicy`.
+    /// Appends `message` to `buf` framed for the stream transport: the
+    /// configured RFC 6587 framing on TCP, always octet counting (RFC
+    /// 5425) on TLS.
+    fn frame_stream(&self, message: &[u8], buf: &mut Vec<u8>) {
+        let framing = match self.s {
+            LoggerBackend::Tls(_) => TcpFraming::OctetCounted,
+            _ => self.tcp_framing,
+        };
+        match framing {
+            TcpFraming::OctetCounted => {
+                buf.extend_from_slice(format!("{} ", message.len()).as_bytes());
+                buf.extend_from_slice(message);
+            }
+            TcpFraming::NonTransparent(trailer) => {
+                buf.extend_from_slice(message);
+                if message.last() != Some(&trailer) {
+                    buf.push(trailer);
+                }
+            }
+        }
+    }
+
+    /// Formats several messages and sends them with as few syscalls as the
+    /// backend allows: stream transports get a single write covering every
+    /// framed message, UDP coalesces newline-separated messages into
+    /// MTU-sized datagrams, and everything else falls back to one send per
+    /// message.
+    pub fn send_batch(&self, messages: &[(Severity, &str)]) -> Result<usize, io::Error> {
+        if let LoggerBackend::Journald(_) = self.s {
+            let mut sent = 0;
+            for &(severity, message) in messages {
+                sent += self.send(severity, message)?;
+            }
+            return Ok(sent);
+        }
+        let mut formatted: Vec<String> = Vec::with_capacity(messages.len());
+        for &(severity, message) in messages {
+            if !self.enabled_for(severity, None) {
+                self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            let ctx = self.message_context(severity, None, None, None);
+            match self.apply_size_limit(message) {
+                Some(parts) => {
+                    for part in &parts {
+                        formatted.push(self.formatter.format(&ctx, part));
+                    }
+                }
+                None => formatted.push(self.formatter.format(&ctx, message)),
+            }
+        }
+        if formatted.is_empty() {
+            return Ok(0);
+        }
+        match self.s {
+            LoggerBackend::UnixStream(ref stream_wrap, _) => {
+                let mut buf = Vec::new();
+                for line in &formatted {
+                    buf.extend_from_slice(line.as_bytes());
+                    buf.push(b'\n');
+                }
+                let result = stream_wrap
+                    .lock()
+                    .unwrap()
+                    .write_all(&buf[..])
+                    .map(|()| buf.len());
+                self.account_batch(result, formatted.len())
+            }
+            LoggerBackend::Tcp(ref stream_wrap, _) => {
+                let mut buf = Vec::new();
+                for line in &formatted {
+                    self.frame_stream(line.as_bytes(), &mut buf);
+                }
+                let result = stream_wrap
+                    .lock()
+                    .unwrap()
+                    .write_all(&buf[..])
+                    .map(|()| buf.len());
+                self.account_batch(result, formatted.len())
+            }
+            LoggerBackend::Tls(ref stream_wrap) => {
+                let mut buf = Vec::new();
+                for line in &formatted {
+                    self.frame_stream(line.as_bytes(), &mut buf);
+                }
+                let result = stream_wrap
+                    .lock()
+                    .unwrap()
+                    .write_all(&buf[..])
+                    .map(|()| buf.len());
+                self.account_batch(result, formatted.len())
+            }
+            LoggerBackend::Udp(ref socket, ref addr) => {
+                let mut sent = 0;
+                let mut datagram: Vec<u8> = Vec::new();
+                let mut batched = 0;
+                for line in &formatted {
+                    let line = line.as_bytes();
+                    if !datagram.is_empty()
+                        && datagram.len() + 1 + line.len() > UDP_BATCH_DATAGRAM_SIZE
+                    {
+                        let result = socket.send_to(&datagram[..], addr);
+                        sent += self.account_batch(result, batched)?;
+                        datagram.clear();
+                        batched = 0;
+                    }
+                    if !datagram.is_empty() {
+                        datagram.push(b'\n');
+                    }
+                    datagram.extend_from_slice(line);
+                    batched += 1;
+                }
+                if !datagram.is_empty() {
+                    let result = socket.send_to(&datagram[..], addr);
+                    sent += self.account_batch(result, batched)?;
+                }
+                Ok(sent)
+            }
+            _ => {
+                let mut sent = 0;
+                for line in &formatted {
+                    sent += self.send_raw(line.as_bytes())?;
+                }
+                Ok(sent)
+            }
+        }
+    }
+
+    /// `send_raw`-style counter bookkeeping for a write covering `count`
+    /// messages.
+    fn account_batch(
+        &self,
+        result: Result<usize, io::Error>,
+        count: usize,
+    ) -> Result<usize, io::Error> {
+        match result {
+            Ok(n) => {
+                self.counters.messages_sent.fetch_add(count, Ordering::Relaxed);
+                self.counters.bytes_written.fetch_add(n, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.counters.send_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    /// Sends one message, already formatted. Stream backends write the
+    /// whole framed message or fail — `Ok` never means a truncated or
+    /// interleaved message on the wire — and the returned count includes
+    /// any framing bytes.
+    pub fn send_raw(&self, message: &[u8]) -> Result<usize, io::Error> {
+        let result = self.send_raw_with_retry(message);
+        match result {
+            Ok(n) => {
+                self.counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+                self.counters.bytes_written.fetch_add(n, Ordering::Relaxed);
+            }
+            Err(ref e) if self.drop_on_timeout && is_timeout(e) => {
+                self.counters.messages_dropped.fetch_add(1, Ordering::Relaxed);
+                return Ok(0);
+            }
+            Err(_) => {
+                self.counters.send_errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        result
+    }
+
+    fn send_raw_with_retry(&self, message: &[u8]) -> Result<usize, io::Error> {
+        let mut result = self.write_once(message);
+        let mut backoff = self.reconnect.initial_backoff;
+        let mut retries = 0;
+        while retries < self.reconnect.max_retries {
+            match result {
+                Err(ref e) if is_disconnect(e) => {}
+                _ => return result,
+            }
+            thread::sleep(backoff);
+            backoff = cmp::min(backoff * 2, self.reconnect.max_backoff);
+            retries += 1;
+            result = self.reconnect().and_then(|_| self.write_once(message));
+        }
+        result
+    }
+
+    fn write_once(&self, message: &[u8]) -> Result<usize, io::Error> {
+        match self.s {
+            LoggerBackend::Unix(ref dgram, _) => dgram.lock().unwrap().send(&message[..]),
+            LoggerBackend::UnixStream(ref stream_wrap, _) => {
+                // The local daemon delimits stream messages by newline,
+                // not by RFC 6587 framing. One buffer, one write_all under
+                // the lock: the receiver never sees a partial message or
+                // fragments interleaved from another sender.
+                let mut framed = Vec::with_capacity(message.len() + 1);
+                framed.extend_from_slice(&message[..]);
+                framed.push(b'\n');
+                let mut stream = stream_wrap.lock().unwrap();
+                stream.write_all(&framed[..]).map(|()| framed.len())
+            }
+            LoggerBackend::Udp(ref socket, ref addr) => socket.send_to(&message[..], addr),
+            LoggerBackend::Tcp(ref stream_wrap, _) => {
+                // RFC 6587: frame each message so receivers do not merge or
+                // split them on the stream. write_all, not write: a short
+                // write would truncate the message and desynchronize the
+                // framing for everything that follows on the connection.
+                let mut framed = Vec::with_capacity(message.len() + 8);
+                self.frame_stream(&message[..], &mut framed);
+                let mut stream = stream_wrap.lock().unwrap();
+                stream.write_all(&framed[..]).map(|()| framed.len())
+            }
+            LoggerBackend::Tls(ref stream_wrap) => {
+                // RFC 5425 octet counting: "MSG-LEN SP SYSLOG-MSG"
+                let mut framed = Vec::with_capacity(message.len() + 8);
+                self.frame_stream(&message[..], &mut framed);
+                let mut stream = stream_wrap.lock().unwrap();
+                stream.write_all(&framed[..]).map(|()| framed.len())
+            }
+            LoggerBackend::Journald(ref dgram) => dgram.lock().unwrap().send(&message[..]),
+            LoggerBackend::GelfUdp(ref socket, ref addr, ref encoder) => {
+                let mut sent = 0;
+                for datagram in encoder.encode(&message[..])? {
+                    sent += socket.send_to(&datagram[..], addr)?;
+                }
+                Ok(sent)
+            }
+            LoggerBackend::Stderr => {
+                let stderr = io::stderr();
+                let mut lock = stderr.lock();
+                lock.write_all(&message[..])?;
+                lock.write_all(b"\n")?;
+                Ok(message.len())
+            }
+            LoggerBackend::File(ref file, _) => {
+                let mut file = file.lock().unwrap();
+                file.write_all(&message[..])?;
+                file.write_all(b"\n")?;
+                Ok(message.len())
+            }
+            LoggerBackend::Null => Ok(message.len()),
+        }
+    }
+
+    /// Re-establishes the backend connection after a drop. For TCP the
+    /// server address is resolved again, so a DNS change is picked up.
+    fn reconnect(&self) -> Result<(), io::Error> {
+        let result = self.reconnect_backend();
+        if result.is_ok() {
+            self.counters.reconnects.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    fn reconnect_backend(&self) -> Result<(), io::Error> {
+        match self.s {
+            LoggerBackend::Unix(ref dgram, ref path) => {
+                let sock = UnixDatagram::unbound()?;
+                sock.connect(path)?;
+                sock.set_write_timeout(self.write_timeout)?;
+                *dgram.lock().unwrap() = sock;
+                Ok(())
+            }
+            LoggerBackend::UnixStream(ref stream_wrap, ref path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(self.write_timeout)?;
+                *stream_wrap.lock().unwrap() = stream;
+                Ok(())
+            }
+            LoggerBackend::Tcp(ref stream_wrap, ref server) => {
+                let stream = TcpStream::connect(&server[..])?;
+                stream.set_write_timeout(self.write_timeout)?;
+                *stream_wrap.lock().unwrap() = stream;
+                Ok(())
+            }
+            LoggerBackend::Journald(ref dgram) => {
+                let sock = UnixDatagram::unbound()?;
+                sock.connect(JOURNALD_PATH)?;
+                sock.set_write_timeout(self.write_timeout)?;
+                *dgram.lock().unwrap() = sock;
+                Ok(())
+            }
+            LoggerBackend::File(ref file, ref path) => {
+                let reopened = OpenOptions::new().create(true).append(true).open(path)?;
+                *file.lock().unwrap() = reopened;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn set_reconnect_policy(&mut self, policy: ReconnectPolicy) {
+        self.reconnect = policy
+    }
+
+    pub fn set_tcp_framing(&mut self, framing: TcpFraming) {
+        self.tcp_framing = framing
+    }
+
+    /// Suppress messages less severe than `severity`; they short-circuit
+    /// before formatting.
+    pub fn set_min_severity(&mut self, severity: Severity) {
+        self.min_severity = Some(severity)
+    }
+
+    /// Overrides the minimum severity for one log target (as reported by
+    /// the log crate's `Record::target`).
+    pub fn set_target_severity(&mut self, target: &str, severity: Severity) {
+        self.target_severity.insert(target.to_owned(), severity);
+    }
+
+    /// Limit message bodies to `size` bytes, handled per `policy`.
+    pub fn set_max_message_size(&mut self, size: usize, policy: SizeLimitPolicy) {
+        self.max_message_size = Some(size);
+        self.size_limit_policy = policy;
+    }
+
+    /// Returns the message split per the size limit, or None when it fits
+    /// (or no limit is configured).
+    fn apply_size_limit(&self, message: &str) -> Option<Vec<String>> {
+        static CORRELATION: AtomicUsize = ATOMIC_USIZE_INIT;
+        let max = match self.max_message_size {
+            Some(max) if max > 0 && message.len() > max => max,
+            _ => return None,
+        };
+        match self.size_limit_policy {
+            SizeLimitPolicy::Truncate => Some(vec![format!(
+                "{}[...]",
+                truncate_at_boundary(message, max)
+            )]),
+            SizeLimitPolicy::Split => {
+                let mut chunks = Vec::new();
+                let mut rest = message;
+                while !rest.is_empty() {
+                    let mut chunk = truncate_at_boundary(rest, max);
+                    if chunk.is_empty() {
+                        // max is smaller than the next character; emit the
+                        // whole char anyway so the loop always makes progress
+                        let char_len = rest.chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+                        chunk = &rest[..char_len];
+                    }
+                    chunks.push(chunk);
+                    rest = &rest[chunk.len()..];
+                }
+                let correlation = CORRELATION.fetch_add(1, Ordering::SeqCst);
+                let total = chunks.len();
+                Some(
+                    chunks
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, chunk)| {
+                            format!(
+                                "[{}-{} {}/{}] {}",
+                                self.process_info.pid,
+                                correlation,
+                                i + 1,
+                                total,
+                                chunk
+                            )
+                        })
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    /// Serializes a message as native journal fields and sends it. Custom
+    /// structured-data params become uppercase KEY=VALUE journal fields
+    /// prefixed with their SD-ID.
+    fn send_journald(
+        &self,
+        severity: Severity,
+        message: &str,
+        data: Option<&StructuredDataBuilder>,
+    ) -> Result<usize, io::Error> {
+        let mut payload = Vec::new();
+        append_journald_field(&mut payload, "PRIORITY", &(severity as u8).to_string());
+        append_journald_field(
+            &mut payload,
+            "SYSLOG_FACILITY",
+            &((self.facility as u8) >> 3).to_string(),
+        );
+        append_journald_field(&mut payload, "SYSLOG_IDENTIFIER", &self.process_info.name);
+        append_journald_field(&mut payload, "SYSLOG_PID", &self.process_info.pid.to_string());
+        append_journald_field(&mut payload, "MESSAGE", message);
+        if let Some(data) = data {
+            for &(ref id, ref params) in data.elements() {
+                for &(ref name, ref value) in params {
+                    let field = journald_field_name(&format!("{}_{}", id, name));
+                    append_journald_field(&mut payload, &field, value);
+                }
+            }
+        }
+        self.send_raw(&payload[..])
+    }
+
+    /// Flushes the backend stream; a no-op for datagram backends.
+    pub fn flush(&self) -> Result<(), io::Error> {
+        match self.s {
+            LoggerBackend::UnixStream(ref stream_wrap, _) => stream_wrap.lock().unwrap().flush(),
+            LoggerBackend::Tcp(ref stream_wrap, _) => stream_wrap.lock().unwrap().flush(),
+            LoggerBackend::Tls(ref stream_wrap) => stream_wrap.lock().unwrap().flush(),
+            LoggerBackend::Stderr => io::stderr().flush(),
+            LoggerBackend::File(ref file, _) => file.lock().unwrap().flush(),
+            _ => Ok(()),
+        }
+    }
+
+    /// A snapshot of the logger's I/O counters.
+    pub fn stats(&self) -> LoggerStats {
+        LoggerStats {
+            messages_sent: self.counters.messages_sent.load(Ordering::Relaxed) as u64,
+            bytes_written: self.counters.bytes_written.load(Ordering::Relaxed) as u64,
+            send_errors: self.counters.send_errors.load(Ordering::Relaxed) as u64,
+            reconnects: self.counters.reconnects.load(Ordering::Relaxed) as u64,
+            messages_dropped: self.counters.messages_dropped.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    /// Checks that the backend is still writable without emitting a log
+    /// line: datagram backends send an empty datagram, stream backends are
+    /// probed for a buffered socket error and a live peer.
+    pub fn ping(&self) -> Result<(), io::Error> {
+        match self.s {
+            LoggerBackend::Unix(ref dgram, _) => dgram.lock().unwrap().send(&[]).map(|_| ()),
+            LoggerBackend::UnixStream(ref stream_wrap, _) => {
+                let stream = stream_wrap.lock().unwrap();
+                if let Some(e) = stream.take_error()? {
+                    return Err(e);
+                }
+                stream.peer_addr().map(|_| ())
+            }
+            LoggerBackend::Udp(ref socket, ref addr) => socket.send_to(&[], addr).map(|_| ()),
+            LoggerBackend::Tcp(ref stream_wrap, _) => {
+                let stream = stream_wrap.lock().unwrap();
+                if let Some(e) = stream.take_error()? {
+                    return Err(e);
+                }
+                stream.peer_addr().map(|_| ())
+            }
+            LoggerBackend::Tls(ref stream_wrap) => {
+                let stream = stream_wrap.lock().unwrap();
+                if let Some(e) = stream.get_ref().take_error()? {
+                    return Err(e);
+                }
+                stream.get_ref().peer_addr().map(|_| ())
+            }
+            LoggerBackend::Journald(ref dgram) => dgram.lock().unwrap().send(&[]).map(|_| ()),
+            LoggerBackend::GelfUdp(ref socket, ref addr, _) => {
+                socket.send_to(&[], addr).map(|_| ())
+            }
+            LoggerBackend::Stderr => Ok(()),
+            LoggerBackend::File(ref file, _) => file.lock().unwrap().sync_data(),
+            LoggerBackend::Null => Ok(()),
+        }
+    }
+
+    /// The most recent error swallowed by the `Log` implementation, if any.
+    /// Taking it clears the buffer.
+    pub fn take_last_error(&self) -> Option<io::Error> {
+        self.last_error.lock().unwrap().take()
+    }
+
+    /// Whether a message of this severity (and optional log target) would
+    /// be sent under the configured thresholds.
+    pub fn enabled_for(&self, severity: Severity, target: Option<&str>) -> bool {
+        let threshold = target
+            .and_then(|t| self.target_severity.get(t).cloned())
+            .or(self.min_severity);
+        match threshold {
+            // Severities order from LOG_EMERG (0, most severe) down
+            Some(min) => severity <= min,
+            None => true,
+        }
+    }
+
+    pub fn emerg(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_EMERG, message)
+    }
+
+    pub fn alert(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_ALERT, message)
+    }
+
+    pub fn crit(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_CRIT, message)
+    }
+
+    pub fn err(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_ERR, message)
+    }
+
+    pub fn warning(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_WARNING, message)
+    }
+
+    pub fn notice(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_NOTICE, message)
+    }
+
+    pub fn info(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_INFO, message)
+    }
+
+    pub fn debug(&self, message: &str) -> Result<usize, io::Error> {
+        self.send(Severity::LOG_DEBUG, message)
+    }
+
+    pub fn process_info(&self) -> &ProcessInfo {
+        &self.process_info
+    }
+
+    pub fn set_process_info(&mut self, info: ProcessInfo) {
+        self.process_info = info
+    }
+
+    pub fn process_name(&self) -> &String {
+        &self.process_info.name
+    }
+
+    pub fn set_process_name(&mut self, name: String) {
+        self.process_info.name = name
+    }
+
+    pub fn process_id(&self) -> libc::pid_t {
+        self.process_info.pid
+    }
+
+    /// Rejects non-positive pids instead of emitting them verbatim.
+    pub fn set_process_id(&mut self, id: libc::pid_t) -> Result<(), io::Error> {
+        if id <= 0 {
+            return Err(invalid_pid(id));
+        }
+        self.process_info.pid = id;
+        Ok(())
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_ref().map(|h| &h[..])
+    }
+
+    /// Overrides the detected hostname.
+    pub fn set_hostname(&mut self, hostname: String) {
+        self.hostname = Some(hostname)
+    }
+}
+
+#[cfg(feature = "log-integration")]
+fn severity_for_level(level: LogLevel) -> Severity {
+    match level {
+        LogLevel::Error => Severity::LOG_ERR,
+        LogLevel::Warn => Severity::LOG_WARNING,
+        LogLevel::Info => Severity::LOG_INFO,
+        LogLevel::Debug | LogLevel::Trace => Severity::LOG_DEBUG,
+    }
+}
+
+#[cfg(feature = "log-integration")]
+impl Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        self.enabled_for(severity_for_level(metadata.level()), Some(metadata.target()))
+    }
+
+    fn log(&self, record: &LogRecord) {
+        // Filter on the record's target here rather than in send_*, so a
+        // per-target override can be more permissive than the global
+        // minimum.
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let severity = severity_for_level(record.level());
+        // Trace has no syslog severity of its own; tag it so it can be
+        // told apart from Debug on the receiving side.
+        let message = if record.level() == LogLevel::Trace {
+            format!("[trace] {}", record.args())
+        } else {
+            format!("{}", record.args())
+        };
+        if let LoggerBackend::Journald(_) = self.s {
+            if let Err(e) = self.send_journald(severity, &message, None) {
+                *self.last_error.lock().unwrap() = Some(e);
+            }
+            return;
+        }
+        let formatted = self
+            .formatter
+            .format(&self.message_context(severity, None, None, None), &message);
+        if let Err(e) = self.send_raw(formatted.as_bytes()) {
+            *self.last_error.lock().unwrap() = Some(e);
+        }
+    }
+}
+
+/// An error from the fallible `try_init_*` family.
+#[cfg(feature = "log-integration")]
+#[derive(Debug)]
+pub enum InitError {
+    /// The backend connection could not be established.
+    Io(io::Error),
+    /// A logger was already installed.
+    Log(SetLoggerError),
+}
+
+#[cfg(feature = "log-integration")]
+impl fmt::Display for InitError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InitError::Io(ref e) => write!(f, "syslog connection failed: {}", e),
+            InitError::Log(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "log-integration")]
+impl std::error::Error for InitError {
+    fn description(&self) -> &str {
+        "could not initialize syslog logging"
+    }
+}
+
+#[cfg(feature = "log-integration")]
+impl From<io::Error> for InitError {
+    fn from(e: io::Error) -> InitError {
+        InitError::Io(e)
+    }
+}
+
+#[cfg(feature = "log-integration")]
+impl From<SetLoggerError> for InitError {
+    fn from(e: SetLoggerError) -> InitError {
+        InitError::Log(e)
+    }
+}
+
+/// Installs an already-connected logger with the log crate.
+#[cfg(feature = "log-integration")]
+pub fn try_init_with(logger: Box<Logger>, log_level: LogLevelFilter) -> Result<(), InitError> {
+    log::set_logger(move |max_level| {
+        max_level.set(log_level);
+        logger
+    })?;
+    Ok(())
+}
+
+/// Like `init_unix`, but surfaces connection failures instead of panicking.
+#[cfg(feature = "log-integration")]
+pub fn try_init_unix(facility: Facility, log_level: LogLevelFilter) -> Result<(), InitError> {
+    try_init_with(unix(facility)?, log_level)
+}
+
+/// Like `init_udp`, but surfaces connection failures instead of panicking.
+#[cfg(feature = "log-integration")]
+pub fn try_init_udp<T: ToSocketAddrs>(
+    local: T,
+    server: T,
+    hostname: String,
+    facility: Facility,
+    log_level: LogLevelFilter,
+) -> Result<(), InitError> {
+    try_init_with(udp(local, server, hostname, facility)?, log_level)
+}
+
+/// Like `init_tcp`, but surfaces connection failures instead of panicking.
+#[cfg(feature = "log-integration")]
+pub fn try_init_tcp<T: ToSocketAddrs + ToString>(
+    server: T,
+    hostname: String,
+    facility: Facility,
+    log_level: LogLevelFilter,
+) -> Result<(), InitError> {
+    try_init_with(tcp(server, hostname, facility)?, log_level)
+}
+
+#[cfg(feature = "log-integration")]
+fn unwrap_init(result: Result<(), InitError>) -> Result<(), SetLoggerError> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(InitError::Log(e)) => Err(e),
+        Err(InitError::Io(e)) => panic!("could not connect to syslog: {}", e),
+    }
+}
+
+/// Unix socket Logger init function compatible with log crate
+#[cfg(feature = "log-integration")]
+pub fn init_unix(facility: Facility, log_level: LogLevelFilter) -> Result<(), SetLoggerError> {
+    unwrap_init(try_init_unix(facility, log_level))
+}
+
+/// UDP Logger init function compatible with log crate
+#[cfg(feature = "log-integration")]
+pub fn init_udp<T: ToSocketAddrs>(
+    local: T,
+    server: T,
+    hostname: String,
+    facility: Facility,
+    log_level: LogLevelFilter,
+) -> Result<(), SetLoggerError> {
+    unwrap_init(try_init_udp(local, server, hostname, facility, log_level))
+}
+
+/// TCP Logger init function compatible with log crate
+#[cfg(feature = "log-integration")]
+pub fn init_tcp<T: ToSocketAddrs + ToString>(
+    server: T,
+    hostname: String,
+    facility: Facility,
+    log_level: LogLevelFilter,
+) -> Result<(), SetLoggerError> {
+    unwrap_init(try_init_tcp(server, hostname, facility, log_level))
+}
+
+/// Overrides the `init` fallback chain: comma-separated endpoint URLs,
+/// e.g. `unix:///dev/log,udp://logs.internal:514,stderr`.
+pub const SYSLOG_URL_VAR: &'static str = "SYSLOG_URL";
+
+/// One step of the `init` fallback chain, as an endpoint URL. Recognized
+/// forms: `unix` / `unix://` (probe the default socket locations),
+/// `unix:///path/to/socket`, `tcp://host:port`, `udp://host:port`,
+/// `journald`, `stderr` and `null`.
+fn connect_url(url: &str, facility: Facility) -> Result<Box<Logger>, io::Error> {
+    match url {
+        "unix" | "unix://" => return unix(facility),
+        "journald" => return journald(facility),
+        "stderr" => return stderr(facility),
+        "null" => return null(facility),
+        _ => {}
+    }
+    if let Some(path) = scheme_rest(url, "unix://") {
+        return Builder::new().facility(facility).unix_path(path).connect();
+    }
+    if let Some(server) = scheme_rest(url, "tcp://") {
+        return tcp(server, "localhost".to_owned(), facility);
+    }
+    if let Some(server) = scheme_rest(url, "udp://") {
+        return udp_to(server, "localhost".to_owned(), facility);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("unsupported syslog url: {}", url),
+    ))
+}
+
+fn scheme_rest<'a>(url: &'a str, scheme: &str) -> Option<&'a str> {
+    if url.starts_with(scheme) {
+        Some(&url[scheme.len()..])
+    } else {
+        None
+    }
+}
+
+/// Splits a `SYSLOG_URL`-style list into trimmed endpoint URLs.
+fn parse_chain(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(|url| url.trim().to_owned())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+/// The fallback chain `init` tries in order: `SYSLOG_URL` when set, else
+/// the classic unix → tcp 601 → udp 514 → stderr detection.
+fn detection_chain() -> Vec<String> {
+    match env::var(SYSLOG_URL_VAR) {
+        Ok(ref urls) if !urls.is_empty() => parse_chain(urls),
+        _ => vec![
+            "unix".to_owned(),
+            "tcp://127.0.0.1:601".to_owned(),
+            "udp://127.0.0.1:514".to_owned(),
+            "stderr".to_owned(),
+        ],
+    }
+}
+
+/// Connects the first reachable endpoint; unreachable and malformed
+/// entries are skipped, and the null logger absorbs messages when the
+/// whole chain fails, so this never panics.
+fn connect_chain(urls: &[String], facility: Facility) -> Box<Logger> {
+    for url in urls {
+        if let Ok(logger) = connect_url(url, facility) {
+            return logger;
+        }
+    }
+    // The null backend has nothing to connect, so this cannot fail.
+    null(facility).unwrap()
+}
+
+/// Initializes logging over the endpoint chain from `SYSLOG_URL`, or the
+/// default unix socket → tcp on port 601 → udp on port 514 → stderr
+/// detection when it is unset. Never panics: when no endpoint is
+/// reachable messages are discarded rather than taking the process down.
+#[cfg(feature = "log-integration")]
+pub fn init(
+    facility: Facility,
+    log_level: LogLevelFilter,
+    application_name: Option<&str>,
+) -> Result<(), SetLoggerError> {
+    log::set_logger(|max_level| {
+        max_level.set(log_level);
+        let mut logger = connect_chain(&detection_chain(), facility);
+        if let Some(name) = application_name {
+            logger.set_process_name(name.to_owned());
+        }
+        logger
+    })
+}
+
+/// Appends one field in the native journal protocol: `NAME=value\n`, or the
+/// length-prefixed binary form when the value contains a newline.
+fn append_journald_field(buf: &mut Vec<u8>, name: &str, value: &str) {
+    if value.contains('\n') {
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(b'\n');
+        let len = value.len() as u64;
+        for i in 0..8 {
+            buf.push((len >> (8 * i)) as u8);
+        }
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    } else {
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(b'=');
+        buf.extend_from_slice(value.as_bytes());
+        buf.push(b'\n');
+    }
+}
+
+/// Journal field names are uppercase ASCII letters, digits and underscores,
+/// and may not start with a digit or underscore.
+fn journald_field_name(name: &str) -> String {
+    let mut field: String = name
+        .chars()
+        .map(|c| match c {
+            'a'...'z' => ((c as u8) - b'a' + b'A') as char,
+            'A'...'Z' | '0'...'9' => c,
+            _ => '_',
+        })
+        .collect();
+    if field
+        .as_bytes()
+        .first()
+        .map(|&b| b == b'_' || (b >= b'0' && b <= b'9'))
+        .unwrap_or(true)
+    {
+        field.insert(0, 'X');
+    }
+    field
+}
+
+/// Longest prefix of `s` at most `max` bytes long, cut on a char boundary.
+fn truncate_at_boundary(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while end > 0 && !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Connects the local syslog socket at `path`. The socket is normally
+/// SOCK_DGRAM, but some daemons bind SOCK_STREAM instead, and the kernel
+/// refuses the mismatched connect with EPROTOTYPE; fall back to a stream
+/// connection in that case.
+fn connect_unix_socket<P: AsRef<Path>>(path: P) -> Result<LoggerBackend, io::Error> {
+    let path = path.as_ref();
+    let dgram = UnixDatagram::unbound()?;
+    match dgram.connect(path) {
+        Ok(()) => Ok(LoggerBackend::Unix(Mutex::new(dgram), path.to_path_buf())),
+        Err(ref e) if e.raw_os_error() == Some(libc::EPROTOTYPE) => {
+            let stream = UnixStream::connect(path)?;
+            Ok(LoggerBackend::UnixStream(
+                Mutex::new(stream),
+                path.to_path_buf(),
+            ))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn apply_write_timeout(backend: &LoggerBackend, timeout: Duration) -> Result<(), io::Error> {
+    match *backend {
+        LoggerBackend::Unix(ref dgram, _) => dgram.lock().unwrap().set_write_timeout(Some(timeout)),
+        LoggerBackend::UnixStream(ref stream_wrap, _) => {
+            stream_wrap.lock().unwrap().set_write_timeout(Some(timeout))
+        }
+        LoggerBackend::Udp(ref socket, _) => socket.set_write_timeout(Some(timeout)),
+        LoggerBackend::Tcp(ref stream_wrap, _) => {
+            stream_wrap.lock().unwrap().set_write_timeout(Some(timeout))
+        }
+        LoggerBackend::Tls(ref stream_wrap) => stream_wrap
+            .lock()
+            .unwrap()
+            .get_ref()
+            .set_write_timeout(Some(timeout)),
+        LoggerBackend::Journald(ref dgram) => {
+            dgram.lock().unwrap().set_write_timeout(Some(timeout))
+        }
+        LoggerBackend::GelfUdp(ref socket, _, _) => socket.set_write_timeout(Some(timeout)),
+        LoggerBackend::Stderr | LoggerBackend::File(..) | LoggerBackend::Null => Ok(()),
+    }
+}
+
+/// A timed-out write surfaces as TimedOut or WouldBlock depending on the
+/// platform.
+fn is_timeout(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => true,
+        _ => false,
+    }
+}
+
+fn is_disconnect(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionRefused
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::NotConnected => true,
+        _ => false,
+    }
+}
+
+fn tls_error<E: std::error::Error>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e.to_string())
+}
+
+fn resolve_first<T: ToSocketAddrs>(addr: T) -> Result<SocketAddr, io::Error> {
+    addr.to_socket_addrs()?.next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "could not resolve server address")
+    })
+}
+
+/// The machine's hostname via gethostname(2), used when the caller does
+/// not supply one.
+fn detect_hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    let res = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if res != 0 {
+        return None;
+    }
+    let end = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    match String::from_utf8(buf[..end].to_vec()) {
+        Ok(ref hostname) if hostname.is_empty() => None,
+        Ok(hostname) => Some(hostname),
+        Err(_) => None,
+    }
+}
+
+/// The calling thread's id: the kernel tid on Linux, the pthread handle
+/// elsewhere.
+#[cfg(target_os = "linux")]
+fn thread_id() -> u64 {
+    unsafe { libc::syscall(libc::SYS_gettid) as u64 }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn thread_id() -> u64 {
+    unsafe { libc::pthread_self() as u64 }
+}
+
+fn get_process_name() -> Option<String> {
+    env::current_exe().ok().and_then(|path| {
+        path.file_name()
+            .and_then(|os_name| os_name.to_str())
+            .map(|name| name.to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_logger(hostname: Option<&str>, pid: i32) -> Logger {
+        Logger {
+            facility: Facility::LOG_USER,
+            hostname: hostname.map(|h| h.to_owned()),
+            process_info: ProcessInfo {
+                name: "test".to_owned(),
+                pid: pid,
+                include_thread_id: false,
+            },
+            formatter: Box::new(format::Plain),
+            reconnect: ReconnectPolicy::default(),
+            tcp_framing: TcpFraming::OctetCounted,
+            min_severity: None,
+            target_severity: HashMap::new(),
+            max_message_size: None,
+            size_limit_policy: SizeLimitPolicy::Truncate,
+            write_timeout: None,
+            drop_on_timeout: false,
+            last_error: Mutex::new(None),
+            counters: Counters::new(),
+            s: LoggerBackend::Unix(
+                Mutex::new(UnixDatagram::unbound().unwrap()),
+                PathBuf::new(),
+            ),
+        }
+    }
+
+    /// 2009-02-13T23:31:30.123Z
+    #[cfg(feature = "rfc5424")]
+    fn fixed_timestamp() -> time::Tm {
+        time::at_utc(time::Timespec::new(1234567890, 123_000_000))
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn format_5424_millisecond_timestamp() {
+        let logger = test_logger(Some("host"), 42);
+        let formatted = logger.format_5424_at(
+            fixed_timestamp(),
+            Severity::LOG_INFO,
+            Some(7),
+            &StructuredDataBuilder::new(),
+            "hello",
+        );
+        assert_eq!(formatted, "<14>1 2009-02-13T23:31:30.123Z host test 42 7 - hello");
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn format_5424_nil_hostname() {
+        let logger = test_logger(None, 42);
+        let formatted = logger.format_5424_at(
+            fixed_timestamp(),
+            Severity::LOG_INFO,
+            Some(7),
+            &StructuredDataBuilder::new(),
+            "hello",
+        );
+        assert_eq!(formatted, "<14>1 2009-02-13T23:31:30.123Z - test 42 7 - hello");
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn format_5424_nil_procid() {
+        let logger = test_logger(Some("host"), 0);
+        let formatted = logger.format_5424_at(
+            fixed_timestamp(),
+            Severity::LOG_INFO,
+            Some(7),
+            &StructuredDataBuilder::new(),
+            "hello",
+        );
+        assert_eq!(formatted, "<14>1 2009-02-13T23:31:30.123Z host test - 7 - hello");
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn format_5424_nil_msgid() {
+        let logger = test_logger(Some("host"), 42);
+        let formatted = logger.format_5424_at(
+            fixed_timestamp(),
+            Severity::LOG_INFO,
+            None,
+            &StructuredDataBuilder::new(),
+            "hello",
+        );
+        assert_eq!(formatted, "<14>1 2009-02-13T23:31:30.123Z host test 42 - - hello");
+    }
+
+    #[cfg(feature = "rfc5424")]
+    #[test]
+    fn format_5424_all_nil() {
+        let logger = test_logger(None, 0);
+        let formatted = logger.format_5424_at(
+            fixed_timestamp(),
+            Severity::LOG_INFO,
+            None,
+            &StructuredDataBuilder::new(),
+            "hello",
+        );
+        assert_eq!(formatted, "<14>1 2009-02-13T23:31:30.123Z - test - - - hello");
+    }
+
+    #[test]
+    fn split_limit_below_char_width_still_terminates() {
+        let mut logger = test_logger(Some("host"), 42);
+        // 1 byte cannot hold either character below; each chunk must
+        // still carry one whole char instead of looping forever
+        logger.set_max_message_size(1, SizeLimitPolicy::Split);
+        let parts = logger.apply_size_limit("é…").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert!(parts[0].ends_with('é'));
+        assert!(parts[1].ends_with('…'));
+    }
+
+    #[test]
+    fn unix_stream_fallback_and_newline_framing() {
+        use std::fs;
+        use std::os::unix::net::UnixListener;
+
+        let path = env::temp_dir().join(format!("syslog-test-stream-{}", unsafe { getpid() }));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let backend = connect_unix_socket(path.to_str().unwrap()).unwrap();
+        match backend {
+            LoggerBackend::UnixStream(..) => {}
+            _ => panic!("datagram connect to a stream socket should fall back"),
+        }
+
+        let mut logger = test_logger(Some("host"), 42);
+        logger.s = backend;
+        let (mut server_side, _) = listener.accept().unwrap();
+        // The returned count covers the newline framing too.
+        assert_eq!(logger.send_raw(b"hello").unwrap(), 6);
+        let mut received = [0u8; 6];
+        server_side.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"hello\n");
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "rfc3164")]
+    #[test]
+    fn cee_messages_carry_cookie_and_compact_json() {
+        use std::fs;
+        use std::os::unix::net::UnixListener;
+
+        let path = env::temp_dir().join(format!("syslog-test-cee-{}", unsafe { getpid() }));
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path).unwrap();
+
+        let mut logger = test_logger(Some("host"), 42);
+        logger.s = connect_unix_socket(&path).unwrap();
+        let (mut server_side, _) = listener.accept().unwrap();
+
+        let payload: serde_json::Value =
+            "{\"event\":\"sign\",\"result\":0}".parse().unwrap();
+        let sent = logger.send_cee(Severity::LOG_NOTICE, &payload).unwrap();
+        let mut received = vec![0u8; sent];
+        server_side.read_exact(&mut received).unwrap();
+        let received = String::from_utf8(received).unwrap();
+        assert!(
+            received.contains(": @cee:{\"event\":\"sign\",\"result\":0}"),
+            "{}",
+            received
+        );
+
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn chain_parsing_and_null_fallback() {
+        assert_eq!(
+            parse_chain("unix:///dev/log, udp://logs:514 ,stderr,"),
+            ["unix:///dev/log", "udp://logs:514", "stderr"]
+        );
+        // Malformed and unreachable entries fall through to the null
+        // logger instead of panicking.
+        let chain = vec![
+            "bogus://nowhere".to_owned(),
+            "unix:///nonexistent-socket".to_owned(),
+        ];
+        let logger = connect_chain(&chain, Facility::LOG_USER);
+        match logger.s {
+            LoggerBackend::Null => {}
+            _ => panic!("expected the null fallback"),
+        }
+        assert!(logger.send(Severity::LOG_INFO, "discarded").unwrap() > 0);
+    }
+
+    const ALL_SEVERITIES: [Severity; 8] = [
+        Severity::LOG_EMERG,
+        Severity::LOG_ALERT,
+        Severity::LOG_CRIT,
+        Severity::LOG_ERR,
+        Severity::LOG_WARNING,
+        Severity::LOG_NOTICE,
+        Severity::LOG_INFO,
+        Severity::LOG_DEBUG,
+    ];
+
+    const ALL_FACILITIES: [Facility; 20] = [
+        Facility::LOG_KERN,
+        Facility::LOG_USER,
+        Facility::LOG_MAIL,
+        Facility::LOG_DAEMON,
+        Facility::LOG_AUTH,
+        Facility::LOG_SYSLOG,
+        Facility::LOG_LPR,
+        Facility::LOG_NEWS,
+        Facility::LOG_UUCP,
+        Facility::LOG_CRON,
+        Facility::LOG_AUTHPRIV,
+        Facility::LOG_FTP,
+        Facility::LOG_LOCAL0,
+        Facility::LOG_LOCAL1,
+        Facility::LOG_LOCAL2,
+        Facility::LOG_LOCAL3,
+        Facility::LOG_LOCAL4,
+        Facility::LOG_LOCAL5,
+        Facility::LOG_LOCAL6,
+        Facility::LOG_LOCAL7,
+    ];
+
+    #[test]
+    fn severity_string_and_number_round_trip() {
+        for &severity in ALL_SEVERITIES.iter() {
+            assert_eq!(severity.to_string().parse(), Ok(severity));
+            assert_eq!(format!("LOG_{}", severity).to_uppercase().parse(), Ok(severity));
+            assert_eq!(Severity::try_from(severity as u8), Ok(severity));
+        }
+        assert_eq!("warn".parse(), Ok(Severity::LOG_WARNING));
+        assert_eq!("error".parse(), Ok(Severity::LOG_ERR));
+        assert_eq!("panic".parse(), Ok(Severity::LOG_EMERG));
+        assert_eq!("".parse::<Severity>(), Err(()));
+        assert_eq!("verbose".parse::<Severity>(), Err(()));
+        assert_eq!(Severity::try_from(8), Err(()));
+    }
+
+    #[test]
+    fn facility_string_and_number_round_trip() {
+        for &facility in ALL_FACILITIES.iter() {
+            assert_eq!(facility.to_string().parse(), Ok(facility));
+            assert_eq!(format!("LOG_{}", facility).to_uppercase().parse(), Ok(facility));
+            assert_eq!(Facility::try_from(facility as u8), Ok(facility));
+        }
+        // Severity bits set, and the unassigned facility numbers 12-15.
+        assert_eq!(Facility::try_from((Facility::LOG_USER as u8) | 3), Err(()));
+        for number in 12u8..16 {
+            assert_eq!(Facility::try_from(number << 3), Err(()));
+        }
+    }
+
+    #[test]
+    fn priority_decode_inverts_encoding() {
+        for &facility in ALL_FACILITIES.iter() {
+            for &severity in ALL_SEVERITIES.iter() {
+                let priority = facility as u8 | severity as u8;
+                assert_eq!(decode_priority(priority), Ok((facility, severity)));
+            }
+        }
+        // <14> is the classic user.info example from RFC 3164.
+        assert_eq!(
+            decode_priority(14),
+            Ok((Facility::LOG_USER, Severity::LOG_INFO))
+        );
+        assert_eq!(decode_priority(12 << 3), Err(()));
+    }
+}
@@ -0,0 +1,120 @@
+//! Mapped diagnostic context.
+//!
+//! A thread-local set of key/value pairs attached to every message logged
+//! from that thread. RFC 5424 formatters render the pairs as an `mdc@kr`
+//! structured-data element; RFC 3164 appends them as `key=value` suffixes.
+//! This lets callers like the PKCS#11 shim tag a whole session's log lines
+//! once instead of threading the tag through every call site.
+
+use std::cell::RefCell;
+
+thread_local! {
+    static MDC: RefCell<Vec<(String, String)>> = RefCell::new(Vec::new());
+}
+
+/// Sets `key` for the current thread, replacing any previous value.
+pub fn insert(key: &str, value: &str) {
+    MDC.with(|mdc| {
+        let mut pairs = mdc.borrow_mut();
+        for &mut (ref k, ref mut v) in pairs.iter_mut() {
+            if k == key {
+                *v = value.to_owned();
+                return;
+            }
+        }
+        pairs.push((key.to_owned(), value.to_owned()));
+    })
+}
+
+/// Removes `key` from the current thread's context, returning its value.
+pub fn remove(key: &str) -> Option<String> {
+    MDC.with(|mdc| {
+        let mut pairs = mdc.borrow_mut();
+        match pairs.iter().position(|&(ref k, _)| k == key) {
+            Some(index) => Some(pairs.remove(index).1),
+            None => None,
+        }
+    })
+}
+
+pub fn get(key: &str) -> Option<String> {
+    MDC.with(|mdc| {
+        mdc.borrow()
+            .iter()
+            .find(|&&(ref k, _)| k == key)
+            .map(|&(_, ref v)| v.clone())
+    })
+}
+
+pub fn clear() {
+    MDC.with(|mdc| mdc.borrow_mut().clear())
+}
+
+/// A copy of the current thread's context, in insertion order.
+pub fn snapshot() -> Vec<(String, String)> {
+    MDC.with(|mdc| mdc.borrow().clone())
+}
+
+/// Sets the pairs for the guard's lifetime; dropping the guard restores
+/// whatever each key held before.
+pub fn scoped(pairs: &[(&str, &str)]) -> MdcGuard {
+    let mut saved = Vec::with_capacity(pairs.len());
+    for &(key, value) in pairs {
+        saved.push((key.to_owned(), get(key)));
+        insert(key, value);
+    }
+    MdcGuard { saved: saved }
+}
+
+/// Restores the previous context values when dropped.
+pub struct MdcGuard {
+    saved: Vec<(String, Option<String>)>,
+}
+
+impl Drop for MdcGuard {
+    fn drop(&mut self) {
+        for &(ref key, ref previous) in self.saved.iter().rev() {
+            match *previous {
+                Some(ref value) => insert(key, value),
+                None => {
+                    remove(key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_replaces_and_preserves_order() {
+        clear();
+        insert("a", "1");
+        insert("b", "2");
+        insert("a", "3");
+        assert_eq!(
+            snapshot(),
+            vec![
+                ("a".to_owned(), "3".to_owned()),
+                ("b".to_owned(), "2".to_owned()),
+            ]
+        );
+        clear();
+    }
+
+    #[test]
+    fn scoped_restores_previous_values() {
+        clear();
+        insert("session", "outer");
+        {
+            let _guard = scoped(&[("session", "inner"), ("op", "sign")]);
+            assert_eq!(get("session"), Some("inner".to_owned()));
+            assert_eq!(get("op"), Some("sign".to_owned()));
+        }
+        assert_eq!(get("session"), Some("outer".to_owned()));
+        assert_eq!(get("op"), None);
+        clear();
+    }
+}
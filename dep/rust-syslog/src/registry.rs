@@ -0,0 +1,82 @@
+//! Process-wide logger sharing.
+//!
+//! A process can end up constructing the same logger several times: each
+//! dlopen of the PKCS#11 shim (SSH and git may both load it) builds its own
+//! global, and each would otherwise hold its own syslog socket.
+//! `connect_shared` hands out one reference-counted `Logger` per
+//! (facility, backend) pair instead. The registry holds only weak
+//! references, so once the last handle drops the socket closes, and the
+//! next request reconnects.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, Once, Weak, ONCE_INIT};
+
+use {Builder, Logger};
+
+fn registry() -> &'static Mutex<HashMap<String, Weak<Logger>>> {
+    static mut REGISTRY: Option<Mutex<HashMap<String, Weak<Logger>>>> = None;
+    static INIT: Once = ONCE_INIT;
+    unsafe {
+        INIT.call_once(|| REGISTRY = Some(Mutex::new(HashMap::new())));
+        REGISTRY.as_ref().unwrap()
+    }
+}
+
+/// Returns the live logger for the builder's (facility, backend) pair, or
+/// connects a new one and registers it. The lock is held across the
+/// connect so concurrent first callers do not race to open two sockets.
+pub fn connect_shared(builder: Builder) -> Result<Arc<Logger>, io::Error> {
+    let key = builder.registry_key();
+    let mut registry = registry().lock().unwrap();
+    if let Some(existing) = registry.get(&key).and_then(|weak| weak.upgrade()) {
+        return Ok(existing);
+    }
+    registry.retain(|_, weak| weak.upgrade().is_some());
+    let logger: Arc<Logger> = Arc::from(builder.connect()?);
+    registry.insert(key, Arc::downgrade(&logger));
+    Ok(logger)
+}
+
+/// How many distinct shared loggers are currently alive.
+pub fn active_count() -> usize {
+    registry()
+        .lock()
+        .unwrap()
+        .values()
+        .filter(|weak| weak.upgrade().is_some())
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Facility;
+
+    #[test]
+    fn same_key_shares_one_logger() {
+        let first = connect_shared(Builder::new().facility(Facility::LOG_LOCAL7).stderr())
+            .unwrap();
+        let second = connect_shared(Builder::new().facility(Facility::LOG_LOCAL7).stderr())
+            .unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn different_facilities_do_not_share() {
+        let first = connect_shared(Builder::new().facility(Facility::LOG_LOCAL5).stderr())
+            .unwrap();
+        let second = connect_shared(Builder::new().facility(Facility::LOG_LOCAL6).stderr())
+            .unwrap();
+        assert!(!Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn dropped_loggers_leave_the_registry() {
+        let logger = connect_shared(Builder::new().facility(Facility::LOG_LOCAL4).stderr())
+            .unwrap();
+        let weak = Arc::downgrade(&logger);
+        drop(logger);
+        assert!(weak.upgrade().is_none());
+    }
+}
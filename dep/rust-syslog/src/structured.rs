@@ -0,0 +1,161 @@
+//! RFC 5424 structured data.
+//!
+//! SD elements are kept in insertion order and rendered with the escaping
+//! RFC 5424 §6.3.3 requires (`\`, `"` and `]` in PARAM-VALUE are
+//! backslash-escaped). SD-ID and PARAM-NAME are validated against the
+//! SD-NAME grammar: 1-32 printable US-ASCII characters excluding `=`,
+//! space, `]` and `"`.
+
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum StructuredDataError {
+    /// The SD-ID is empty, too long, or contains a forbidden character.
+    InvalidSdId(String),
+    /// The PARAM-NAME is empty, too long, or contains a forbidden character.
+    InvalidParamName(String),
+    /// `param` was called before any `element`.
+    NoElement,
+}
+
+impl fmt::Display for StructuredDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StructuredDataError::InvalidSdId(ref id) => write!(f, "invalid SD-ID {:?}", id),
+            StructuredDataError::InvalidParamName(ref name) => {
+                write!(f, "invalid PARAM-NAME {:?}", name)
+            }
+            StructuredDataError::NoElement => write!(f, "param added before any element"),
+        }
+    }
+}
+
+impl Error for StructuredDataError {
+    fn description(&self) -> &str {
+        "invalid RFC 5424 structured data"
+    }
+}
+
+/// Ordered RFC 5424 structured data under construction.
+#[derive(Clone, Debug, Default)]
+pub struct StructuredDataBuilder {
+    elements: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl StructuredDataBuilder {
+    pub fn new() -> StructuredDataBuilder {
+        StructuredDataBuilder {
+            elements: Vec::new(),
+        }
+    }
+
+    /// Opens a new SD element; subsequent `param` calls attach to it.
+    pub fn element(mut self, id: &str) -> Result<StructuredDataBuilder, StructuredDataError> {
+        if !is_sd_name(id) {
+            return Err(StructuredDataError::InvalidSdId(id.to_owned()));
+        }
+        self.elements.push((id.to_owned(), Vec::new()));
+        Ok(self)
+    }
+
+    /// Adds a parameter to the most recently opened element.
+    pub fn param(
+        mut self,
+        name: &str,
+        value: &str,
+    ) -> Result<StructuredDataBuilder, StructuredDataError> {
+        if !is_sd_name(name) {
+            return Err(StructuredDataError::InvalidParamName(name.to_owned()));
+        }
+        match self.elements.last_mut() {
+            Some(&mut (_, ref mut params)) => {
+                params.push((name.to_owned(), value.to_owned()));
+                Ok(self)
+            }
+            None => Err(StructuredDataError::NoElement),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elements.is_empty()
+    }
+
+    /// The elements in insertion order, for backends (like journald) that
+    /// serialize structured data themselves.
+    pub fn elements(&self) -> &[(String, Vec<(String, String)>)] {
+        &self.elements[..]
+    }
+
+    /// Renders the STRUCTURED-DATA field; "-" (NILVALUE) when empty.
+    pub fn render(&self) -> String {
+        if self.elements.is_empty() {
+            return "-".to_owned();
+        }
+        let mut res = String::new();
+        for &(ref id, ref params) in &self.elements {
+            res = res + "[" + id;
+            for &(ref name, ref value) in params {
+                res = res + " " + name + "=\"" + &escape_param_value(value) + "\"";
+            }
+            res += "]";
+        }
+        res
+    }
+}
+
+/// Renders one SD element from unvalidated pairs, sanitizing names into
+/// the SD-NAME grammar instead of rejecting them. Used for the `mdc@kr`
+/// element, whose keys come from arbitrary caller strings.
+pub fn render_raw_element(id: &str, params: &[(String, String)]) -> String {
+    let mut res = String::new();
+    res = res + "[" + id;
+    for &(ref name, ref value) in params {
+        res = res + " " + &sanitize_sd_name(name) + "=\"" + &escape_param_value(value) + "\"";
+    }
+    res += "]";
+    res
+}
+
+/// Maps forbidden SD-NAME characters to '_' and truncates to 32 bytes.
+fn sanitize_sd_name(name: &str) -> String {
+    let mut sanitized: String = name
+        .chars()
+        .map(|c| {
+            let b = c as u32;
+            if b > 32 && b < 127 && c != '=' && c != ']' && c != '"' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(32)
+        .collect();
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+/// SD-NAME per RFC 5424: 1*32 PRINTUSASCII except '=', SP, ']', '"'.
+fn is_sd_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name
+            .bytes()
+            .all(|b| b > 32 && b < 127 && b != b'=' && b != b']' && b != b'"')
+}
+
+fn escape_param_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | '"' | ']' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,897 @@
+//! Integration with the C library's users and groups.
+//!
+//! This module uses `extern` functions and types from `libc` that integrate
+//! with the system's C library, which integrates with the OS itself to get user
+//! and group information. It's where the "core" user handling is done.
+
+use std::env;
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::ptr::{self, read};
+use std::sync::{Arc, Mutex, MutexGuard, Once, ONCE_INIT};
+
+use libc::{c_char, c_int, pid_t, size_t, uid_t, gid_t, ERANGE};
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly"))]
+use libc::{c_long, time_t};
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+use libc::time_t;
+
+use os;
+
+// glibc and musl agree on this layout, so one Linux definition covers
+// Alpine containers too (checked by the layout tests below).
+#[repr(C)]
+#[cfg(target_os = "linux")]
+pub struct c_passwd {
+    pub pw_name:   *const c_char,  // user name
+    pub pw_passwd: *const c_char,  // password field
+    pub pw_uid:    uid_t,          // user ID
+    pub pw_gid:    gid_t,          // group ID
+    pub pw_gecos:  *const c_char,  // full name
+    pub pw_dir:    *const c_char,  // home directory
+    pub pw_shell:  *const c_char,  // login shell
+}
+
+#[repr(C)]
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly"))]
+pub struct c_passwd {
+    pub pw_name:   *const c_char,  // user name
+    pub pw_passwd: *const c_char,  // password field
+    pub pw_uid:    uid_t,          // user ID
+    pub pw_gid:    gid_t,          // group ID
+    pub pw_change: time_t,         // password change time
+    pub pw_class:  *const c_char,  // user access class
+    pub pw_gecos:  *const c_char,  // full name
+    pub pw_dir:    *const c_char,  // home directory
+    pub pw_shell:  *const c_char,  // login shell
+    pub pw_expire: time_t,         // account expiry time
+    pub pw_fields: c_long,         // internal: fields filled in
+}
+
+// OpenBSD and NetBSD carry the BSD password-ageing fields but not
+// FreeBSD's internal pw_fields member.
+#[repr(C)]
+#[cfg(any(target_os = "openbsd", target_os = "netbsd"))]
+pub struct c_passwd {
+    pub pw_name:   *const c_char,  // user name
+    pub pw_passwd: *const c_char,  // password field
+    pub pw_uid:    uid_t,          // user ID
+    pub pw_gid:    gid_t,          // group ID
+    pub pw_change: time_t,         // password change time
+    pub pw_class:  *const c_char,  // user access class
+    pub pw_gecos:  *const c_char,  // full name
+    pub pw_dir:    *const c_char,  // home directory
+    pub pw_shell:  *const c_char,  // login shell
+    pub pw_expire: time_t,         // account expiry time
+}
+
+#[repr(C)]
+pub struct c_group {
+    pub gr_name:   *const c_char,         // group name
+    pub gr_passwd: *const c_char,         // password field
+    pub gr_gid:    gid_t,                 // group ID
+    pub gr_mem:    *const *const c_char,  // names of members
+}
+
+extern "C" {
+    fn getpwuid_r(uid: uid_t, pwd: *mut c_passwd, buf: *mut c_char,
+                  buflen: size_t, result: *mut *mut c_passwd) -> c_int;
+    fn getpwnam_r(user_name: *const c_char, pwd: *mut c_passwd, buf: *mut c_char,
+                  buflen: size_t, result: *mut *mut c_passwd) -> c_int;
+    fn getgrgid_r(gid: gid_t, grp: *mut c_group, buf: *mut c_char,
+                  buflen: size_t, result: *mut *mut c_group) -> c_int;
+    fn getgrnam_r(group_name: *const c_char, grp: *mut c_group, buf: *mut c_char,
+                  buflen: size_t, result: *mut *mut c_group) -> c_int;
+
+    fn getuid() -> uid_t;
+    fn geteuid() -> uid_t;
+    fn getgid() -> gid_t;
+    fn getegid() -> gid_t;
+    fn getpid() -> pid_t;
+
+    fn setpwent();
+    fn getpwent() -> *const c_passwd;
+    fn endpwent();
+
+    fn setgrent();
+    fn getgrent() -> *const c_group;
+    fn endgrent();
+}
+
+// Darwin declares getgrouplist over int rather than gid_t.
+#[cfg(target_os = "macos")]
+#[allow(non_camel_case_types)]
+type grouplist_gid = c_int;
+#[cfg(not(target_os = "macos"))]
+#[allow(non_camel_case_types)]
+type grouplist_gid = gid_t;
+
+extern "C" {
+    fn getgrouplist(user: *const c_char, group: grouplist_gid,
+                    groups: *mut grouplist_gid, ngroups: *mut c_int) -> c_int;
+}
+
+/// Information about a particular user.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct User {
+    /// This user's ID.
+    pub uid: uid_t,
+
+    /// This user's name.
+    #[cfg_attr(feature = "serde_support", serde(rename = "name"))]
+    pub name_arc: Arc<String>,
+
+    /// The ID of this user's primary group.
+    pub primary_group: gid_t,
+
+    /// Extra platform-specific fields.
+    pub extras: os::UserExtras,
+}
+
+impl User {
+    /// Returns this user's name.
+    pub fn name(&self) -> &str {
+        &**self.name_arc
+    }
+
+    /// Returns this user's home directory.
+    pub fn home_dir(&self) -> &str {
+        &self.extras.home_dir
+    }
+
+    /// Returns this user's login shell.
+    pub fn shell(&self) -> &str {
+        &self.extras.shell
+    }
+
+    /// Returns this user's raw GECOS field.
+    pub fn gecos(&self) -> &str {
+        &self.extras.gecos
+    }
+
+    /// Returns this user's full name: the first comma-separated GECOS
+    /// sub-field (the rest being office and phone numbers by convention),
+    /// e.g. "Jane Doe" for a GECOS of "Jane Doe,,555-1234".
+    pub fn full_name(&self) -> &str {
+        self.extras.gecos.split(',').next().unwrap_or("")
+    }
+
+    /// Whether this user belongs to the given group, either as their
+    /// primary group or through the group's member list.
+    pub fn is_in_group(&self, group: &Group) -> bool {
+        self.primary_group == group.gid || group.is_member(self.name())
+    }
+}
+
+/// Information about a particular group.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+pub struct Group {
+    /// This group's ID.
+    pub gid: gid_t,
+
+    /// This group's name.
+    #[cfg_attr(feature = "serde_support", serde(rename = "name"))]
+    pub name_arc: Arc<String>,
+
+    /// The group's password field, almost always a placeholder like "x"
+    /// or "*" these days.
+    pub passwd: String,
+
+    /// The names of this group's members.
+    pub members: Vec<String>,
+}
+
+impl Group {
+    /// Returns this group's name.
+    pub fn name(&self) -> &str {
+        &**self.name_arc
+    }
+
+    /// Whether the group has an actual group password set, as opposed to
+    /// the usual "x"/"*"/empty placeholders.
+    pub fn has_password(&self) -> bool {
+        match &self.passwd[..] {
+            "" | "x" | "*" => false,
+            _ => true,
+        }
+    }
+
+    /// Whether the named user appears in this group's member list. Note
+    /// that users are not listed in their primary group's members; use
+    /// `User::is_in_group` to cover both.
+    pub fn is_member(&self, username: &str) -> bool {
+        self.members.iter().any(|member| member == username)
+    }
+}
+
+unsafe fn from_raw_buf(p: *const c_char) -> String {
+    CStr::from_ptr(p).to_string_lossy().into_owned()
+}
+
+unsafe fn passwd_to_user(pointer: *const c_passwd) -> Option<User> {
+    if pointer.is_null() {
+        return None;
+    }
+    let pw = read(pointer);
+    Some(User {
+        uid: pw.pw_uid,
+        name_arc: Arc::new(from_raw_buf(pw.pw_name)),
+        primary_group: pw.pw_gid,
+        extras: os::UserExtras::from_passwd(&pw),
+    })
+}
+
+unsafe fn struct_to_group(pointer: *const c_group) -> Option<Group> {
+    if pointer.is_null() {
+        return None;
+    }
+    let gr = read(pointer);
+    Some(Group {
+        gid: gr.gr_gid,
+        name_arc: Arc::new(from_raw_buf(gr.gr_name)),
+        passwd: os::string_from(gr.gr_passwd),
+        members: members(gr.gr_mem),
+    })
+}
+
+unsafe fn members(groups: *const *const c_char) -> Vec<String> {
+    let mut members = Vec::new();
+    for i in 0.. {
+        let username = groups.offset(i);
+        if username.is_null() || (*username).is_null() {
+            break;
+        }
+        members.push(from_raw_buf(*username));
+    }
+    members
+}
+
+/// Initial string buffer size for the reentrant lookups; doubled on ERANGE
+/// up to `LOOKUP_BUF_MAX`. glibc's sysconf default is 1024.
+const LOOKUP_BUF_SIZE: usize = 1024;
+const LOOKUP_BUF_MAX: usize = 1 << 20;
+
+/// Drives one of the `_r` lookups: calls `lookup` with a caller-managed
+/// string buffer, growing it and retrying while the C library reports
+/// ERANGE. The `_r` variants write into our buffers instead of static
+/// storage, so concurrent lookups from different threads are safe.
+///
+/// `Ok(None)` means the entry does not exist; `Err` carries the errno the
+/// C library reported, e.g. when an NSS backend is down.
+fn lookup_passwd<F>(lookup: F) -> Result<Option<User>, io::Error>
+    where F: Fn(*mut c_passwd, *mut c_char, size_t, *mut *mut c_passwd) -> c_int
+{
+    let mut buf: Vec<c_char> = vec![0; LOOKUP_BUF_SIZE];
+    loop {
+        let mut pwd: c_passwd = unsafe { mem::zeroed() };
+        let mut result: *mut c_passwd = ptr::null_mut();
+        let status = lookup(&mut pwd, buf.as_mut_ptr(), buf.len() as size_t, &mut result);
+        if status == ERANGE && buf.len() < LOOKUP_BUF_MAX {
+            let new_len = buf.len() * 2;
+            buf.resize(new_len, 0);
+            continue;
+        }
+        if status != 0 {
+            return Err(io::Error::from_raw_os_error(status));
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        return Ok(unsafe { passwd_to_user(result as *const c_passwd) });
+    }
+}
+
+/// The `lookup_passwd` equivalent for group lookups.
+fn lookup_group<F>(lookup: F) -> Result<Option<Group>, io::Error>
+    where F: Fn(*mut c_group, *mut c_char, size_t, *mut *mut c_group) -> c_int
+{
+    let mut buf: Vec<c_char> = vec![0; LOOKUP_BUF_SIZE];
+    loop {
+        let mut grp: c_group = unsafe { mem::zeroed() };
+        let mut result: *mut c_group = ptr::null_mut();
+        let status = lookup(&mut grp, buf.as_mut_ptr(), buf.len() as size_t, &mut result);
+        if status == ERANGE && buf.len() < LOOKUP_BUF_MAX {
+            let new_len = buf.len() * 2;
+            buf.resize(new_len, 0);
+            continue;
+        }
+        if status != 0 {
+            return Err(io::Error::from_raw_os_error(status));
+        }
+        if result.is_null() {
+            return Ok(None);
+        }
+        return Ok(unsafe { struct_to_group(result as *const c_group) });
+    }
+}
+
+/// Returns the user with the given user ID, distinguishing a missing
+/// entry (`Ok(None)`) from a failed lookup (`Err`, e.g. the NSS/LDAP
+/// backend being down).
+pub fn try_get_user_by_uid(uid: uid_t) -> Result<Option<User>, io::Error> {
+    lookup_passwd(|pwd, buf, buflen, result| unsafe {
+        getpwuid_r(uid, pwd, buf, buflen, result)
+    })
+}
+
+/// The error-reporting variant of `get_user_by_name`.
+pub fn try_get_user_by_name(username: &str) -> Result<Option<User>, io::Error> {
+    let username_c = match CString::new(username) {
+        Ok(c) => c,
+        Err(_) => return Ok(None),  // interior NUL cannot be a valid username
+    };
+    lookup_passwd(|pwd, buf, buflen, result| unsafe {
+        getpwnam_r(username_c.as_ptr(), pwd, buf, buflen, result)
+    })
+}
+
+/// The error-reporting variant of `get_group_by_gid`.
+pub fn try_get_group_by_gid(gid: gid_t) -> Result<Option<Group>, io::Error> {
+    lookup_group(|grp, buf, buflen, result| unsafe {
+        getgrgid_r(gid, grp, buf, buflen, result)
+    })
+}
+
+/// The error-reporting variant of `get_group_by_name`.
+pub fn try_get_group_by_name(group_name: &str) -> Result<Option<Group>, io::Error> {
+    let group_name_c = match CString::new(group_name) {
+        Ok(c) => c,
+        Err(_) => return Ok(None),
+    };
+    lookup_group(|grp, buf, buflen, result| unsafe {
+        getgrnam_r(group_name_c.as_ptr(), grp, buf, buflen, result)
+    })
+}
+
+/// Returns the user with the given user ID.
+pub fn get_user_by_uid(uid: uid_t) -> Option<User> {
+    try_get_user_by_uid(uid).unwrap_or(None)
+}
+
+/// Returns the user with the given username.
+pub fn get_user_by_name(username: &str) -> Option<User> {
+    try_get_user_by_name(username).unwrap_or(None)
+}
+
+/// Returns the group with the given group ID.
+pub fn get_group_by_gid(gid: gid_t) -> Option<Group> {
+    try_get_group_by_gid(gid).unwrap_or(None)
+}
+
+/// Returns the group with the given group name.
+pub fn get_group_by_name(group_name: &str) -> Option<Group> {
+    try_get_group_by_name(group_name).unwrap_or(None)
+}
+
+/// Looks a user up by name or numeric uid, the way `chown` reads its
+/// owner argument: the users table is consulted by name first, so an
+/// account actually named "1000" wins over uid 1000, and a numeric
+/// string matching no name is then tried as a uid.
+pub fn uid_or_name(value: &str) -> Option<User> {
+    if let Some(user) = get_user_by_name(value) {
+        return Some(user);
+    }
+    value.parse::<uid_t>().ok().and_then(get_user_by_uid)
+}
+
+/// The `uid_or_name` equivalent for groups.
+pub fn gid_or_name(value: &str) -> Option<Group> {
+    if let Some(group) = get_group_by_name(value) {
+        return Some(group);
+    }
+    value.parse::<gid_t>().ok().and_then(get_group_by_gid)
+}
+
+/// Returns the groups the user with the given username and primary group
+/// is a member of, per getgrouplist(3). `None` means the membership list
+/// could not be read, not that the user is in no groups: the primary group
+/// is always part of the result.
+pub fn get_user_groups(username: &str, gid: gid_t) -> Option<Vec<Group>> {
+    let username_c = match CString::new(username) {
+        Ok(c) => c,
+        Err(_) => return None,
+    };
+    let mut size = 16 as c_int;
+    loop {
+        let mut gids: Vec<grouplist_gid> = vec![0; size as usize];
+        let mut count = size;
+        let status = unsafe {
+            getgrouplist(username_c.as_ptr(), gid as grouplist_gid,
+                         gids.as_mut_ptr(), &mut count)
+        };
+        if status >= 0 {
+            gids.truncate(count as usize);
+            return Some(
+                gids.into_iter()
+                    .filter_map(|gid| get_group_by_gid(gid as gid_t))
+                    .collect(),
+            );
+        }
+        // The buffer was too small: glibc reports the needed size through
+        // `count`, Darwin leaves it to us to guess again.
+        size = if count > size { count } else { size * 2 };
+        if size as usize > LOOKUP_BUF_MAX {
+            return None;
+        }
+    }
+}
+
+/// Returns the user ID for the user running the process.
+pub fn get_current_uid() -> uid_t {
+    unsafe { getuid() }
+}
+
+/// Returns the username of the user running the process.
+pub fn get_current_username() -> Option<String> {
+    get_user_by_uid(get_current_uid()).map(|u| u.name().to_owned())
+}
+
+/// Returns the user ID for the effective user running the process.
+pub fn get_effective_uid() -> uid_t {
+    unsafe { geteuid() }
+}
+
+/// Returns the username of the effective user running the process.
+pub fn get_effective_username() -> Option<String> {
+    get_user_by_uid(get_effective_uid()).map(|u| u.name().to_owned())
+}
+
+/// Returns the group ID for the user running the process.
+pub fn get_current_gid() -> gid_t {
+    unsafe { getgid() }
+}
+
+/// Returns the group name of the user running the process.
+pub fn get_current_groupname() -> Option<String> {
+    get_group_by_gid(get_current_gid()).map(|g| g.name().to_owned())
+}
+
+/// Returns the group ID for the effective user running the process.
+pub fn get_effective_gid() -> gid_t {
+    unsafe { getegid() }
+}
+
+/// Returns the group name of the effective user running the process.
+pub fn get_effective_groupname() -> Option<String> {
+    get_group_by_gid(get_effective_gid()).map(|g| g.name().to_owned())
+}
+
+/// A point-in-time snapshot of the process's identity.
+///
+/// `capture` makes all of its libc calls up front; the resulting value is
+/// a plain `Copy` struct that can be read from anywhere — including
+/// signal handlers, where calling into libc again would not be
+/// async-signal-safe. The PKCS#11 shim captures one of these at load time
+/// since it runs inside arbitrary host processes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProcessIdentity {
+    /// The real user ID.
+    pub ruid: uid_t,
+    /// The effective user ID.
+    pub euid: uid_t,
+    /// The real group ID.
+    pub rgid: gid_t,
+    /// The effective group ID.
+    pub egid: gid_t,
+    /// The process ID.
+    pub pid: pid_t,
+}
+
+impl ProcessIdentity {
+    /// Snapshots the current identity.
+    pub fn capture() -> ProcessIdentity {
+        unsafe {
+            ProcessIdentity {
+                ruid: getuid(),
+                euid: geteuid(),
+                rgid: getgid(),
+                egid: getegid(),
+                pid: getpid(),
+            }
+        }
+    }
+
+    /// Whether the snapshot was taken with root privileges.
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+}
+
+/// The identities a process runs with, resolved in one call.
+///
+/// `ProcessIdentity` stays libc-only so it can be read from signal
+/// handlers; this struct adds the passwd-backed fields that cost a lookup
+/// (login name, home directory), for callers who need both the ids and
+/// the names without making several cache passes.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// The real user ID.
+    pub real_uid: uid_t,
+    /// The effective user ID.
+    pub effective_uid: uid_t,
+    /// The real group ID.
+    pub real_gid: gid_t,
+    /// The effective group ID.
+    pub effective_gid: gid_t,
+    /// The real uid's login name, when it has a passwd entry.
+    pub username: Option<String>,
+    /// The invoking user's home directory: `SUDO_USER`'s under sudo,
+    /// otherwise `HOME` or the real uid's passwd entry.
+    pub home_dir: Option<PathBuf>,
+}
+
+impl Identity {
+    /// Resolves the current process's identity: the four ids from libc
+    /// plus one passwd lookup (a second one under sudo, for the invoking
+    /// user's entry).
+    pub fn current() -> Identity {
+        let (real_uid, effective_uid, real_gid, effective_gid) =
+            unsafe { (getuid(), geteuid(), getgid(), getegid()) };
+        Identity {
+            real_uid: real_uid,
+            effective_uid: effective_uid,
+            real_gid: real_gid,
+            effective_gid: effective_gid,
+            username: get_user_by_uid(real_uid).map(|user| user.name().to_owned()),
+            home_dir: invoking_user_home().map(PathBuf::from),
+        }
+    }
+
+    /// Whether the process has root privileges.
+    pub fn is_root(&self) -> bool {
+        self.effective_uid == 0
+    }
+
+    /// Whether the process was started through sudo: running with root
+    /// privileges while sudo's bookkeeping (`SUDO_UID`/`SUDO_USER`) still
+    /// names the invoking user.
+    pub fn is_sudo(&self) -> bool {
+        self.is_root()
+            && (env::var_os("SUDO_UID").is_some() || env::var_os("SUDO_USER").is_some())
+    }
+}
+
+/// The process-wide lock serializing passwd and group enumeration.
+/// `setpwent`/`getpwent`/`endpwent` (and the `grent` equivalents) walk
+/// global C library state, so two concurrent iterations would corrupt
+/// each other.
+fn enumeration_lock() -> &'static Mutex<()> {
+    static mut LOCK: Option<Mutex<()>> = None;
+    static INIT: Once = ONCE_INIT;
+    unsafe {
+        INIT.call_once(|| LOCK = Some(Mutex::new(())));
+        LOCK.as_ref().unwrap()
+    }
+}
+
+/// Safely iterates over every user present on the system: the returned
+/// iterator holds the crate's enumeration lock, so no second enumeration
+/// can start until it is dropped.
+pub fn all_users() -> LockedAllUsers {
+    let guard = enumeration_lock().lock().unwrap_or_else(|e| e.into_inner());
+    #[allow(deprecated)]
+    let inner = unsafe { AllUsers::new() };
+    LockedAllUsers {
+        inner: inner,
+        _guard: guard,
+    }
+}
+
+/// Safely iterates over every group present on the system; the `all_users`
+/// of `AllGroups`.
+pub fn all_groups() -> LockedAllGroups {
+    let guard = enumeration_lock().lock().unwrap_or_else(|e| e.into_inner());
+    #[allow(deprecated)]
+    let inner = unsafe { AllGroups::new() };
+    LockedAllGroups {
+        inner: inner,
+        _guard: guard,
+    }
+}
+
+/// Shells that mark an account as not intended for interactive login.
+const NOLOGIN_SHELLS: [&'static str; 5] = [
+    "/sbin/nologin",
+    "/usr/sbin/nologin",
+    "/bin/nologin",
+    "/bin/false",
+    "/usr/bin/false",
+];
+
+/// Returns the user whose home directory is the given path, if any.
+/// Enumerates the whole users table, so prefer uid/name lookups when
+/// either is known.
+pub fn find_user_by_home_dir<P: AsRef<Path>>(home_dir: P) -> Option<User> {
+    let home_dir = home_dir.as_ref();
+    all_users().find(|user| Path::new(user.home_dir()) == home_dir)
+}
+
+/// Returns every user whose shell allows interactive login, i.e. human
+/// accounts whose dotfiles are worth touching; system accounts with
+/// nologin/false shells are skipped.
+pub fn users_with_login_shells() -> Vec<User> {
+    all_users().filter(has_login_shell).collect()
+}
+
+/// Whether this user's shell is a real shell rather than a nologin stub.
+pub fn has_login_shell(user: &User) -> bool {
+    let shell = user.shell();
+    !shell.is_empty() && !NOLOGIN_SHELLS.contains(&shell)
+}
+
+/// Expands a leading `~` or `~name` in a path to the corresponding home
+/// directory: `~alice/.kr` becomes `/home/alice/.kr`. A bare `~` is the
+/// invoking user's home — under sudo that is `SUDO_USER`'s home rather
+/// than root's, matching whose path the caller meant. Paths without a
+/// leading tilde come back unchanged; a tilde naming an unknown user
+/// yields `None`, since guessing a home directory would put files in the
+/// wrong place.
+pub fn expand_tilde<P: AsRef<Path>>(path: P) -> Option<PathBuf> {
+    let path = path.as_ref();
+    let s = path.to_str()?;
+    if !s.starts_with('~') {
+        return Some(PathBuf::from(s));
+    }
+    let end = s.find('/').unwrap_or(s.len());
+    let name = &s[1..end];
+    let rest = &s[end..];
+    let home = if name.is_empty() {
+        invoking_user_home()?
+    } else {
+        get_user_by_name(name)?.home_dir().to_owned()
+    };
+    let mut expanded = PathBuf::from(home);
+    if rest.len() > 1 {
+        expanded.push(&rest[1..]);
+    }
+    Some(expanded)
+}
+
+/// The home directory of the user who invoked this process: `SUDO_USER`'s
+/// when running under sudo, otherwise `HOME` (which shells also use for a
+/// bare `~`, so explicit overrides are respected), with the real uid's
+/// passwd entry as the fallback when `HOME` is unset.
+fn invoking_user_home() -> Option<String> {
+    if let Ok(name) = env::var("SUDO_USER") {
+        if let Some(user) = get_user_by_name(&name) {
+            return Some(user.home_dir().to_owned());
+        }
+    }
+    if let Ok(home) = env::var("HOME") {
+        if !home.is_empty() {
+            return Some(home);
+        }
+    }
+    match get_user_by_uid(unsafe { getuid() }) {
+        Some(ref user) if !user.home_dir().is_empty() => Some(user.home_dir().to_owned()),
+        _ => None,
+    }
+}
+
+/// An `AllUsers` holding the enumeration lock for its lifetime.
+pub struct LockedAllUsers {
+    inner: AllUsers,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Iterator for LockedAllUsers {
+    type Item = User;
+
+    fn next(&mut self) -> Option<User> {
+        self.inner.next()
+    }
+}
+
+/// An `AllGroups` holding the enumeration lock for its lifetime.
+pub struct LockedAllGroups {
+    inner: AllGroups,
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl Iterator for LockedAllGroups {
+    type Item = Group;
+
+    fn next(&mut self) -> Option<Group> {
+        self.inner.next()
+    }
+}
+
+/// An iterator over every user present on the system.
+///
+/// This struct actually requires no fields, but has one hidden one to make it
+/// `unsafe` to create.
+pub struct AllUsers(());
+
+impl AllUsers {
+    /// Creates a new iterator over every user present on the system.
+    ///
+    /// ## Unsafety
+    ///
+    /// This constructor is marked as `unsafe`, which is odd for a crate
+    /// that's meant to be a safe interface. It *has* to be unsafe because
+    /// the underlying functions (`setpwent`/`getpwent`/`endpwent`) iterate
+    /// over global state, and if two instances were iterated at once, the
+    /// state could get corrupted. The caller must guarantee that only one
+    /// instance exists at a time.
+    #[deprecated(since = "0.8.1", note = "use the safe `all_users` function instead")]
+    pub unsafe fn new() -> AllUsers {
+        setpwent();
+        AllUsers(())
+    }
+}
+
+impl Drop for AllUsers {
+    fn drop(&mut self) {
+        unsafe { endpwent() };
+    }
+}
+
+impl Iterator for AllUsers {
+    type Item = User;
+
+    fn next(&mut self) -> Option<User> {
+        unsafe { passwd_to_user(getpwent()) }
+    }
+}
+
+/// An iterator over every group present on the system.
+///
+/// Like `AllUsers`, this has a hidden field so constructing it stays
+/// `unsafe`.
+pub struct AllGroups(());
+
+impl AllGroups {
+    /// Creates a new iterator over every group present on the system.
+    ///
+    /// ## Unsafety
+    ///
+    /// This constructor is `unsafe` for the same reason `AllUsers::new`
+    /// is: `setgrent`/`getgrent`/`endgrent` iterate over global state, so
+    /// the caller must guarantee that only one instance exists at a time.
+    #[deprecated(since = "0.8.1", note = "use the safe `all_groups` function instead")]
+    pub unsafe fn new() -> AllGroups {
+        setgrent();
+        AllGroups(())
+    }
+}
+
+impl Drop for AllGroups {
+    fn drop(&mut self) {
+        unsafe { endgrent() };
+    }
+}
+
+impl Iterator for AllGroups {
+    type Item = Group;
+
+    fn next(&mut self) -> Option<Group> {
+        unsafe { struct_to_group(getgrent()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use libc;
+
+    use super::{c_passwd, c_group};
+
+    /// Asserts a field sits at the same offset in our struct and libc's,
+    /// our stand-in for offset_of until the language grows one.
+    macro_rules! assert_same_offset {
+        ($ours:ty, $theirs:ty, $field:ident) => {{
+            let ours: $ours = unsafe { mem::zeroed() };
+            let theirs: $theirs = unsafe { mem::zeroed() };
+            let our_offset = &ours.$field as *const _ as usize - &ours as *const _ as usize;
+            let their_offset = &theirs.$field as *const _ as usize - &theirs as *const _ as usize;
+            assert_eq!(
+                our_offset, their_offset,
+                "offset mismatch for {}", stringify!($field)
+            );
+        }};
+    }
+
+    #[test]
+    fn passwd_layout_matches_libc() {
+        assert_eq!(mem::size_of::<c_passwd>(), mem::size_of::<libc::passwd>());
+        assert_same_offset!(c_passwd, libc::passwd, pw_name);
+        assert_same_offset!(c_passwd, libc::passwd, pw_passwd);
+        assert_same_offset!(c_passwd, libc::passwd, pw_uid);
+        assert_same_offset!(c_passwd, libc::passwd, pw_gid);
+        assert_same_offset!(c_passwd, libc::passwd, pw_gecos);
+        assert_same_offset!(c_passwd, libc::passwd, pw_dir);
+        assert_same_offset!(c_passwd, libc::passwd, pw_shell);
+    }
+
+    #[test]
+    fn expand_tilde_resolves_users_and_passes_others_through() {
+        use std::path::PathBuf;
+        use super::{expand_tilde, get_user_by_name};
+
+        assert_eq!(
+            expand_tilde("/etc/passwd"),
+            Some(PathBuf::from("/etc/passwd"))
+        );
+        assert!(expand_tilde("~").is_some());
+        assert_eq!(expand_tilde("~no-such-user-here/.kr"), None);
+
+        // Root exists everywhere the tests run; resolve its real home
+        // rather than assuming /root.
+        let root_home = get_user_by_name("root").unwrap().home_dir().to_owned();
+        assert_eq!(
+            expand_tilde("~root/.kr/krd-agent.sock"),
+            Some(PathBuf::from(root_home).join(".kr/krd-agent.sock"))
+        );
+    }
+
+    #[test]
+    fn uid_or_name_accepts_both_spellings() {
+        use super::{gid_or_name, uid_or_name};
+
+        assert_eq!(uid_or_name("root").unwrap().uid, 0);
+        assert_eq!(uid_or_name("0").unwrap().name(), "root");
+        assert!(uid_or_name("no-such-user-here").is_none());
+        assert!(uid_or_name("4294967296").is_none()); // overflows uid_t
+
+        assert_eq!(gid_or_name("0").unwrap().gid, 0);
+        assert!(gid_or_name("no-such-group-here").is_none());
+    }
+
+    #[test]
+    fn identity_agrees_with_the_individual_getters() {
+        use super::{get_current_uid, get_current_username, get_effective_uid, Identity};
+
+        let identity = Identity::current();
+        assert_eq!(identity.real_uid, get_current_uid());
+        assert_eq!(identity.effective_uid, get_effective_uid());
+        assert_eq!(identity.username, get_current_username());
+        assert_eq!(identity.is_root(), identity.effective_uid == 0);
+        if identity.is_sudo() {
+            assert!(identity.is_root());
+        }
+    }
+
+    #[cfg(feature = "serde_support")]
+    #[test]
+    fn users_and_groups_round_trip_through_json() {
+        extern crate serde_json;
+        use std::sync::Arc;
+        use super::{get_user_by_uid, Group, User};
+
+        let user = get_user_by_uid(0).unwrap();
+        let json = serde_json::to_string(&user).unwrap();
+        // The Arc wrapper is an implementation detail; diagnostics should
+        // see a plain "name" field.
+        assert!(json.contains("\"name\":\"root\""), "{}", json);
+        let back: User = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.uid, user.uid);
+        assert_eq!(back.name(), user.name());
+        assert_eq!(back.home_dir(), user.home_dir());
+
+        let group = Group {
+            gid: 99,
+            name_arc: Arc::new("fixture".to_owned()),
+            passwd: "*".to_owned(),
+            members: vec!["alice".to_owned(), "bob".to_owned()],
+        };
+        let json = serde_json::to_string(&group).unwrap();
+        let back: Group = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.gid, 99);
+        assert_eq!(back.name(), "fixture");
+        assert!(back.is_member("alice"));
+    }
+
+    #[test]
+    fn group_layout_matches_libc() {
+        assert_eq!(mem::size_of::<c_group>(), mem::size_of::<libc::group>());
+        assert_same_offset!(c_group, libc::group, gr_name);
+        assert_same_offset!(c_group, libc::group, gr_passwd);
+        assert_same_offset!(c_group, libc::group, gr_gid);
+        assert_same_offset!(c_group, libc::group, gr_mem);
+    }
+}
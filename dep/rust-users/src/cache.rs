@@ -0,0 +1,739 @@
+//! A cache for users and groups provided by the OS.
+//!
+//! Because the users table changes so infrequently, it's common for
+//! short-running programs to cache the results instead of calling `getpwuid`
+//! or `getgrgid` every time a name or ID lookup is needed.
+//!
+//! Long-running programs can either evict entries by hand
+//! (`invalidate_user`, `clear`) or construct the cache `with_ttl`, in which
+//! case entries older than the given duration are re-fetched on access.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use libc::{uid_t, gid_t};
+
+use base::{self, User, Group, all_users, all_groups};
+use {Users, Groups};
+
+/// A producer of user and group instances that caches every result.
+pub struct UsersCache {
+    users: BiMap<uid_t, User>,
+    groups: BiMap<gid_t, Group>,
+    uid: Cell<Option<uid_t>>,
+    gid: Cell<Option<gid_t>>,
+    euid: Cell<Option<uid_t>>,
+    egid: Cell<Option<gid_t>>,
+    /// When set, entries older than this are re-fetched on access.
+    ttl: Option<Duration>,
+}
+
+/// A cached lookup result (hit or confirmed miss) and when it was made.
+struct CacheEntry<V> {
+    value: V,
+    cached_at: Instant,
+}
+
+impl<V> CacheEntry<V> {
+    fn new(value: V) -> CacheEntry<V> {
+        CacheEntry {
+            value: value,
+            cached_at: Instant::now(),
+        }
+    }
+}
+
+/// A kinda-bi-directional `HashMap` that associates keys to values, and
+/// then strings back to keys.
+///
+/// It doesn't go the full route and offer *values*-to-keys lookup, because
+/// we only want to search based on usernames and group names. There wouldn't
+/// be much point offering a "User to uid" map, as the uid is present in the
+/// user struct!
+struct BiMap<K, V> {
+    forward: RefCell<HashMap<K, CacheEntry<Option<Arc<V>>>>>,
+    backward: RefCell<HashMap<Arc<String>, CacheEntry<Option<K>>>>,
+}
+
+impl<K, V> BiMap<K, V> {
+    fn new() -> BiMap<K, V> {
+        BiMap {
+            forward: RefCell::new(HashMap::new()),
+            backward: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl UsersCache {
+    /// Creates a new empty cache.
+    pub fn new() -> UsersCache {
+        UsersCache {
+            users: BiMap::new(),
+            groups: BiMap::new(),
+            uid: Cell::new(None),
+            gid: Cell::new(None),
+            euid: Cell::new(None),
+            egid: Cell::new(None),
+            ttl: None,
+        }
+    }
+
+    /// Creates a new empty cache whose entries expire `ttl` after they
+    /// were fetched, so a long-lived process eventually notices deleted
+    /// and renamed users.
+    pub fn with_ttl(ttl: Duration) -> UsersCache {
+        let mut cache = UsersCache::new();
+        cache.ttl = Some(ttl);
+        cache
+    }
+
+    /// Creates a new cache preloaded with all the users present on the
+    /// system.
+    ///
+    /// This used to be `unsafe`; enumeration is now serialized by the
+    /// crate's internal lock (see `base::all_users`).
+    pub fn with_all_users() -> UsersCache {
+        let cache = UsersCache::new();
+        for user in all_users() {
+            let uid = user.uid;
+            let user_arc = Arc::new(user);
+            cache.users.forward.borrow_mut()
+                .insert(uid, CacheEntry::new(Some(user_arc.clone())));
+            cache.users.backward.borrow_mut()
+                .insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+        }
+        cache
+    }
+
+    /// Creates a new cache preloaded with all the groups present on the
+    /// system.
+    pub fn with_all_groups() -> UsersCache {
+        let cache = UsersCache::new();
+        for group in all_groups() {
+            let gid = group.gid;
+            let group_arc = Arc::new(group);
+            cache.groups.forward.borrow_mut()
+                .insert(gid, CacheEntry::new(Some(group_arc.clone())));
+            cache.groups.backward.borrow_mut()
+                .insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+        }
+        cache
+    }
+
+    /// Drops the cached entry for the given user ID (and its name mapping),
+    /// forcing the next lookup to hit the OS again.
+    pub fn invalidate_user(&self, uid: uid_t) {
+        if let Some(entry) = self.users.forward.borrow_mut().remove(&uid) {
+            if let Some(user) = entry.value {
+                self.users.backward.borrow_mut().remove(&user.name_arc);
+            }
+        }
+    }
+
+    /// The `invalidate_user` equivalent for groups.
+    pub fn invalidate_group(&self, gid: gid_t) {
+        if let Some(entry) = self.groups.forward.borrow_mut().remove(&gid) {
+            if let Some(group) = entry.value {
+                self.groups.backward.borrow_mut().remove(&group.name_arc);
+            }
+        }
+    }
+
+    /// Drops every cached entry, including the current/effective IDs.
+    pub fn clear(&self) {
+        self.users.forward.borrow_mut().clear();
+        self.users.backward.borrow_mut().clear();
+        self.groups.forward.borrow_mut().clear();
+        self.groups.backward.borrow_mut().clear();
+        self.uid.set(None);
+        self.gid.set(None);
+        self.euid.set(None);
+        self.egid.set(None);
+    }
+
+    fn expired(&self, cached_at: Instant) -> bool {
+        match self.ttl {
+            Some(ttl) => cached_at.elapsed() > ttl,
+            None => false,
+        }
+    }
+
+    /// The error-reporting variant of `get_user_by_uid`: `Err` (e.g. an
+    /// NSS backend being down) is propagated without poisoning the cache,
+    /// so the next call retries the OS.
+    pub fn try_get_user_by_uid(&self, uid: uid_t) -> Result<Option<Arc<User>>, io::Error> {
+        let mut users_forward = self.users.forward.borrow_mut();
+        let stale_name = match users_forward.get(&uid) {
+            Some(entry) if !self.expired(entry.cached_at) => return Ok(entry.value.clone()),
+            Some(entry) => entry.value.as_ref().map(|u| u.name_arc.clone()),
+            None => None,
+        };
+        let fetched = base::try_get_user_by_uid(uid)?;
+        let mut users_backward = self.users.backward.borrow_mut();
+        if let Some(name) = stale_name {
+            users_backward.remove(&name);
+        }
+        match fetched {
+            Some(user) => {
+                let user_arc = Arc::new(user);
+                users_backward.insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                users_forward.insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                Ok(Some(user_arc))
+            }
+            None => {
+                users_forward.insert(uid, CacheEntry::new(None));
+                Ok(None)
+            }
+        }
+    }
+
+    /// The error-reporting variant of `get_user_by_name`.
+    pub fn try_get_user_by_name(&self, username: &str) -> Result<Option<Arc<User>>, io::Error> {
+        {
+            let users_backward = self.users.backward.borrow();
+            if let Some(entry) = users_backward.get(&username.to_owned()) {
+                if !self.expired(entry.cached_at) {
+                    return Ok(match entry.value {
+                        Some(uid) => self.users.forward.borrow()
+                            .get(&uid).and_then(|e| e.value.clone()),
+                        None => None,
+                    });
+                }
+            }
+        }
+        match base::try_get_user_by_name(username)? {
+            Some(user) => {
+                let uid = user.uid;
+                let user_arc = Arc::new(user);
+                self.users.forward.borrow_mut()
+                    .insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                self.users.backward.borrow_mut()
+                    .insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                Ok(Some(user_arc))
+            }
+            None => {
+                self.users.backward.borrow_mut()
+                    .insert(Arc::new(username.to_owned()), CacheEntry::new(None));
+                Ok(None)
+            }
+        }
+    }
+
+    /// The error-reporting variant of `get_group_by_gid`.
+    pub fn try_get_group_by_gid(&self, gid: gid_t) -> Result<Option<Arc<Group>>, io::Error> {
+        let mut groups_forward = self.groups.forward.borrow_mut();
+        let stale_name = match groups_forward.get(&gid) {
+            Some(entry) if !self.expired(entry.cached_at) => return Ok(entry.value.clone()),
+            Some(entry) => entry.value.as_ref().map(|g| g.name_arc.clone()),
+            None => None,
+        };
+        let fetched = base::try_get_group_by_gid(gid)?;
+        let mut groups_backward = self.groups.backward.borrow_mut();
+        if let Some(name) = stale_name {
+            groups_backward.remove(&name);
+        }
+        match fetched {
+            Some(group) => {
+                let group_arc = Arc::new(group);
+                groups_backward.insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                groups_forward.insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                Ok(Some(group_arc))
+            }
+            None => {
+                groups_forward.insert(gid, CacheEntry::new(None));
+                Ok(None)
+            }
+        }
+    }
+
+    /// The error-reporting variant of `get_group_by_name`.
+    pub fn try_get_group_by_name(&self, group_name: &str) -> Result<Option<Arc<Group>>, io::Error> {
+        {
+            let groups_backward = self.groups.backward.borrow();
+            if let Some(entry) = groups_backward.get(&group_name.to_owned()) {
+                if !self.expired(entry.cached_at) {
+                    return Ok(match entry.value {
+                        Some(gid) => self.groups.forward.borrow()
+                            .get(&gid).and_then(|e| e.value.clone()),
+                        None => None,
+                    });
+                }
+            }
+        }
+        match base::try_get_group_by_name(group_name)? {
+            Some(group) => {
+                let gid = group.gid;
+                let group_arc = Arc::new(group);
+                self.groups.forward.borrow_mut()
+                    .insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                self.groups.backward.borrow_mut()
+                    .insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                Ok(Some(group_arc))
+            }
+            None => {
+                self.groups.backward.borrow_mut()
+                    .insert(Arc::new(group_name.to_owned()), CacheEntry::new(None));
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl Users for UsersCache {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        let mut users_forward = self.users.forward.borrow_mut();
+        let stale_name = match users_forward.get(&uid) {
+            Some(entry) if !self.expired(entry.cached_at) => return entry.value.clone(),
+            // expired: remember the old name so its mapping can go too
+            Some(entry) => entry.value.as_ref().map(|u| u.name_arc.clone()),
+            None => None,
+        };
+        let mut users_backward = self.users.backward.borrow_mut();
+        if let Some(name) = stale_name {
+            users_backward.remove(&name);
+        }
+        match base::get_user_by_uid(uid) {
+            Some(user) => {
+                let user_arc = Arc::new(user);
+                users_backward.insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                users_forward.insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                Some(user_arc)
+            }
+            None => {
+                users_forward.insert(uid, CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_user_by_name(&self, username: &str) -> Option<Arc<User>> {
+        let mut users_backward = self.users.backward.borrow_mut();
+        let cached = match users_backward.get(&username.to_owned()) {
+            Some(entry) if !self.expired(entry.cached_at) => Some(entry.value),
+            _ => None,
+        };
+        match cached {
+            Some(Some(uid)) => {
+                self.users.forward.borrow().get(&uid).and_then(|e| e.value.clone())
+            }
+            Some(None) => None,
+            None => match base::get_user_by_name(username) {
+                Some(user) => {
+                    let uid = user.uid;
+                    let user_arc = Arc::new(user);
+                    self.users.forward.borrow_mut()
+                        .insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                    users_backward.insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                    Some(user_arc)
+                }
+                None => {
+                    users_backward.insert(Arc::new(username.to_owned()), CacheEntry::new(None));
+                    None
+                }
+            },
+        }
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        match self.uid.get() {
+            Some(uid) => uid,
+            None => {
+                let uid = base::get_current_uid();
+                self.uid.set(Some(uid));
+                uid
+            }
+        }
+    }
+
+    fn get_current_username(&self) -> Option<Arc<String>> {
+        let uid = self.get_current_uid();
+        self.get_user_by_uid(uid).map(|u| u.name_arc.clone())
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        match self.euid.get() {
+            Some(uid) => uid,
+            None => {
+                let uid = base::get_effective_uid();
+                self.euid.set(Some(uid));
+                uid
+            }
+        }
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<String>> {
+        let uid = self.get_effective_uid();
+        self.get_user_by_uid(uid).map(|u| u.name_arc.clone())
+    }
+
+    fn groups_of(&self, user: &User) -> Vec<Arc<Group>> {
+        // getgrouplist gives us full Group values, but re-resolving by gid
+        // keeps the membership results in (and consistent with) the cache
+        base::get_user_groups(user.name(), user.primary_group)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|group| self.get_group_by_gid(group.gid))
+            .collect()
+    }
+}
+
+impl Groups for UsersCache {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        let mut groups_forward = self.groups.forward.borrow_mut();
+        let stale_name = match groups_forward.get(&gid) {
+            Some(entry) if !self.expired(entry.cached_at) => return entry.value.clone(),
+            Some(entry) => entry.value.as_ref().map(|g| g.name_arc.clone()),
+            None => None,
+        };
+        let mut groups_backward = self.groups.backward.borrow_mut();
+        if let Some(name) = stale_name {
+            groups_backward.remove(&name);
+        }
+        match base::get_group_by_gid(gid) {
+            Some(group) => {
+                let group_arc = Arc::new(group);
+                groups_backward.insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                groups_forward.insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                Some(group_arc)
+            }
+            None => {
+                groups_forward.insert(gid, CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_group_by_name(&self, group_name: &str) -> Option<Arc<Group>> {
+        let mut groups_backward = self.groups.backward.borrow_mut();
+        let cached = match groups_backward.get(&group_name.to_owned()) {
+            Some(entry) if !self.expired(entry.cached_at) => Some(entry.value),
+            _ => None,
+        };
+        match cached {
+            Some(Some(gid)) => {
+                self.groups.forward.borrow().get(&gid).and_then(|e| e.value.clone())
+            }
+            Some(None) => None,
+            None => match base::get_group_by_name(group_name) {
+                Some(group) => {
+                    let gid = group.gid;
+                    let group_arc = Arc::new(group);
+                    self.groups.forward.borrow_mut()
+                        .insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                    groups_backward.insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                    Some(group_arc)
+                }
+                None => {
+                    groups_backward.insert(Arc::new(group_name.to_owned()), CacheEntry::new(None));
+                    None
+                }
+            },
+        }
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        match self.gid.get() {
+            Some(gid) => gid,
+            None => {
+                let gid = base::get_current_gid();
+                self.gid.set(Some(gid));
+                gid
+            }
+        }
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<String>> {
+        let gid = self.get_current_gid();
+        self.get_group_by_gid(gid).map(|g| g.name_arc.clone())
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        match self.egid.get() {
+            Some(gid) => gid,
+            None => {
+                let gid = base::get_effective_gid();
+                self.egid.set(Some(gid));
+                gid
+            }
+        }
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<String>> {
+        let gid = self.get_effective_gid();
+        self.get_group_by_gid(gid).map(|g| g.name_arc.clone())
+    }
+}
+
+/// A `Send + Sync` sibling of `UsersCache`, for caches shared between
+/// threads (e.g. inside a `lazy_static`). Lookups take a read lock on the
+/// hit path and a write lock only to record misses and re-fetches.
+pub struct ThreadSafeUsersCache {
+    users: SharedBiMap<uid_t, User>,
+    groups: SharedBiMap<gid_t, Group>,
+    uid: Mutex<Option<uid_t>>,
+    gid: Mutex<Option<gid_t>>,
+    euid: Mutex<Option<uid_t>>,
+    egid: Mutex<Option<gid_t>>,
+    ttl: Option<Duration>,
+}
+
+/// The `BiMap` layout again, under `RwLock` instead of `RefCell`.
+struct SharedBiMap<K, V> {
+    forward: RwLock<HashMap<K, CacheEntry<Option<Arc<V>>>>>,
+    backward: RwLock<HashMap<Arc<String>, CacheEntry<Option<K>>>>,
+}
+
+impl<K, V> SharedBiMap<K, V> {
+    fn new() -> SharedBiMap<K, V> {
+        SharedBiMap {
+            forward: RwLock::new(HashMap::new()),
+            backward: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl ThreadSafeUsersCache {
+    /// Creates a new empty cache.
+    pub fn new() -> ThreadSafeUsersCache {
+        ThreadSafeUsersCache {
+            users: SharedBiMap::new(),
+            groups: SharedBiMap::new(),
+            uid: Mutex::new(None),
+            gid: Mutex::new(None),
+            euid: Mutex::new(None),
+            egid: Mutex::new(None),
+            ttl: None,
+        }
+    }
+
+    /// Creates a new empty cache with the given entry time-to-live; see
+    /// `UsersCache::with_ttl`.
+    pub fn with_ttl(ttl: Duration) -> ThreadSafeUsersCache {
+        let mut cache = ThreadSafeUsersCache::new();
+        cache.ttl = Some(ttl);
+        cache
+    }
+
+    /// Drops the cached entry for the given user ID (and its name mapping),
+    /// forcing the next lookup to hit the OS again.
+    pub fn invalidate_user(&self, uid: uid_t) {
+        if let Some(entry) = self.users.forward.write().unwrap().remove(&uid) {
+            if let Some(user) = entry.value {
+                self.users.backward.write().unwrap().remove(&user.name_arc);
+            }
+        }
+    }
+
+    /// The `invalidate_user` equivalent for groups.
+    pub fn invalidate_group(&self, gid: gid_t) {
+        if let Some(entry) = self.groups.forward.write().unwrap().remove(&gid) {
+            if let Some(group) = entry.value {
+                self.groups.backward.write().unwrap().remove(&group.name_arc);
+            }
+        }
+    }
+
+    /// Drops every cached entry, including the current/effective IDs.
+    pub fn clear(&self) {
+        self.users.forward.write().unwrap().clear();
+        self.users.backward.write().unwrap().clear();
+        self.groups.forward.write().unwrap().clear();
+        self.groups.backward.write().unwrap().clear();
+        *self.uid.lock().unwrap() = None;
+        *self.gid.lock().unwrap() = None;
+        *self.euid.lock().unwrap() = None;
+        *self.egid.lock().unwrap() = None;
+    }
+
+    fn expired(&self, cached_at: Instant) -> bool {
+        match self.ttl {
+            Some(ttl) => cached_at.elapsed() > ttl,
+            None => false,
+        }
+    }
+
+    fn cached_id(&self, cell: &Mutex<Option<uid_t>>, fetch: fn() -> uid_t) -> uid_t {
+        let mut cached = cell.lock().unwrap();
+        match *cached {
+            Some(id) => id,
+            None => {
+                let id = fetch();
+                *cached = Some(id);
+                id
+            }
+        }
+    }
+}
+
+impl Users for ThreadSafeUsersCache {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        {
+            let users_forward = self.users.forward.read().unwrap();
+            if let Some(entry) = users_forward.get(&uid) {
+                if !self.expired(entry.cached_at) {
+                    return entry.value.clone();
+                }
+            }
+        }
+        let mut users_forward = self.users.forward.write().unwrap();
+        let mut users_backward = self.users.backward.write().unwrap();
+        if let Some(entry) = users_forward.remove(&uid) {
+            if let Some(stale) = entry.value {
+                users_backward.remove(&stale.name_arc);
+            }
+        }
+        match base::get_user_by_uid(uid) {
+            Some(user) => {
+                let user_arc = Arc::new(user);
+                users_backward.insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                users_forward.insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                Some(user_arc)
+            }
+            None => {
+                users_forward.insert(uid, CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_user_by_name(&self, username: &str) -> Option<Arc<User>> {
+        {
+            let users_backward = self.users.backward.read().unwrap();
+            if let Some(entry) = users_backward.get(&username.to_owned()) {
+                if !self.expired(entry.cached_at) {
+                    return match entry.value {
+                        Some(uid) => self.users.forward.read().unwrap()
+                            .get(&uid).and_then(|e| e.value.clone()),
+                        None => None,
+                    };
+                }
+            }
+        }
+        match base::get_user_by_name(username) {
+            Some(user) => {
+                let uid = user.uid;
+                let user_arc = Arc::new(user);
+                self.users.forward.write().unwrap()
+                    .insert(uid, CacheEntry::new(Some(user_arc.clone())));
+                self.users.backward.write().unwrap()
+                    .insert(user_arc.name_arc.clone(), CacheEntry::new(Some(uid)));
+                Some(user_arc)
+            }
+            None => {
+                self.users.backward.write().unwrap()
+                    .insert(Arc::new(username.to_owned()), CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.cached_id(&self.uid, base::get_current_uid)
+    }
+
+    fn get_current_username(&self) -> Option<Arc<String>> {
+        let uid = self.get_current_uid();
+        self.get_user_by_uid(uid).map(|u| u.name_arc.clone())
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.cached_id(&self.euid, base::get_effective_uid)
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<String>> {
+        let uid = self.get_effective_uid();
+        self.get_user_by_uid(uid).map(|u| u.name_arc.clone())
+    }
+
+    fn groups_of(&self, user: &User) -> Vec<Arc<Group>> {
+        base::get_user_groups(user.name(), user.primary_group)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|group| self.get_group_by_gid(group.gid))
+            .collect()
+    }
+}
+
+impl Groups for ThreadSafeUsersCache {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        {
+            let groups_forward = self.groups.forward.read().unwrap();
+            if let Some(entry) = groups_forward.get(&gid) {
+                if !self.expired(entry.cached_at) {
+                    return entry.value.clone();
+                }
+            }
+        }
+        let mut groups_forward = self.groups.forward.write().unwrap();
+        let mut groups_backward = self.groups.backward.write().unwrap();
+        if let Some(entry) = groups_forward.remove(&gid) {
+            if let Some(stale) = entry.value {
+                groups_backward.remove(&stale.name_arc);
+            }
+        }
+        match base::get_group_by_gid(gid) {
+            Some(group) => {
+                let group_arc = Arc::new(group);
+                groups_backward.insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                groups_forward.insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                Some(group_arc)
+            }
+            None => {
+                groups_forward.insert(gid, CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_group_by_name(&self, group_name: &str) -> Option<Arc<Group>> {
+        {
+            let groups_backward = self.groups.backward.read().unwrap();
+            if let Some(entry) = groups_backward.get(&group_name.to_owned()) {
+                if !self.expired(entry.cached_at) {
+                    return match entry.value {
+                        Some(gid) => self.groups.forward.read().unwrap()
+                            .get(&gid).and_then(|e| e.value.clone()),
+                        None => None,
+                    };
+                }
+            }
+        }
+        match base::get_group_by_name(group_name) {
+            Some(group) => {
+                let gid = group.gid;
+                let group_arc = Arc::new(group);
+                self.groups.forward.write().unwrap()
+                    .insert(gid, CacheEntry::new(Some(group_arc.clone())));
+                self.groups.backward.write().unwrap()
+                    .insert(group_arc.name_arc.clone(), CacheEntry::new(Some(gid)));
+                Some(group_arc)
+            }
+            None => {
+                self.groups.backward.write().unwrap()
+                    .insert(Arc::new(group_name.to_owned()), CacheEntry::new(None));
+                None
+            }
+        }
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.cached_id(&self.gid, base::get_current_gid)
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<String>> {
+        let gid = self.get_current_gid();
+        self.get_group_by_gid(gid).map(|g| g.name_arc.clone())
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.cached_id(&self.egid, base::get_effective_gid)
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<String>> {
+        let gid = self.get_effective_gid();
+        self.get_group_by_gid(gid).map(|g| g.name_arc.clone())
+    }
+}
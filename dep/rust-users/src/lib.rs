@@ -0,0 +1,96 @@
+//! This is a library for getting information on Unix users and groups. It
+//! supports getting the system users, and creating your own mock tables.
+//!
+//! In Unix, each user has an individual *user ID*, and each process has an
+//! *effective user ID* that says which user's permissions it is using.
+//! Furthermore, users can be the members of *groups*, which also have names
+//! and IDs. This functionality is exposed in libc, the C standard library,
+//! but as an unsafe Rust interface. This wrapper library provides a safe
+//! interface, using `User` and `Group` values instead of low-level pointers
+//! and strings. It also offers basic caching functionality.
+//!
+//! It does not (yet) offer *editing* functionality; the values returned are
+//! read-only.
+//!
+//! This is a vendored fork maintained for kr; upstream is
+//! <https://github.com/ogham/rust-users>.
+
+extern crate libc;
+#[cfg(feature = "serde_support")]
+#[macro_use]
+extern crate serde_derive;
+
+pub mod base;
+pub mod cache;
+pub mod mock;
+pub mod os;
+pub mod switch;
+pub mod validate;
+
+pub use base::{User, Group};
+pub use base::{get_user_by_uid, get_user_by_name};
+pub use base::{get_group_by_gid, get_group_by_name};
+pub use base::{try_get_user_by_uid, try_get_user_by_name};
+pub use base::{try_get_group_by_gid, try_get_group_by_name};
+pub use base::get_user_groups;
+pub use base::{get_current_uid, get_current_username};
+pub use base::{get_effective_uid, get_effective_username};
+pub use base::{get_current_gid, get_current_groupname};
+pub use base::{get_effective_gid, get_effective_groupname};
+pub use base::{AllUsers, AllGroups};
+pub use base::{all_users, all_groups, LockedAllUsers, LockedAllGroups};
+pub use base::{find_user_by_home_dir, users_with_login_shells, has_login_shell};
+pub use base::{uid_or_name, gid_or_name};
+pub use base::expand_tilde;
+pub use base::{Identity, ProcessIdentity};
+pub use cache::{UsersCache, ThreadSafeUsersCache};
+pub use validate::{is_system_user, is_valid_username, is_valid_groupname, SystemUidRange};
+
+use libc::{uid_t, gid_t};
+use std::sync::Arc;
+
+/// Trait for producers of users.
+pub trait Users {
+    /// Returns a user given their ID.
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>>;
+
+    /// Returns a user given their username.
+    fn get_user_by_name(&self, username: &str) -> Option<Arc<User>>;
+
+    /// Returns the user ID for the user running the process.
+    fn get_current_uid(&self) -> uid_t;
+
+    /// Returns the username of the user running the process.
+    fn get_current_username(&self) -> Option<Arc<String>>;
+
+    /// Returns the user ID for the effective user running the process.
+    fn get_effective_uid(&self) -> uid_t;
+
+    /// Returns the username of the effective user running the process.
+    fn get_effective_username(&self) -> Option<Arc<String>>;
+
+    /// Returns the groups the given user is a member of, including their
+    /// primary group.
+    fn groups_of(&self, user: &User) -> Vec<Arc<Group>>;
+}
+
+/// Trait for producers of groups.
+pub trait Groups {
+    /// Returns a group given its ID.
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>>;
+
+    /// Returns a group given its name.
+    fn get_group_by_name(&self, group_name: &str) -> Option<Arc<Group>>;
+
+    /// Returns the group ID for the user running the process.
+    fn get_current_gid(&self) -> gid_t;
+
+    /// Returns the group name of the user running the process.
+    fn get_current_groupname(&self) -> Option<Arc<String>>;
+
+    /// Returns the group ID for the effective user running the process.
+    fn get_effective_gid(&self) -> gid_t;
+
+    /// Returns the group name of the effective user running the process.
+    fn get_effective_groupname(&self) -> Option<Arc<String>>;
+}
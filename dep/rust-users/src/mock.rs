@@ -0,0 +1,176 @@
+//! Mockable users and groups.
+//!
+//! When you're testing your code, you don't want to actually rely on the
+//! system actually having various users and groups present - it's much better
+//! to have a custom set of users that are *guaranteed* to be there, so you can
+//! test against them.
+//!
+//! This module allows you to create these custom users and groups
+//! definitions, then access them using the same `Users` trait as in the main
+//! library, with few changes to your code.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use libc::{uid_t, gid_t};
+
+pub use base::{User, Group};
+pub use {Users, Groups};
+
+/// A mocking users table that you can add your own users and groups to.
+///
+/// Beyond plain tables, lookups can be scripted to fail: entries can be
+/// removed between calls to simulate users disappearing mid-run (SUDO_USER
+/// pointing at a deleted account), and the next N lookups can be forced to
+/// return nothing regardless of table contents, as if the lookup machinery
+/// itself were briefly unavailable.
+pub struct MockUsers {
+    users: HashMap<uid_t, Arc<User>>,
+    groups: HashMap<gid_t, Arc<Group>>,
+    uid: uid_t,
+    failing_user_lookups: Cell<u32>,
+    failing_group_lookups: Cell<u32>,
+}
+
+impl MockUsers {
+    /// Creates a new, empty mock users table.
+    pub fn with_current_uid(current_uid: uid_t) -> MockUsers {
+        MockUsers {
+            users: HashMap::new(),
+            groups: HashMap::new(),
+            uid: current_uid,
+            failing_user_lookups: Cell::new(0),
+            failing_group_lookups: Cell::new(0),
+        }
+    }
+
+    /// Adds a user to the users table.
+    pub fn add_user(&mut self, user: User) -> Option<Arc<User>> {
+        self.users.insert(user.uid, Arc::new(user))
+    }
+
+    /// Adds a group to the groups table.
+    pub fn add_group(&mut self, group: Group) -> Option<Arc<Group>> {
+        self.groups.insert(group.gid, Arc::new(group))
+    }
+
+    /// Removes a user from the table, as if the account were deleted,
+    /// returning the entry if it was present.
+    pub fn remove_user(&mut self, uid: uid_t) -> Option<Arc<User>> {
+        self.users.remove(&uid)
+    }
+
+    /// The `remove_user` equivalent for groups.
+    pub fn remove_group(&mut self, gid: gid_t) -> Option<Arc<Group>> {
+        self.groups.remove(&gid)
+    }
+
+    /// Makes the next `count` user lookups (by uid or name) return `None`
+    /// even for users present in the table.
+    pub fn fail_next_user_lookups(&self, count: u32) {
+        self.failing_user_lookups.set(count);
+    }
+
+    /// The `fail_next_user_lookups` equivalent for group lookups.
+    pub fn fail_next_group_lookups(&self, count: u32) {
+        self.failing_group_lookups.set(count);
+    }
+
+    /// Consumes one scripted failure, reporting whether this lookup
+    /// should fail.
+    fn user_lookup_fails(&self) -> bool {
+        let remaining = self.failing_user_lookups.get();
+        if remaining > 0 {
+            self.failing_user_lookups.set(remaining - 1);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn group_lookup_fails(&self) -> bool {
+        let remaining = self.failing_group_lookups.get();
+        if remaining > 0 {
+            self.failing_group_lookups.set(remaining - 1);
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Users for MockUsers {
+    fn get_user_by_uid(&self, uid: uid_t) -> Option<Arc<User>> {
+        if self.user_lookup_fails() {
+            return None;
+        }
+        self.users.get(&uid).cloned()
+    }
+
+    fn get_user_by_name(&self, username: &str) -> Option<Arc<User>> {
+        if self.user_lookup_fails() {
+            return None;
+        }
+        self.users.values().find(|u| u.name() == username).cloned()
+    }
+
+    fn get_current_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_current_username(&self) -> Option<Arc<String>> {
+        self.users.get(&self.uid).map(|u| u.name_arc.clone())
+    }
+
+    fn get_effective_uid(&self) -> uid_t {
+        self.uid
+    }
+
+    fn get_effective_username(&self) -> Option<Arc<String>> {
+        self.users.get(&self.uid).map(|u| u.name_arc.clone())
+    }
+
+    fn groups_of(&self, user: &User) -> Vec<Arc<Group>> {
+        self.groups
+            .values()
+            .filter(|g| {
+                g.gid == user.primary_group
+                    || g.members.iter().any(|member| member == user.name())
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+impl Groups for MockUsers {
+    fn get_group_by_gid(&self, gid: gid_t) -> Option<Arc<Group>> {
+        if self.group_lookup_fails() {
+            return None;
+        }
+        self.groups.get(&gid).cloned()
+    }
+
+    fn get_group_by_name(&self, group_name: &str) -> Option<Arc<Group>> {
+        if self.group_lookup_fails() {
+            return None;
+        }
+        self.groups.values().find(|g| g.name() == group_name).cloned()
+    }
+
+    fn get_current_gid(&self) -> gid_t {
+        self.uid
+    }
+
+    fn get_current_groupname(&self) -> Option<Arc<String>> {
+        self.groups.get(&self.uid).map(|g| g.name_arc.clone())
+    }
+
+    fn get_effective_gid(&self) -> gid_t {
+        self.uid
+    }
+
+    fn get_effective_groupname(&self) -> Option<Arc<String>> {
+        self.groups.get(&self.uid).map(|g| g.name_arc.clone())
+    }
+}
@@ -0,0 +1,150 @@
+//! Platform-specific extensions to the user and group structs.
+//!
+//! The `passwd` struct is one of the less standardised parts of the C
+//! library: the BSDs (including macOS) carry extra password-ageing fields
+//! that Linux does not. The extra fields for the current platform get
+//! collected into a `UserExtras` value held by every `User`.
+
+#[cfg(target_os = "linux")]
+pub use self::linux::UserExtras;
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly",
+          target_os = "openbsd", target_os = "netbsd"))]
+pub use self::bsd::UserExtras;
+
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use super::super::base::c_passwd;
+    use super::string_from;
+
+    /// Linux-specific fields carried on every `User`.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+    pub struct UserExtras {
+        /// The path to this user's home directory.
+        pub home_dir: String,
+
+        /// This user's login shell.
+        pub shell: String,
+
+        /// This user's GECOS field: the full name, optionally followed by
+        /// comma-separated contact sub-fields.
+        pub gecos: String,
+    }
+
+    impl UserExtras {
+        pub unsafe fn from_passwd(pw: &c_passwd) -> UserExtras {
+            UserExtras {
+                home_dir: string_from(pw.pw_dir),
+                shell: string_from(pw.pw_shell),
+                gecos: string_from(pw.pw_gecos),
+            }
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "dragonfly",
+          target_os = "openbsd", target_os = "netbsd"))]
+pub mod bsd {
+    use libc::time_t;
+
+    use super::super::base::c_passwd;
+    use super::string_from;
+
+    /// BSD and macOS-specific fields carried on every `User`.
+    #[derive(Clone, Debug)]
+    #[cfg_attr(feature = "serde_support", derive(Serialize, Deserialize))]
+    pub struct UserExtras {
+        /// The path to this user's home directory.
+        pub home_dir: String,
+
+        /// This user's login shell.
+        pub shell: String,
+
+        /// This user's GECOS field: the full name, optionally followed by
+        /// comma-separated contact sub-fields.
+        pub gecos: String,
+
+        /// This user's login class.
+        pub class: String,
+
+        /// The time by which this user's password must be changed, as a
+        /// Unix timestamp. Zero means no change is required.
+        pub change: time_t,
+
+        /// The time this user's account expires, as a Unix timestamp.
+        /// Zero means it never does.
+        pub expire: time_t,
+    }
+
+    impl UserExtras {
+        pub unsafe fn from_passwd(pw: &c_passwd) -> UserExtras {
+            UserExtras {
+                home_dir: string_from(pw.pw_dir),
+                shell: string_from(pw.pw_shell),
+                gecos: string_from(pw.pw_gecos),
+                class: string_from(pw.pw_class),
+                change: pw.pw_change,
+                expire: pw.pw_expire,
+            }
+        }
+
+        /// Returns this user's login class (the `pw_class` field).
+        pub fn pw_class(&self) -> &str {
+            &self.class
+        }
+
+        /// Whether this user's password change deadline has passed as of
+        /// `now`. A zero `pw_change` means no deadline is set.
+        pub fn password_change_due(&self, now: time_t) -> bool {
+            self.change != 0 && self.change <= now
+        }
+
+        /// Whether this account has expired as of `now`. A zero
+        /// `pw_expire` means the account never expires.
+        pub fn password_is_expired(&self, now: time_t) -> bool {
+            self.expire != 0 && self.expire <= now
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::UserExtras;
+
+        fn extras(change: ::libc::time_t, expire: ::libc::time_t) -> UserExtras {
+            UserExtras {
+                home_dir: "/home/x".to_owned(),
+                shell: "/bin/sh".to_owned(),
+                gecos: String::new(),
+                class: "staff".to_owned(),
+                change: change,
+                expire: expire,
+            }
+        }
+
+        #[test]
+        fn zero_means_no_deadline() {
+            let e = extras(0, 0);
+            assert!(!e.password_change_due(1_500_000_000));
+            assert!(!e.password_is_expired(1_500_000_000));
+            assert_eq!(e.pw_class(), "staff");
+        }
+
+        #[test]
+        fn deadlines_compare_against_now() {
+            let e = extras(1_000, 2_000);
+            assert!(!e.password_change_due(999));
+            assert!(e.password_change_due(1_000));
+            assert!(!e.password_is_expired(1_999));
+            assert!(e.password_is_expired(2_000));
+        }
+    }
+}
+
+pub(crate) unsafe fn string_from(p: *const ::libc::c_char) -> String {
+    use std::ffi::CStr;
+    if p.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(p).to_string_lossy().into_owned()
+    }
+}
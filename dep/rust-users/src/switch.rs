@@ -0,0 +1,317 @@
+//! Functions for switching the running process's user or group.
+
+use std::ffi::CString;
+use std::io;
+use std::ptr;
+
+use libc::{c_char, c_int, uid_t, gid_t, setuid, seteuid, setgid, setegid};
+#[cfg(not(target_os = "macos"))]
+use libc::size_t;
+
+use base::{get_effective_uid, get_effective_gid};
+
+// Darwin declares setgroups/initgroups over int rather than
+// size_t/gid_t.
+#[cfg(target_os = "macos")]
+#[allow(non_camel_case_types)]
+type setgroups_num = c_int;
+#[cfg(not(target_os = "macos"))]
+#[allow(non_camel_case_types)]
+type setgroups_num = size_t;
+
+#[cfg(target_os = "macos")]
+#[allow(non_camel_case_types)]
+type initgroups_gid = c_int;
+#[cfg(not(target_os = "macos"))]
+#[allow(non_camel_case_types)]
+type initgroups_gid = gid_t;
+
+extern "C" {
+    fn getgroups(size: c_int, list: *mut gid_t) -> c_int;
+    fn setgroups(size: setgroups_num, list: *const gid_t) -> c_int;
+    fn initgroups(user: *const c_char, group: initgroups_gid) -> c_int;
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+extern "C" {
+    fn setresuid(ruid: uid_t, euid: uid_t, suid: uid_t) -> c_int;
+    fn setresgid(rgid: gid_t, egid: gid_t, sgid: gid_t) -> c_int;
+    fn getresuid(ruid: *mut uid_t, euid: *mut uid_t, suid: *mut uid_t) -> c_int;
+    fn getresgid(rgid: *mut gid_t, egid: *mut gid_t, sgid: *mut gid_t) -> c_int;
+}
+
+/// Sets the **current user** for the running process to the one with the
+/// given user ID.
+pub fn set_current_uid(uid: uid_t) -> io::Result<()> {
+    match unsafe { setuid(uid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Sets the **effective user** for the running process to the one with the
+/// given user ID.
+pub fn set_effective_uid(uid: uid_t) -> io::Result<()> {
+    match unsafe { seteuid(uid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Sets the **current group** for the running process to the one with the
+/// given group ID.
+pub fn set_current_gid(gid: gid_t) -> io::Result<()> {
+    match unsafe { setgid(gid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Sets the **effective group** for the running process to the one with the
+/// given group ID.
+pub fn set_effective_gid(gid: gid_t) -> io::Result<()> {
+    match unsafe { setegid(gid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Guard returned by `switch_user_group`; switches back to the previous
+/// effective user and group when dropped.
+pub struct SwitchUserGuard {
+    uid: uid_t,
+    gid: gid_t,
+}
+
+impl Drop for SwitchUserGuard {
+    fn drop(&mut self) {
+        // Panic on error here, as failing to set values back is a possible
+        // security breach.
+        set_effective_uid(self.uid).unwrap();
+        set_effective_gid(self.gid).unwrap();
+    }
+}
+
+/// Sets the **effective user and group** for the running process, until the
+/// returned guard is dropped, at which point they are switched back.
+pub fn switch_user_group(uid: uid_t, gid: gid_t) -> io::Result<SwitchUserGuard> {
+    let guard = SwitchUserGuard {
+        uid: get_effective_uid(),
+        gid: get_effective_gid(),
+    };
+    set_effective_gid(gid)?;
+    set_effective_uid(uid)?;
+    Ok(guard)
+}
+
+/// Returns the process's supplementary group list.
+pub fn get_supplementary_groups() -> io::Result<Vec<gid_t>> {
+    let count = unsafe { getgroups(0, ptr::null_mut()) };
+    if count < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut groups = vec![0 as gid_t; count as usize];
+    let written = unsafe { getgroups(count, groups.as_mut_ptr()) };
+    if written < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    groups.truncate(written as usize);
+    Ok(groups)
+}
+
+/// Replaces the process's supplementary group list. Requires privileges.
+pub fn set_supplementary_groups(groups: &[gid_t]) -> io::Result<()> {
+    match unsafe { setgroups(groups.len() as setgroups_num, groups.as_ptr()) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Sets the supplementary group list to the groups `username` is a member
+/// of, plus `gid`, per initgroups(3).
+pub fn init_supplementary_groups(username: &str, gid: gid_t) -> io::Result<()> {
+    let username_c = CString::new(username).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "username contains NUL")
+    })?;
+    match unsafe { initgroups(username_c.as_ptr(), gid as initgroups_gid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Guard returned by `switch_user_group_full`; switches the effective
+/// user, group and the supplementary group list back when dropped.
+pub struct SwitchUserGroupFullGuard {
+    uid: uid_t,
+    gid: gid_t,
+    groups: Vec<gid_t>,
+}
+
+impl Drop for SwitchUserGroupFullGuard {
+    fn drop(&mut self) {
+        // Panic on error here, as failing to set values back is a possible
+        // security breach. The effective uid comes back first: restoring
+        // the group list needs the original privileges.
+        set_effective_uid(self.uid).unwrap();
+        set_effective_gid(self.gid).unwrap();
+        set_supplementary_groups(&self.groups).unwrap();
+    }
+}
+
+/// Like `switch_user_group`, but also replaces the supplementary group
+/// list with `username`'s groups instead of leaving the caller's in
+/// place — supplementary groups are part of the privilege being dropped.
+pub fn switch_user_group_full(
+    uid: uid_t,
+    gid: gid_t,
+    username: &str,
+) -> io::Result<SwitchUserGroupFullGuard> {
+    let guard = SwitchUserGroupFullGuard {
+        uid: get_effective_uid(),
+        gid: get_effective_gid(),
+        groups: get_supplementary_groups()?,
+    };
+    init_supplementary_groups(username, gid)?;
+    set_effective_gid(gid)?;
+    set_effective_uid(uid)?;
+    Ok(guard)
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+fn set_all_gids(gid: gid_t) -> io::Result<()> {
+    match unsafe { setresgid(gid, gid, gid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+fn set_all_uids(uid: uid_t) -> io::Result<()> {
+    match unsafe { setresuid(uid, uid, uid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+// Without setres*, fall back to setgid/setuid, which set all three IDs
+// when the caller is privileged.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+fn set_all_gids(gid: gid_t) -> io::Result<()> {
+    set_current_gid(gid)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+fn set_all_uids(uid: uid_t) -> io::Result<()> {
+    set_current_uid(uid)
+}
+
+/// Irreversibly drops privileges to the given user and group: clears the
+/// supplementary group list, then sets the real, effective *and saved*
+/// IDs (setresgid/setresuid where available) so the process cannot switch
+/// back. As a belt-and-braces check, the old effective uid is re-tried
+/// and an error returned if the drop turns out to be revertible.
+pub fn drop_privileges(uid: uid_t, gid: gid_t) -> io::Result<()> {
+    let old_euid = get_effective_uid();
+    set_supplementary_groups(&[gid])?;
+    set_all_gids(gid)?;
+    set_all_uids(uid)?;
+    if uid != old_euid && unsafe { seteuid(old_euid) } == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            "privilege drop could be reverted",
+        ));
+    }
+    Ok(())
+}
+
+/// The real, effective and saved user IDs of the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResUids {
+    pub real: uid_t,
+    pub effective: uid_t,
+    pub saved: uid_t,
+}
+
+/// The real, effective and saved group IDs of the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResGids {
+    pub real: gid_t,
+    pub effective: gid_t,
+    pub saved: gid_t,
+}
+
+/// The (uid_t)-1 "leave unchanged" argument to setresuid/setresgid.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+const ID_UNCHANGED: uid_t = !0;
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+fn unsupported(call: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Other,
+        format!(
+            "{} is not available on this platform; macOS exposes no \
+             saved-ID syscalls, so use the seteuid/setegid swapping in \
+             switch_user_group instead",
+            call
+        ),
+    )
+}
+
+/// Returns the process's real, effective and saved user IDs.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+pub fn get_res_uids() -> io::Result<ResUids> {
+    let mut ids = ResUids { real: 0, effective: 0, saved: 0 };
+    match unsafe { getresuid(&mut ids.real, &mut ids.effective, &mut ids.saved) } {
+        0 => Ok(ids),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+pub fn get_res_uids() -> io::Result<ResUids> {
+    Err(unsupported("getresuid"))
+}
+
+/// Returns the process's real, effective and saved group IDs.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+pub fn get_res_gids() -> io::Result<ResGids> {
+    let mut ids = ResGids { real: 0, effective: 0, saved: 0 };
+    match unsafe { getresgid(&mut ids.real, &mut ids.effective, &mut ids.saved) } {
+        0 => Ok(ids),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+pub fn get_res_gids() -> io::Result<ResGids> {
+    Err(unsupported("getresgid"))
+}
+
+/// Sets only the **saved** user ID, leaving the real and effective IDs in
+/// place — the retain half of a retain-and-drop pattern.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+pub fn set_saved_uid(uid: uid_t) -> io::Result<()> {
+    match unsafe { setresuid(ID_UNCHANGED, ID_UNCHANGED, uid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+pub fn set_saved_uid(_uid: uid_t) -> io::Result<()> {
+    Err(unsupported("setresuid"))
+}
+
+/// Sets only the **saved** group ID; see `set_saved_uid`.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly"))]
+pub fn set_saved_gid(gid: gid_t) -> io::Result<()> {
+    match unsafe { setresgid(ID_UNCHANGED as gid_t, ID_UNCHANGED as gid_t, gid) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd", target_os = "dragonfly")))]
+pub fn set_saved_gid(_gid: gid_t) -> io::Result<()> {
+    Err(unsupported("setresgid"))
+}
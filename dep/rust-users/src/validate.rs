@@ -0,0 +1,103 @@
+//! Validation helpers for user IDs and names.
+//!
+//! kr's setup code needs to refuse some accounts — agent sockets should
+//! not be installed for system users, and usernames read from the
+//! environment (SUDO_USER) should be checked before being fed back into
+//! lookups or paths.
+
+use libc::uid_t;
+
+/// The range of user IDs treated as system accounts on this platform.
+///
+/// Linux distributions reserve uids below 1000 for system accounts
+/// (Debian policy; Red Hat moved from 500 to 1000), while macOS uses 500
+/// as the first human uid.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SystemUidRange {
+    pub min: uid_t,
+    pub max: uid_t,
+}
+
+impl SystemUidRange {
+    /// This platform's conventional system-account range.
+    pub fn platform_default() -> SystemUidRange {
+        SystemUidRange {
+            min: 0,
+            max: FIRST_HUMAN_UID - 1,
+        }
+    }
+
+    pub fn contains(&self, uid: uid_t) -> bool {
+        uid >= self.min && uid <= self.max
+    }
+}
+
+#[cfg(target_os = "macos")]
+const FIRST_HUMAN_UID: uid_t = 500;
+#[cfg(not(target_os = "macos"))]
+const FIRST_HUMAN_UID: uid_t = 1000;
+
+/// Whether the given uid belongs to a system account under this
+/// platform's default range.
+pub fn is_system_user(uid: uid_t) -> bool {
+    SystemUidRange::platform_default().contains(uid)
+}
+
+/// Whether the given name is a valid username under the POSIX portable
+/// filename character set rules used by useradd: starts with a lowercase
+/// letter or underscore, continues with lowercase letters, digits,
+/// underscores or dashes, optionally ends with `$` (Samba machine
+/// accounts), and fits in LOGIN_NAME_MAX-ish 32 bytes.
+pub fn is_valid_username(name: &str) -> bool {
+    if name.is_empty() || name.len() > 32 {
+        return false;
+    }
+    let bytes = name.as_bytes();
+    match bytes[0] {
+        b'a'...b'z' | b'_' => {}
+        _ => return false,
+    }
+    let rest = &bytes[1..];
+    let rest = if rest.last() == Some(&b'$') {
+        &rest[..rest.len() - 1]
+    } else {
+        rest
+    };
+    rest.iter().all(|&b| match b {
+        b'a'...b'z' | b'0'...b'9' | b'_' | b'-' => true,
+        _ => false,
+    })
+}
+
+/// Group names follow the same rules as usernames.
+pub fn is_valid_groupname(name: &str) -> bool {
+    is_valid_username(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usernames() {
+        assert!(is_valid_username("alice"));
+        assert!(is_valid_username("_krd"));
+        assert!(is_valid_username("jane-doe"));
+        assert!(is_valid_username("machine$"));
+        assert!(!is_valid_username(""));
+        assert!(!is_valid_username("Alice"));
+        assert!(!is_valid_username("1stuser"));
+        assert!(!is_valid_username("alice bob"));
+        assert!(!is_valid_username("../../etc/passwd"));
+        assert!(!is_valid_username("abcdefghijklmnopqrstuvwxyz0123456"));
+    }
+
+    #[test]
+    fn system_uids() {
+        assert!(is_system_user(0));
+        assert!(!is_system_user(60000));
+        let range = SystemUidRange { min: 0, max: 99 };
+        assert!(range.contains(99));
+        assert!(!range.contains(100));
+    }
+}
@@ -0,0 +1,247 @@
+//! Bounded de-duplication of notification lines.
+//!
+//! The original tailer kept every printed message in a HashSet for the
+//! lifetime of the host process. This keeps only the most recent
+//! window, so memory stays flat over arbitrarily long notification
+//! streams while duplicates within the window are still suppressed.
+//!
+//! Messages are compared after normalization: krd re-logs an identical
+//! approval prompt for every IdentityFile ssh retries, and the copies
+//! differ only in embedded timestamps and request IDs, which exact
+//! matching treats as four distinct prompts. Suppression is also bounded
+//! in time — the same prompt for a *new* approval minutes later should
+//! show again.
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::time::{Duration, Instant};
+
+const DEFAULT_CAPACITY: usize = 128;
+
+/// How long a shown message keeps suppressing look-alikes, in seconds.
+/// Long enough to cover ssh walking its IdentityFile list, short enough
+/// that the next approval's prompt is not eaten.
+const DEFAULT_WINDOW_SECS: u64 = 10;
+
+/// Strips the parts of a message that vary between retries of the same
+/// prompt, so look-alikes compare equal:
+///
+/// * leading Go-log (`2019/05/01 12:33:05`) and syslog (`May  1
+///   12:33:05`) timestamps, plus any other `HH:MM:SS` token;
+/// * request IDs: long unbroken runs of base64/hex-looking characters.
+pub fn normalize(message: &str) -> String {
+    let mut tokens = Vec::new();
+    let mut words = message.split_whitespace().peekable();
+    while let Some(token) = words.next() {
+        if looks_like_month(token) {
+            // the day-of-month syslog writes after the month is part of
+            // the timestamp too; keeping it would leave syslog- and
+            // Go-prefixed copies of the same prompt looking different
+            if words.peek().map_or(false, |day| looks_like_day(day)) {
+                words.next();
+            }
+            continue;
+        }
+        if looks_like_time(token) || looks_like_date(token) {
+            continue;
+        }
+        if looks_like_id(token) {
+            tokens.push("<id>");
+        } else {
+            tokens.push(token);
+        }
+    }
+    tokens.join(" ")
+}
+
+/// `HH:MM:SS`, optionally with fractional seconds.
+fn looks_like_time(token: &str) -> bool {
+    let mut colons = 0;
+    for c in token.chars() {
+        match c {
+            '0'...'9' | '.' => {}
+            ':' => colons += 1,
+            _ => return false,
+        }
+    }
+    colons == 2 && token.len() >= 7
+}
+
+/// A syslog month abbreviation, always followed by a day-of-month that
+/// `normalize` strips along with it.
+fn looks_like_month(token: &str) -> bool {
+    const MONTHS: [&'static str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    MONTHS.contains(&token)
+}
+
+/// A syslog day-of-month: one or two digits.
+fn looks_like_day(token: &str) -> bool {
+    !token.is_empty() && token.len() <= 2 && token.chars().all(|c| c.is_ascii_digit())
+}
+
+/// `YYYY/MM/DD` or `YYYY-MM-DD`.
+fn looks_like_date(token: &str) -> bool {
+    token.len() == 10
+        && token.chars().enumerate().all(|(i, c)| match i {
+            4 | 7 => c == '/' || c == '-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+/// At least 16 characters drawn entirely from the base64/hex/UUID
+/// alphabet, with at least one digit so ordinary long words survive.
+fn looks_like_id(token: &str) -> bool {
+    let token = token.trim_matches(|c| c == '(' || c == ')' || c == '[' || c == ']' || c == ':');
+    token.len() >= 16
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/' || c == '=' || c == '-' || c == '_')
+}
+
+/// Remembers the most recent `capacity` distinct messages (after
+/// normalization) in insertion order, forgetting the oldest when full
+/// and letting entries re-fire once `window` has passed.
+pub struct RecentSet {
+    capacity: usize,
+    window: Option<Duration>,
+    order: VecDeque<String>,
+    seen: HashMap<String, Instant>,
+}
+
+impl RecentSet {
+    pub fn new(capacity: usize, window: Option<Duration>) -> RecentSet {
+        RecentSet {
+            capacity: if capacity == 0 { 1 } else { capacity },
+            window: window,
+            order: VecDeque::new(),
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Capacity from `KR_NOTIFY_DEDUP_CAPACITY` (default 128) and window
+    /// from `KR_NOTIFY_DEDUP_WINDOW` seconds (default 10; 0 suppresses
+    /// duplicates for as long as they stay within capacity, the old
+    /// behavior).
+    pub fn from_env() -> RecentSet {
+        let capacity = env::var("KR_NOTIFY_DEDUP_CAPACITY")
+            .ok()
+            .and_then(|capacity| capacity.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_CAPACITY);
+        let window_secs = env::var("KR_NOTIFY_DEDUP_WINDOW")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_WINDOW_SECS);
+        let window = if window_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(window_secs))
+        };
+        RecentSet::new(capacity, window)
+    }
+
+    /// Records `message`. Returns false if a normalized look-alike was
+    /// already shown within the window, i.e. a duplicate that should not
+    /// be shown again.
+    pub fn insert(&mut self, message: &str) -> bool {
+        let key = normalize(message);
+        if let Some(&shown) = self.seen.get(&key) {
+            match self.window {
+                Some(window) if shown.elapsed() >= window => {
+                    // the window has passed; show it again and restart
+                    // the clock, keeping its slot in the order queue
+                    self.seen.insert(key, Instant::now());
+                    return true;
+                }
+                _ => return false,
+            }
+        }
+        if self.order.len() == self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.order.push_back(key.clone());
+        self.seen.insert(key, Instant::now());
+        true
+    }
+
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn suppresses_duplicates() {
+        let mut set = RecentSet::new(4, None);
+        assert!(set.insert("a"));
+        assert!(!set.insert("a"));
+        assert!(set.insert("b"));
+        assert!(!set.insert("b"));
+    }
+
+    #[test]
+    fn memory_stays_flat() {
+        let mut set = RecentSet::new(16, None);
+        for i in 0..10_000 {
+            assert!(set.insert(&format!("message {}", i)));
+            assert!(set.len() <= 16);
+        }
+        assert_eq!(set.len(), 16);
+    }
+
+    #[test]
+    fn evicted_messages_show_again() {
+        let mut set = RecentSet::new(2, None);
+        assert!(set.insert("a"));
+        assert!(set.insert("b"));
+        assert!(set.insert("c")); // evicts "a"
+        assert!(set.insert("a"));
+    }
+
+    #[test]
+    fn retries_differing_only_in_noise_are_duplicates() {
+        let mut set = RecentSet::new(8, None);
+        assert!(set.insert(
+            "2019/05/01 12:33:05 approval request Zm9vYmFyYmF6cXV4MTIz= from server"
+        ));
+        assert!(!set.insert(
+            "2019/05/01 12:33:07 approval request cXV4YmF6YmFyZm9vNDU2= from server"
+        ));
+        assert!(!set.insert(
+            "May  1 12:33:09 approval request 0123deadbeef4567cafe from server"
+        ));
+        // a genuinely different message still shows
+        assert!(set.insert("2019/05/01 12:33:10 request approved"));
+    }
+
+    #[test]
+    fn normalize_keeps_ordinary_words() {
+        assert_eq!(
+            normalize("waiting for approval on phone…"),
+            "waiting for approval on phone…"
+        );
+        // long words without digits are not mistaken for request IDs
+        assert_eq!(
+            normalize("internationalization enabled"),
+            "internationalization enabled"
+        );
+    }
+
+    #[test]
+    fn window_expiry_lets_a_prompt_fire_again() {
+        let mut set = RecentSet::new(4, Some(Duration::from_millis(30)));
+        assert!(set.insert("waiting for approval"));
+        assert!(!set.insert("waiting for approval"));
+        thread::sleep(Duration::from_millis(40));
+        assert!(set.insert("waiting for approval"));
+        assert!(!set.insert("waiting for approval"));
+    }
+}
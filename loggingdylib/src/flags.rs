@@ -0,0 +1,60 @@
+//! Environment flags shared by every artifact embedding kr logging.
+//!
+//! The notify dylib and the PKCS#11 shim each grew ad-hoc environment
+//! checks over time; defining the names and the parsing in one place
+//! keeps a flag meaning the same thing no matter which library reads it.
+
+use std::env;
+
+/// Suppress all output on the host's stderr.
+pub const NO_STDERR_VAR: &'static str = "KR_NO_STDERR";
+
+/// Keep stderr verbatim: no silencing and no noise filtering.
+pub const DEBUG_VAR: &'static str = "KR_DEBUG";
+
+/// Leave `SSH_AUTH_SOCK` and agent redirection untouched.
+pub const SKIP_AGENT_REDIRECT_VAR: &'static str = "KR_SKIP_AGENT_REDIRECT";
+
+/// A flag is on when its variable is set to anything except "", "0" or
+/// "false". Bare `KR_NO_STDERR=` in a wrapper script therefore does not
+/// accidentally silence anything.
+fn flag(name: &str) -> bool {
+    match env::var(name) {
+        Ok(value) => !value.is_empty() && value != "0" && value != "false",
+        Err(_) => false,
+    }
+}
+
+pub fn no_stderr() -> bool {
+    flag(NO_STDERR_VAR)
+}
+
+pub fn debug() -> bool {
+    flag(DEBUG_VAR)
+}
+
+pub fn skip_agent_redirect() -> bool {
+    flag(SKIP_AGENT_REDIRECT_VAR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn parses_truthiness() {
+        // A private name so parallel tests cannot race on it.
+        let name = "KR_TEST_FLAG_TRUTHINESS";
+        assert!(!flag(name));
+        for value in &["0", "false", ""] {
+            env::set_var(name, value);
+            assert!(!flag(name));
+        }
+        for value in &["1", "true", "yes"] {
+            env::set_var(name, value);
+            assert!(flag(name));
+        }
+        env::remove_var(name);
+    }
+}
@@ -0,0 +1,601 @@
+//! Krypton SSH notification relay.
+//!
+//! This dylib is injected into `ssh` (DYLD_INSERT_LIBRARIES on macOS,
+//! LD_PRELOAD on Linux) so the user sees krd's pairing and approval
+//! status while the connection blocks on the phone. `Init` tails
+//! ~/.kr/krd-notify.log and relays fresh lines to stderr until the SSH
+//! session starts producing output of its own, at which point login has
+//! succeeded and status lines would only get in the way.
+
+extern crate libc;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate syslog;
+extern crate users;
+
+mod dedup;
+pub mod flags;
+mod protocol;
+mod ratelimit;
+pub mod status;
+mod tailer;
+mod watch;
+
+use std::env;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering, ATOMIC_BOOL_INIT};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dedup::RecentSet;
+use ratelimit::RateLimiter;
+use tailer::Tailer;
+use watch::Watcher;
+
+/// Set once the host process has produced stdout output (login
+/// finished); the tailer stops relaying notifications after that.
+static STDOUT_SEEN: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Set by `Deinit` to stop the relay and pump threads.
+static SHUTDOWN: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// The dup of the real stdout saved before the detection pipe was
+/// interposed, or -1. Held as an atomic so `Deinit` and the pump thread
+/// can race to restore/close it exactly once.
+static SAVED_STDOUT: AtomicIsize = AtomicIsize::new(-1);
+
+/// The read end of the detection pipe, or -1.
+static PIPE_READ: AtomicIsize = AtomicIsize::new(-1);
+
+/// The invoking user's `~/.kr`.
+///
+/// Under `sudo ssh`, `HOME` points at root's home while krd and its
+/// notify log live with the invoking user, so `SUDO_USER` takes
+/// precedence — `users::Identity` applies the same resolution kr-pkcs11
+/// uses for its paths.
+fn kr_dir() -> Option<PathBuf> {
+    users::Identity::current().home_dir.map(|home| home.join(".kr"))
+}
+
+fn notify_log_path() -> Option<PathBuf> {
+    kr_dir().map(|dir| dir.join("krd-notify.log"))
+}
+
+fn notify_sock_path() -> Option<PathBuf> {
+    kr_dir().map(|dir| dir.join("krd-notify.sock"))
+}
+
+/// The identifier krd tags lines with when a notification is meant for a
+/// single session: the session UUID exported by the kr wrapper if there
+/// is one, otherwise our own pid (which krd learns from the requesting
+/// agent connection).
+fn session_id() -> String {
+    match env::var("KR_SESSION_ID") {
+        Ok(id) => id,
+        Err(_) => unsafe { libc::getpid() }.to_string(),
+    }
+}
+
+/// Reassures the user while an approval request is pending on the
+/// phone, so a blocked SSH connection does not look hung. After
+/// `KR_NOTIFY_HEARTBEAT` seconds (default 10; 0 disables) without an
+/// answer, prints elapsed-time status lines at that interval.
+struct Heartbeat {
+    interval: Option<Duration>,
+    waiting_since: Option<Instant>,
+    last_beat: Option<Instant>,
+}
+
+impl Heartbeat {
+    fn from_env() -> Heartbeat {
+        let secs = env::var("KR_NOTIFY_HEARTBEAT")
+            .ok()
+            .and_then(|secs| secs.parse::<u64>().ok())
+            .unwrap_or(10);
+        Heartbeat {
+            interval: if secs == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(secs))
+            },
+            waiting_since: None,
+            last_beat: None,
+        }
+    }
+
+    /// Tracks request/answer notifications as they are emitted.
+    fn observe(&mut self, note: &protocol::Notification) {
+        match note.kind {
+            protocol::Kind::ApprovalRequest => {
+                self.waiting_since = Some(Instant::now());
+                self.last_beat = None;
+            }
+            _ => self.waiting_since = None,
+        }
+    }
+
+    /// Whether the relay loop should wake up periodically to tick.
+    fn waiting(&self) -> bool {
+        self.interval.is_some() && self.waiting_since.is_some()
+    }
+
+    /// Prints a heartbeat line if one is due.
+    fn tick(&mut self) {
+        // heartbeats respect the same silencing as notifications
+        // (explicit silent level, or a non-interactive stderr)
+        if protocol::Level::from_env() == protocol::Level::Silent {
+            return;
+        }
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let since = match self.waiting_since {
+            Some(since) => since,
+            None => return,
+        };
+        let due = match self.last_beat {
+            Some(beat) => beat.elapsed() >= interval,
+            None => since.elapsed() >= interval,
+        };
+        if !due {
+            return;
+        }
+        self.last_beat = Some(Instant::now());
+        let line = format!(
+            "still waiting for Krypton approval ({}s elapsed)…",
+            since.elapsed().as_secs()
+        );
+        if protocol::use_color() {
+            let _ = writeln!(io::stderr(), "\x1b[33m⏳ {}\x1b[0m", line);
+        } else {
+            let _ = writeln!(io::stderr(), "⏳ {}", line);
+        }
+    }
+}
+
+/// `KR_NOTIFY_TIMEOUT`: stop relaying after this many seconds without a
+/// notification, so the threads do not linger in long-lived hosts.
+fn notify_timeout() -> Option<Duration> {
+    env::var("KR_NOTIFY_TIMEOUT")
+        .ok()
+        .and_then(|secs| secs.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+/// Returns the printable message if `line` belongs to `session`.
+///
+/// Tagged lines look like `[<session>] message`; lines for other
+/// sessions are dropped so concurrent SSH processes do not see each
+/// other's approval status. Untagged lines predate session scoping and
+/// are broadcast to everyone.
+fn session_line<'a>(line: &'a str, session: &str) -> Option<&'a str> {
+    if !line.starts_with('[') {
+        return Some(line);
+    }
+    let close = match line.find(']') {
+        Some(close) => close,
+        None => return Some(line),
+    };
+    if &line[1..close] == session {
+        Some(line[close + 1..].trim_start())
+    } else {
+        None
+    }
+}
+
+/// Entry point, resolved by name from the injecting wrapper. Both the
+/// standalone notify dylib and kr-pkcs11 link this crate, so fixes to
+/// the relay land in both artifacts; features trim the parts a host
+/// does not want (a PKCS#11 module must not touch stdout).
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn Init() {
+    #[cfg(feature = "stderr-optout")]
+    {
+        if flags::no_stderr() {
+            return;
+        }
+    }
+    #[cfg(feature = "stdout-detection")]
+    start_stdout_detection();
+    thread::spawn(run_relay);
+}
+
+/// Tears the relay down: signals the threads, restores the real stdout
+/// over the detection pipe and closes the remaining pipe fd. Safe to
+/// call more than once; also run automatically when the library is
+/// unloaded.
+#[no_mangle]
+#[allow(non_snake_case)]
+pub extern "C" fn Deinit() {
+    SHUTDOWN.store(true, Ordering::SeqCst);
+    let saved = SAVED_STDOUT.swap(-1, Ordering::SeqCst) as libc::c_int;
+    if saved >= 0 {
+        unsafe {
+            libc::dup2(saved, libc::STDOUT_FILENO);
+            libc::close(saved);
+        }
+    }
+    // Restoring stdout closed the pipe's write end, so a pump thread
+    // blocked in read() wakes up with EOF; this close is for the case
+    // where the pump already exited or was never started.
+    close_pipe_read();
+}
+
+/// C API: the outcome of the most recent approval exchange, as a
+/// `status::Status` discriminant (0 unknown, 1 pending, 2 approved,
+/// 3 rejected). Lets embedding hosts branch on approval state instead
+/// of parsing our stderr output.
+#[no_mangle]
+pub extern "C" fn kr_notify_last_status() -> libc::c_int {
+    status::last() as libc::c_int
+}
+
+/// C API: blocks up to `timeout_ms` milliseconds for the current
+/// approval exchange to resolve, returning the same discriminants as
+/// `kr_notify_last_status`. A non-positive timeout just reads the
+/// current state. Returns `Pending` (or `Unknown`) when the timeout
+/// passes without an answer.
+#[no_mangle]
+pub extern "C" fn kr_notify_wait(timeout_ms: libc::c_int) -> libc::c_int {
+    let timeout = if timeout_ms > 0 {
+        Duration::from_millis(timeout_ms as u64)
+    } else {
+        Duration::from_millis(0)
+    };
+    status::wait(timeout) as libc::c_int
+}
+
+fn close_pipe_read() {
+    let fd = PIPE_READ.swap(-1, Ordering::SeqCst) as libc::c_int;
+    if fd >= 0 {
+        unsafe {
+            libc::close(fd);
+        }
+    }
+}
+
+extern "C" fn deinit_on_unload() {
+    Deinit();
+}
+
+#[cfg(target_os = "linux")]
+#[link_section = ".fini_array"]
+#[used]
+static DEINIT_ON_UNLOAD: extern "C" fn() = deinit_on_unload;
+
+#[cfg(target_os = "macos")]
+#[link_section = "__DATA,__mod_term_func"]
+#[used]
+static DEINIT_ON_UNLOAD: extern "C" fn() = deinit_on_unload;
+
+fn run_relay() {
+    let mut relay = Relay::new();
+    // Prefer a push subscription from krd; fall back to tailing the
+    // shared log for daemons that predate the notify socket.
+    let stream = subscribe(&relay.session);
+    match stream {
+        Some(stream) => relay_stream(stream, &mut relay),
+        None => tail_notify_log(&mut relay),
+    }
+}
+
+/// Everything one session's notification stream needs: its identity,
+/// the de-dup window, the heartbeat and the optional mirrors.
+struct Relay {
+    session: String,
+    seen: RecentSet,
+    heartbeat: Heartbeat,
+    limiter: RateLimiter,
+    mirror: Option<Arc<syslog::Logger>>,
+    fifo: Option<File>,
+}
+
+impl Relay {
+    fn new() -> Relay {
+        Relay {
+            session: session_id(),
+            seen: RecentSet::from_env(),
+            heartbeat: Heartbeat::from_env(),
+            limiter: RateLimiter::from_env(),
+            mirror: syslog_mirror(),
+            fifo: open_notify_fifo(),
+        }
+    }
+
+    /// Filters, de-duplicates and prints one notification line,
+    /// mirroring it to syslog and the notify FIFO when configured.
+    fn emit(&mut self, line: &str) {
+        let message = match session_line(line, &self.session) {
+            Some(message) => message.to_owned(),
+            None => return,
+        };
+        if message.is_empty() || !self.seen.insert(&message) {
+            return;
+        }
+        let note = protocol::Notification::parse(&message);
+        if let Some(ref note) = note {
+            self.heartbeat.observe(note);
+            status::record(note);
+        }
+        if let Some(ref logger) = self.mirror {
+            // the mirror sees everything, regardless of KR_NOTIFY_LEVEL
+            let plain = match note {
+                Some(ref note) => note.render(false),
+                None => message.clone(),
+            };
+            let entry = match ssh_target_host() {
+                Some(host) => format!("{}: {}", host, plain),
+                None => plain,
+            };
+            let _ = logger.notice(&entry);
+        }
+        let mut fifo_broken = false;
+        if let (Some(fifo), Some(note)) = (self.fifo.as_mut(), note.as_ref()) {
+            match serde_json::to_string(note) {
+                Ok(json) => {
+                    if writeln!(fifo, "{}", json).is_err() {
+                        fifo_broken = true;
+                    }
+                }
+                Err(_) => {}
+            }
+        }
+        if fifo_broken {
+            // the reader went away; stop writing before we hit SIGPIPE
+            self.fifo = None;
+        }
+        let level = protocol::Level::from_env();
+        if level == protocol::Level::Silent {
+            return;
+        }
+        let rendered = match note {
+            Some(note) => {
+                if note.level() > level {
+                    return;
+                }
+                note.render(protocol::use_color())
+            }
+            // raw lines from pre-JSON daemons only show at the debug level
+            None => {
+                if level < protocol::Level::Debug {
+                    return;
+                }
+                message
+            }
+        };
+        // the rate limit applies only to the terminal; the syslog and
+        // FIFO mirrors above saw the full stream
+        let suppressed = match self.limiter.acquire() {
+            Some(suppressed) => suppressed,
+            None => return,
+        };
+        if suppressed > 0 {
+            let _ = writeln!(
+                io::stderr(),
+                "… suppressed {} notification lines",
+                suppressed
+            );
+        }
+        let _ = writeln!(io::stderr(), "{}", rendered);
+    }
+}
+
+/// When `KR_NOTIFY_FIFO` names a path, parsed notifications are
+/// re-serialized as JSON lines into that FIFO so GUI tools, IDE plugins
+/// or tmux status bars can display approval state without scraping
+/// stderr. The FIFO is created if missing and opened non-blocking so a
+/// missing reader never hangs `Init`.
+fn open_notify_fifo() -> Option<File> {
+    let path = env::var("KR_NOTIFY_FIFO").ok()?;
+    let path_c = CString::new(path.into_bytes()).ok()?;
+    unsafe {
+        libc::mkfifo(path_c.as_ptr(), 0o600); // EEXIST is fine
+        let fd = libc::open(path_c.as_ptr(), libc::O_WRONLY | libc::O_NONBLOCK);
+        if fd < 0 {
+            // ENXIO: no reader attached (yet); skip the mirror
+            return None;
+        }
+        Some(File::from_raw_fd(fd))
+    }
+}
+
+/// When `KR_NOTIFY_SYSLOG=1`, every notification is also recorded under
+/// LOG_AUTH for hosts where stderr is captured or discarded by
+/// automation. Uses the process-wide shared connection so repeated
+/// injections do not pile up sockets.
+fn syslog_mirror() -> Option<Arc<syslog::Logger>> {
+    match env::var("KR_NOTIFY_SYSLOG") {
+        Ok(ref flag) if flag == "1" => {}
+        _ => return None,
+    }
+    syslog::Builder::new()
+        .facility(syslog::Facility::LOG_AUTH)
+        .app_name("kr-ssh-notify")
+        .connect_shared()
+        .ok()
+}
+
+/// The SSH target host, exported by the kr wrapper so operators can
+/// attribute notifications to a connection in the audit log.
+fn ssh_target_host() -> Option<String> {
+    env::var("KR_SSH_HOST").ok()
+}
+
+/// Connects to krd's notify socket and registers this session. krd then
+/// pushes only the lines meant for us, so there is no shared file to
+/// truncate and no cross-talk between concurrent SSH processes.
+fn subscribe(session: &str) -> Option<UnixStream> {
+    let path = notify_sock_path()?;
+    let mut stream = UnixStream::connect(&path).ok()?;
+    writeln!(stream, "{}", session).ok()?;
+    Some(stream)
+}
+
+fn relay_stream(stream: UnixStream, relay: &mut Relay) {
+    // wake up every second so heartbeats and the inactivity timeout
+    // fire even when krd has nothing to push
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(1)));
+    let mut reader = BufReader::new(stream);
+    let timeout = notify_timeout();
+    let mut last_activity = Instant::now();
+    let mut line = String::new();
+    while !STDOUT_SEEN.load(Ordering::SeqCst) && !SHUTDOWN.load(Ordering::SeqCst) {
+        match reader.read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                last_activity = Instant::now();
+                let trimmed = line.trim().to_owned();
+                relay.emit(&trimmed);
+                line.clear();
+            }
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock
+                    || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                // a partial line stays in `line` and finishes next read
+                match timeout {
+                    Some(timeout) if last_activity.elapsed() >= timeout => break,
+                    _ => relay.heartbeat.tick(),
+                }
+            }
+            Err(_) => break,
+        }
+    }
+}
+
+fn tail_notify_log(relay: &mut Relay) {
+    let path = match notify_log_path() {
+        Some(path) => path,
+        None => return,
+    };
+    let mut tailer = Tailer::new(path.clone());
+    if tailer.open().is_err() {
+        return;
+    }
+    let watcher = Watcher::new(&path);
+    let timeout = notify_timeout();
+    let mut last_activity = Instant::now();
+    while !STDOUT_SEEN.load(Ordering::SeqCst) && !SHUTDOWN.load(Ordering::SeqCst) {
+        // while an approval is pending, wake up at least once a second
+        // so the heartbeat can fire even if the log stays quiet
+        let wait = if relay.heartbeat.waiting() {
+            Some(Duration::from_secs(1))
+        } else {
+            timeout
+        };
+        watcher.wait(wait);
+        let lines = match tailer.poll() {
+            Ok(lines) => lines,
+            Err(_) => break,
+        };
+        if lines.is_empty() {
+            match timeout {
+                Some(timeout) if last_activity.elapsed() >= timeout => break,
+                _ => {
+                    relay.heartbeat.tick();
+                    continue;
+                }
+            }
+        }
+        last_activity = Instant::now();
+        for line in lines {
+            relay.emit(&line);
+        }
+    }
+}
+
+/// Interposes a pipe on STDOUT_FILENO so the first byte of real session
+/// output can be detected. A pump thread forwards everything to the
+/// saved stdout, flipping `STDOUT_SEEN` on the first read.
+#[cfg(feature = "stdout-detection")]
+fn start_stdout_detection() {
+    let read_fd = unsafe {
+        let saved = libc::dup(libc::STDOUT_FILENO);
+        if saved < 0 {
+            return;
+        }
+        libc::fcntl(saved, libc::F_SETFD, libc::FD_CLOEXEC);
+        let mut pipe_fds = [0 as libc::c_int; 2];
+        if libc::pipe(pipe_fds.as_mut_ptr()) != 0 {
+            libc::close(saved);
+            return;
+        }
+        if libc::dup2(pipe_fds[1], libc::STDOUT_FILENO) < 0 {
+            libc::close(pipe_fds[0]);
+            libc::close(pipe_fds[1]);
+            libc::close(saved);
+            return;
+        }
+        libc::close(pipe_fds[1]);
+        SAVED_STDOUT.store(saved as isize, Ordering::SeqCst);
+        PIPE_READ.store(pipe_fds[0] as isize, Ordering::SeqCst);
+        pipe_fds[0]
+    };
+    thread::spawn(move || pump_stdout(read_fd));
+}
+
+/// One-shot detection: blocks for the first byte of real session
+/// output, then puts the saved stdout back over STDOUT_FILENO so the
+/// process returns to the kernel fast path instead of relaying every
+/// write through this thread for the life of the session.
+#[cfg(feature = "stdout-detection")]
+fn pump_stdout(read_fd: libc::c_int) {
+    let mut buf = [0u8; 4096];
+    let n = unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n <= 0 || SHUTDOWN.load(Ordering::SeqCst) {
+        close_pipe_read();
+        return;
+    }
+    STDOUT_SEEN.store(true, Ordering::SeqCst);
+    let saved = SAVED_STDOUT.swap(-1, Ordering::SeqCst) as libc::c_int;
+    if saved < 0 {
+        // Deinit won the race and already restored stdout
+        close_pipe_read();
+        return;
+    }
+    write_fully(saved, &buf[..n as usize]);
+    unsafe {
+        libc::dup2(saved, libc::STDOUT_FILENO);
+    }
+    // The dup2 closed the pipe's last write end, so draining anything
+    // that raced in before the restore terminates at EOF.
+    loop {
+        let n = unsafe { libc::read(read_fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n <= 0 || !write_fully(saved, &buf[..n as usize]) {
+            break;
+        }
+    }
+    unsafe {
+        libc::close(saved);
+    }
+    close_pipe_read();
+}
+
+#[cfg(feature = "stdout-detection")]
+fn write_fully(fd: libc::c_int, buf: &[u8]) -> bool {
+    let mut written = 0;
+    while written < buf.len() {
+        let w = unsafe {
+            libc::write(
+                fd,
+                buf[written..].as_ptr() as *const libc::c_void,
+                buf.len() - written,
+            )
+        };
+        if w <= 0 {
+            return false;
+        }
+        written += w as usize;
+    }
+    true
+}
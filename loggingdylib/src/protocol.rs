@@ -0,0 +1,149 @@
+//! The krd → dylib notification line format.
+//!
+//! krd emits one JSON object per line, `{"type": "...", "message":
+//! "..."}`, so the dylib can render a readable status line instead of
+//! raw log text. Lines that do not parse (older daemons emit plain
+//! strings) are passed through unchanged by the caller.
+
+use std::env;
+
+use libc;
+use serde_json;
+
+/// How much the user wants to see, from `KR_NOTIFY_LEVEL`.
+///
+/// Classes are cumulative: `status` also shows errors, `debug` also
+/// shows raw lines from daemons that predate the JSON protocol.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Silent,
+    Errors,
+    Status,
+    Debug,
+}
+
+impl Level {
+    /// Reads `KR_NOTIFY_LEVEL`, defaulting to `debug` (everything) in
+    /// interactive sessions. When stderr is not a TTY (scp in a script,
+    /// git called from CI) the default is `silent` instead, so chatter
+    /// does not corrupt captured output; `KR_NOTIFY_FORCE=1` or an
+    /// explicit `KR_NOTIFY_LEVEL` opts back in.
+    pub fn from_env() -> Level {
+        match env::var("KR_NOTIFY_LEVEL") {
+            Ok(ref level) if level == "silent" => return Level::Silent,
+            Ok(ref level) if level == "errors" => return Level::Errors,
+            Ok(ref level) if level == "status" => return Level::Status,
+            Ok(ref level) if level == "debug" => return Level::Debug,
+            _ => {}
+        }
+        if !stderr_interactive() && !forced() {
+            return Level::Silent;
+        }
+        Level::Debug
+    }
+}
+
+/// `KR_NOTIFY_FORCE=1`: show notifications even without a TTY.
+fn forced() -> bool {
+    env::var("KR_NOTIFY_FORCE").map(|v| v == "1").unwrap_or(false)
+}
+
+fn stderr_interactive() -> bool {
+    unsafe { libc::isatty(libc::STDERR_FILENO) == 1 }
+}
+
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    ApprovalRequest,
+    Approved,
+    Rejected,
+    Error,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Notification {
+    #[serde(rename = "type")]
+    pub kind: Kind,
+    #[serde(default)]
+    pub message: String,
+}
+
+impl Notification {
+    /// Parses a notification line, or `None` if it is not one (in which
+    /// case the raw line should be shown as-is).
+    pub fn parse(line: &str) -> Option<Notification> {
+        if !line.starts_with('{') {
+            return None;
+        }
+        serde_json::from_str(line).ok()
+    }
+
+    /// The verbosity class this notification belongs to.
+    pub fn level(&self) -> Level {
+        match self.kind {
+            Kind::Error => Level::Errors,
+            _ => Level::Status,
+        }
+    }
+
+    /// One status line: a glyph plus the message, wrapped in an ANSI
+    /// color when `color` is set.
+    pub fn render(&self, color: bool) -> String {
+        let (glyph, code) = match self.kind {
+            Kind::ApprovalRequest => ("⏳", "33"),
+            Kind::Approved => ("✔", "32"),
+            Kind::Rejected => ("✘", "31"),
+            Kind::Error => ("⚠", "31"),
+        };
+        if color {
+            format!("\x1b[{}m{} {}\x1b[0m", code, glyph, self.message)
+        } else {
+            format!("{} {}", glyph, self.message)
+        }
+    }
+}
+
+/// Whether stderr output should be colored: only on a TTY, and never
+/// when NO_COLOR is set (https://no-color.org).
+pub fn use_color() -> bool {
+    env::var_os("NO_COLOR").is_none() && unsafe { libc::isatty(libc::STDERR_FILENO) } == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_notification() {
+        let note =
+            Notification::parse(r#"{"type": "approved", "message": "request approved"}"#).unwrap();
+        assert_eq!(note.kind, Kind::Approved);
+        assert_eq!(note.message, "request approved");
+    }
+
+    #[test]
+    fn raw_lines_do_not_parse() {
+        assert!(Notification::parse("waiting for phone").is_none());
+        assert!(Notification::parse(r#"{"type": "unknown", "message": "x"}"#).is_none());
+    }
+
+    #[test]
+    fn levels_are_cumulative() {
+        assert!(Level::Silent < Level::Errors);
+        assert!(Level::Errors < Level::Status);
+        assert!(Level::Status < Level::Debug);
+        let note = Notification::parse(r#"{"type": "error", "message": "x"}"#).unwrap();
+        assert_eq!(note.level(), Level::Errors);
+    }
+
+    #[test]
+    fn render_plain() {
+        let note = Notification {
+            kind: Kind::ApprovalRequest,
+            message: "waiting for approval on phone…".to_owned(),
+        };
+        assert_eq!(note.render(false), "⏳ waiting for approval on phone…");
+        assert!(note.render(true).starts_with("\x1b[33m"));
+    }
+}
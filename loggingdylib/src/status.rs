@@ -0,0 +1,93 @@
+//! Approval state shared with embedding hosts.
+//!
+//! The relay records the outcome of the most recent approval exchange
+//! here so hosts that link the dylib (krssh, IDE plugins) can branch on
+//! it through the C API in `lib.rs` instead of parsing stderr.
+
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{Duration, Instant};
+use std::thread;
+
+use protocol::{Kind, Notification};
+
+/// Approval outcome as reported through the C API. The discriminants
+/// are ABI: embedding hosts compare against these numbers.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// No approval exchange has been observed (or the last one errored).
+    Unknown = 0,
+    /// A request is waiting for an answer on the phone.
+    Pending = 1,
+    Approved = 2,
+    Rejected = 3,
+}
+
+static LAST: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Folds a parsed notification into the shared status.
+pub(crate) fn record(note: &Notification) {
+    let status = match note.kind {
+        Kind::ApprovalRequest => Status::Pending,
+        Kind::Approved => Status::Approved,
+        Kind::Rejected => Status::Rejected,
+        // an error ends the exchange without an outcome
+        Kind::Error => Status::Unknown,
+    };
+    LAST.store(status as usize, Ordering::SeqCst);
+}
+
+pub fn last() -> Status {
+    match LAST.load(Ordering::SeqCst) {
+        1 => Status::Pending,
+        2 => Status::Approved,
+        3 => Status::Rejected,
+        _ => Status::Unknown,
+    }
+}
+
+/// Blocks until the exchange reaches a terminal outcome (approved or
+/// rejected) or `timeout` passes, returning the status either way.
+///
+/// Polls rather than waiting on a condvar: the state has to live in a
+/// plain static (the relay thread may be recording while the host tears
+/// the library down), and the 20ms poll is far below the granularity of
+/// a human answering a phone prompt.
+pub fn wait(timeout: Duration) -> Status {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match last() {
+            status @ Status::Approved | status @ Status::Rejected => return status,
+            status => {
+                if Instant::now() >= deadline {
+                    return status;
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(kind: Kind) -> Notification {
+        Notification {
+            kind: kind,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn records_the_exchange_outcome() {
+        record(&note(Kind::ApprovalRequest));
+        assert_eq!(last(), Status::Pending);
+        assert_eq!(wait(Duration::from_millis(0)), Status::Pending);
+        record(&note(Kind::Approved));
+        assert_eq!(last(), Status::Approved);
+        assert_eq!(wait(Duration::from_millis(0)), Status::Approved);
+        record(&note(Kind::Error));
+        assert_eq!(last(), Status::Unknown);
+    }
+}
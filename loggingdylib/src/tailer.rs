@@ -0,0 +1,196 @@
+//! Incremental reading of krd-notify.log.
+//!
+//! krd may truncate the log between reads, and log rotation replaces
+//! the file outright. The tailer compares the on-disk inode against the
+//! handle it holds (fstat vs stat) and reopens from the start when they
+//! diverge, so messages are not silently lost while the reader holds an
+//! offset past EOF. In-place truncation keeps the inode, and the file
+//! may have regrown past our offset by the next poll, so shrinkage alone
+//! is not enough to detect it either; a fingerprint of the file's first
+//! bytes catches the rewrite.
+
+use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+/// How many leading bytes to fingerprint for truncation detection.
+const HEADER_LEN: usize = 64;
+
+pub struct Tailer {
+    path: PathBuf,
+    file: Option<File>,
+    ino: u64,
+    offset: u64,
+    pending: String,
+    /// The first bytes of the file as of our reads, up to `HEADER_LEN`.
+    header: Vec<u8>,
+}
+
+impl Tailer {
+    pub fn new(path: PathBuf) -> Tailer {
+        Tailer {
+            path: path,
+            file: None,
+            ino: 0,
+            offset: 0,
+            pending: String::new(),
+            header: Vec::new(),
+        }
+    }
+
+    /// The initial open truncates so this session starts from a clean
+    /// log, matching what Init has always done.
+    pub fn open(&mut self) -> io::Result<()> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.ino = file.metadata()?.ino();
+        self.file = Some(file);
+        self.offset = 0;
+        self.pending.clear();
+        self.header.clear();
+        Ok(())
+    }
+
+    /// The file was rotated: pick up the replacement from the start.
+    fn reopen(&mut self) -> io::Result<()> {
+        let file = File::open(&self.path)?;
+        self.ino = file.metadata()?.ino();
+        self.file = Some(file);
+        self.offset = 0;
+        self.pending.clear();
+        self.header.clear();
+        Ok(())
+    }
+
+    /// Whether the file's leading bytes no longer match our fingerprint,
+    /// i.e. it was truncated and rewritten in place.
+    fn header_changed(&mut self) -> io::Result<bool> {
+        if self.header.is_empty() {
+            return Ok(false);
+        }
+        let file = self.file.as_mut().unwrap();
+        file.seek(SeekFrom::Start(0))?;
+        let mut current = vec![0u8; self.header.len()];
+        let mut read = 0;
+        while read < current.len() {
+            let n = file.read(&mut current[read..])?;
+            if n == 0 {
+                break;
+            }
+            read += n;
+        }
+        Ok(current[..read] != self.header[..])
+    }
+
+    /// Returns any complete lines appended since the last call,
+    /// reopening the file if krd rotated it and rewinding if it shrank.
+    pub fn poll(&mut self) -> io::Result<Vec<String>> {
+        if self.file.is_none() {
+            self.open()?;
+        }
+        match fs::metadata(&self.path) {
+            Ok(disk) => {
+                if disk.ino() != self.ino {
+                    self.reopen()?;
+                }
+            }
+            // mid-rotation: the old name is gone but the replacement
+            // has not appeared yet; keep the current handle for now
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        }
+        let mut lines = Vec::new();
+        let len = self.file.as_mut().unwrap().metadata()?.len();
+        if len < self.offset || self.header_changed()? {
+            // truncated in place; start over
+            self.offset = 0;
+            self.pending.clear();
+            self.header.clear();
+        }
+        if len == self.offset {
+            return Ok(lines);
+        }
+        {
+            let file = self.file.as_mut().unwrap();
+            file.seek(SeekFrom::Start(self.offset))?;
+            let mut chunk = String::new();
+            file.by_ref().take(len - self.offset).read_to_string(&mut chunk)?;
+            if self.offset == 0 {
+                let take = cmp::min(chunk.len(), HEADER_LEN);
+                self.header = chunk.as_bytes()[..take].to_vec();
+            }
+            self.offset = len;
+            self.pending.push_str(&chunk);
+        }
+        while let Some(newline) = self.pending.find('\n') {
+            let line = self.pending[..newline].trim().to_owned();
+            self.pending.drain(..newline + 1);
+            lines.push(line);
+        }
+        Ok(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("krlogging-tailer-{}-{}", name, process::id()))
+    }
+
+    fn append(path: &PathBuf, text: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn appended_lines() {
+        let path = temp_path("append");
+        let mut tailer = Tailer::new(path.clone());
+        tailer.open().unwrap();
+        append(&path, "one\ntwo\npartial");
+        assert_eq!(tailer.poll().unwrap(), vec!["one", "two"]);
+        assert!(tailer.poll().unwrap().is_empty());
+        append(&path, " line\n");
+        assert_eq!(tailer.poll().unwrap(), vec!["partial line"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncation_rewinds() {
+        let path = temp_path("truncate");
+        let mut tailer = Tailer::new(path.clone());
+        tailer.open().unwrap();
+        append(&path, "one\n");
+        assert_eq!(tailer.poll().unwrap(), vec!["one"]);
+        File::create(&path).unwrap();
+        append(&path, "two\n");
+        assert_eq!(tailer.poll().unwrap(), vec!["two"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rotation_reopens() {
+        let path = temp_path("rotate");
+        let mut tailer = Tailer::new(path.clone());
+        tailer.open().unwrap();
+        append(&path, "old\n");
+        assert_eq!(tailer.poll().unwrap(), vec!["old"]);
+        fs::remove_file(&path).unwrap();
+        assert!(tailer.poll().unwrap().is_empty());
+        File::create(&path).unwrap();
+        append(&path, "fresh\n");
+        assert_eq!(tailer.poll().unwrap(), vec!["fresh"]);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+//! Blocking file-change watching.
+//!
+//! The tailer used to wake every 10ms to poll the notify log, burning CPU
+//! for the whole life of an SSH session. Where the platform offers a
+//! file-watch facility (inotify on Linux, kqueue on macOS) we block in
+//! the kernel until the file actually changes; polling remains only as a
+//! fallback when watching cannot be set up.
+
+use std::ffi::CString;
+#[cfg(target_os = "macos")]
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+#[cfg(target_os = "macos")]
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+
+use libc;
+
+/// How often the fallback backend polls, matching the old tailer.
+const POLL_INTERVAL_MS: u64 = 10;
+
+pub struct Watcher {
+    backend: Backend,
+}
+
+enum Backend {
+    #[cfg(target_os = "linux")]
+    Inotify { fd: libc::c_int },
+    #[cfg(target_os = "macos")]
+    Kqueue { kq: libc::c_int, file_fd: libc::c_int },
+    Poll,
+}
+
+impl Watcher {
+    /// Watches `path`, silently falling back to polling if the platform
+    /// backend cannot be initialized (e.g. fd exhaustion).
+    pub fn new(path: &Path) -> Watcher {
+        Watcher {
+            backend: platform_backend(path).unwrap_or(Backend::Poll),
+        }
+    }
+
+    /// Blocks until the watched file may have changed, or until
+    /// `timeout` passes. Spurious wakeups are fine: the caller re-checks
+    /// the file length either way.
+    pub fn wait(&self, timeout: Option<Duration>) {
+        match self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Inotify { fd } => unsafe {
+                let mut pfd = libc::pollfd {
+                    fd: fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let ms = match timeout {
+                    Some(timeout) => timeout_millis(timeout),
+                    None => -1,
+                };
+                if libc::poll(&mut pfd, 1, ms) > 0 {
+                    // inotify_event records are variable-length; we only
+                    // care that *something* happened, so one read suffices
+                    let mut buf = [0u8; 4096];
+                    libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len());
+                }
+            },
+            #[cfg(target_os = "macos")]
+            Backend::Kqueue { kq, .. } => unsafe {
+                let ts;
+                let ts_ptr = match timeout {
+                    Some(timeout) => {
+                        ts = libc::timespec {
+                            tv_sec: timeout.as_secs() as libc::time_t,
+                            tv_nsec: timeout.subsec_nanos() as libc::c_long,
+                        };
+                        &ts as *const libc::timespec
+                    }
+                    None => ptr::null(),
+                };
+                let mut event: libc::kevent = mem::zeroed();
+                libc::kevent(kq, ptr::null(), 0, &mut event, 1, ts_ptr);
+            },
+            Backend::Poll => thread::sleep(Duration::from_millis(POLL_INTERVAL_MS)),
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        match self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Inotify { fd } => unsafe {
+                libc::close(fd);
+            },
+            #[cfg(target_os = "macos")]
+            Backend::Kqueue { kq, file_fd } => unsafe {
+                libc::close(kq);
+                libc::close(file_fd);
+            },
+            Backend::Poll => {}
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn timeout_millis(timeout: Duration) -> libc::c_int {
+    let ms = timeout
+        .as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(timeout.subsec_millis()));
+    if ms > libc::c_int::max_value() as u64 {
+        libc::c_int::max_value()
+    } else {
+        ms as libc::c_int
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn platform_backend(path: &Path) -> Option<Backend> {
+    let path_c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let fd = libc::inotify_init1(libc::IN_CLOEXEC);
+        if fd < 0 {
+            return None;
+        }
+        let mask = libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_MOVE_SELF | libc::IN_DELETE_SELF;
+        if libc::inotify_add_watch(fd, path_c.as_ptr(), mask) < 0 {
+            libc::close(fd);
+            return None;
+        }
+        Some(Backend::Inotify { fd: fd })
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn platform_backend(path: &Path) -> Option<Backend> {
+    let path_c = CString::new(path.as_os_str().as_bytes()).ok()?;
+    unsafe {
+        let kq = libc::kqueue();
+        if kq < 0 {
+            return None;
+        }
+        let file_fd = libc::open(path_c.as_ptr(), libc::O_EVTONLY);
+        if file_fd < 0 {
+            libc::close(kq);
+            return None;
+        }
+        let change = libc::kevent {
+            ident: file_fd as libc::uintptr_t,
+            filter: libc::EVFILT_VNODE,
+            flags: libc::EV_ADD | libc::EV_CLEAR,
+            fflags: libc::NOTE_WRITE | libc::NOTE_EXTEND | libc::NOTE_DELETE | libc::NOTE_RENAME,
+            data: 0,
+            udata: ptr::null_mut(),
+        };
+        if libc::kevent(kq, &change, 1, ptr::null_mut(), 0, ptr::null()) < 0 {
+            libc::close(file_fd);
+            libc::close(kq);
+            return None;
+        }
+        Some(Backend::Kqueue {
+            kq: kq,
+            file_fd: file_fd,
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn platform_backend(_path: &Path) -> Option<Backend> {
+    None
+}
@@ -0,0 +1,35 @@
+//! Benchmarks the info entry points NSS hammers at startup, comparing the
+//! cached-struct copies against the cost of rebuilding them per call.
+
+#[macro_use]
+extern crate criterion;
+extern crate kr_pkcs11;
+
+use std::env;
+use std::mem;
+
+use criterion::Criterion;
+
+use kr_pkcs11::pkcs11::*;
+use kr_pkcs11::pkcs11shim::{CK_C_GetInfo, CK_C_GetSlotInfo, CK_C_GetTokenInfo, KRYPTON_SLOT_ID};
+use kr_pkcs11::soft;
+
+fn bench_info(c: &mut Criterion) {
+    // Soft mode keeps the token-present probe off the agent socket.
+    env::set_var(soft::SOFT_MODE_VAR, "1");
+    c.bench_function("C_GetInfo", |b| {
+        let mut info: CK_INFO = unsafe { mem::zeroed() };
+        b.iter(|| CK_C_GetInfo(&mut info));
+    });
+    c.bench_function("C_GetSlotInfo", |b| {
+        let mut info: CK_SLOT_INFO = unsafe { mem::zeroed() };
+        b.iter(|| CK_C_GetSlotInfo(KRYPTON_SLOT_ID, &mut info));
+    });
+    c.bench_function("C_GetTokenInfo", |b| {
+        let mut info: CK_TOKEN_INFO = unsafe { mem::zeroed() };
+        b.iter(|| CK_C_GetTokenInfo(KRYPTON_SLOT_ID, &mut info));
+    });
+}
+
+criterion_group!(benches, bench_info);
+criterion_main!(benches);
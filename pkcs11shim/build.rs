@@ -0,0 +1,28 @@
+//! Embeds the crate version and git revision into the library so
+//! `C_GetInfo` and `KR_GetBuildInfo` report the build that is actually
+//! installed instead of a hard-coded 1.0.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let version = env::var("CARGO_PKG_VERSION").unwrap();
+    let mut parts = version.split('.');
+    println!(
+        "cargo:rustc-env=KR_PKCS11_VERSION_MAJOR={}",
+        parts.next().unwrap_or("0")
+    );
+    println!(
+        "cargo:rustc-env=KR_PKCS11_VERSION_MINOR={}",
+        parts.next().unwrap_or("0")
+    );
+    let git_hash = Command::new("git")
+        .args(&["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=KR_PKCS11_GIT_HASH={}", git_hash);
+}
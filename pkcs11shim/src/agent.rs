@@ -0,0 +1,550 @@
+//! Minimal ssh-agent protocol client used as the signing backend.
+//!
+//! Instead of a bespoke krd RPC, the shim speaks the standard ssh-agent
+//! wire protocol over `~/.kr/krd-agent.sock`. Agent identities become
+//! PKCS#11 key objects and agent signatures become PKCS#11 signatures,
+//! which also makes the shim usable against any conforming agent.
+
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use serde_json;
+
+use pkcs11shim::kr_path;
+use zeroize;
+
+// Agent protocol message numbers (draft-miller-ssh-agent)
+pub const SSH_AGENT_FAILURE: u8 = 5;
+pub const SSH_AGENT_SUCCESS: u8 = 6;
+pub const SSH_AGENTC_REQUEST_IDENTITIES: u8 = 11;
+pub const SSH_AGENT_IDENTITIES_ANSWER: u8 = 12;
+pub const SSH_AGENTC_SIGN_REQUEST: u8 = 13;
+pub const SSH_AGENT_SIGN_RESPONSE: u8 = 14;
+
+pub const SSH_AGENTC_EXTENSION: u8 = 27;
+
+// Signature flags for SSH_AGENTC_SIGN_REQUEST
+pub const SSH_AGENT_RSA_SHA2_256: u32 = 2;
+pub const SSH_AGENT_RSA_SHA2_512: u32 = 4;
+
+/// krd extension carrying metadata about the next request on this
+/// connection; agents that don't know it just answer SSH_AGENT_FAILURE.
+pub const METADATA_EXTENSION: &'static str = "metadata@krypt.co";
+
+/// krd extension proving knowledge of the shared secret before a TCP
+/// connection may issue requests; see `AgentConn::authenticate`.
+pub const AUTH_EXTENSION: &'static str = "auth@krypt.co";
+
+pub const AGENT_SOCKET_FILENAME: &'static str = "krd-agent.sock";
+
+/// The shared secret a TCP endpoint is protected by, one line under
+/// `~/.kr`; provisioned alongside the pairing by whoever exposes krd on
+/// the network.
+pub const AGENT_SECRET_FILENAME: &'static str = "krd-agent.secret";
+
+/// Overrides where the agent lives: `unix:///path`, `tcp://host:port`,
+/// or a bare socket path. Unset means `~/.kr/krd-agent.sock`.
+pub const AGENT_SOCK_VAR: &'static str = "KR_AGENT_SOCK";
+
+/// Upper bound on a single agent message; matches OpenSSH's
+/// MAX_AGENT_REPLY_LEN.
+pub const MAX_AGENT_MESSAGE: usize = 256 * 1024;
+
+/// One public key known to the agent.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    /// SSH wire-format public key blob.
+    pub key_blob: Vec<u8>,
+    /// The agent's comment for this key; krd uses the paired profile's email.
+    pub comment: String,
+}
+
+impl Identity {
+    /// The key type string at the front of the wire blob, e.g.
+    /// "ssh-rsa" or "ssh-ed25519".
+    pub fn key_format(&self) -> Option<String> {
+        read_string_at(&self.key_blob, 0).map(|(s, _)| s)
+    }
+}
+
+/// Metadata about the caller on whose behalf the shim is signing, shown in
+/// the phone's approval prompt.
+#[derive(Clone, Debug, Serialize)]
+pub struct SignMetadata {
+    /// Executable path of the host application.
+    pub caller_path: String,
+    /// Pid of the host application.
+    pub caller_pid: i32,
+}
+
+lazy_static! {
+    /// The default agent socket path, resolved exactly once the first
+    /// time it is needed. `C_Initialize` forces the resolution so the
+    /// environment (`HOME`, `SUDO_USER`) is read at a well-defined point
+    /// instead of on an arbitrary later thread.
+    pub static ref AGENT_SOCKET_PATH: PathBuf = kr_path(AGENT_SOCKET_FILENAME);
+
+    /// Where the agent actually lives, `KR_AGENT_SOCK` included;
+    /// resolved once, at the same point as the path above.
+    pub static ref AGENT_ENDPOINT: Endpoint = Endpoint::from_env();
+}
+
+/// An agent endpoint: krd's local socket or, for remote and
+/// containerized setups, a TCP address.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Endpoint {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+impl Endpoint {
+    /// Parses a `KR_AGENT_SOCK` value. A bare path counts as a unix
+    /// socket so existing override scripts keep working.
+    pub fn parse(value: &str) -> Option<Endpoint> {
+        if value.starts_with("unix://") {
+            let path = &value["unix://".len()..];
+            if path.is_empty() {
+                return None;
+            }
+            return Some(Endpoint::Unix(PathBuf::from(path)));
+        }
+        if value.starts_with("tcp://") {
+            let addr = &value["tcp://".len()..];
+            if addr.is_empty() || !addr.contains(':') {
+                return None;
+            }
+            return Some(Endpoint::Tcp(addr.to_owned()));
+        }
+        if value.is_empty() || value.contains("://") {
+            return None;
+        }
+        Some(Endpoint::Unix(PathBuf::from(value)))
+    }
+
+    fn from_env() -> Endpoint {
+        if let Ok(value) = env::var(AGENT_SOCK_VAR) {
+            match Endpoint::parse(&value) {
+                Some(endpoint) => return endpoint,
+                None => warning!("ignoring unparseable {}: {}", AGENT_SOCK_VAR, value),
+            }
+        }
+        Endpoint::Unix(AGENT_SOCKET_PATH.clone())
+    }
+}
+
+/// The stream under an `AgentConn`, so the same protocol code drives
+/// both endpoint kinds.
+enum Transport {
+    Unix(UnixStream),
+    Tcp(TcpStream),
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Transport::Unix(ref mut stream) => stream.read(buf),
+            Transport::Tcp(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Transport::Unix(ref mut stream) => stream.write(buf),
+            Transport::Tcp(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Transport::Unix(ref mut stream) => stream.flush(),
+            Transport::Tcp(ref mut stream) => stream.flush(),
+        }
+    }
+}
+
+/// A connection to an ssh-agent.
+pub struct AgentConn {
+    stream: Transport,
+    /// Reusable receive buffer. mlocked so signed data and signatures
+    /// never page out; wiped on reuse and on drop.
+    scratch: zeroize::LockedBuffer,
+}
+
+impl AgentConn {
+    /// Connects to the configured agent endpoint. A TCP connection must
+    /// present the shared secret before it is usable.
+    pub fn connect() -> io::Result<AgentConn> {
+        let (stream, authenticate) = match *AGENT_ENDPOINT {
+            Endpoint::Unix(ref path) => (Transport::Unix(UnixStream::connect(path)?), false),
+            Endpoint::Tcp(ref addr) => (Transport::Tcp(TcpStream::connect(addr.as_str())?), true),
+        };
+        let mut conn = AgentConn {
+            stream: stream,
+            scratch: zeroize::LockedBuffer::new(MAX_AGENT_MESSAGE),
+        };
+        if authenticate {
+            conn.authenticate()?;
+        }
+        Ok(conn)
+    }
+
+    /// Proves knowledge of the shared secret in `~/.kr` before any
+    /// request goes over a TCP link. Framed as an agent extension so
+    /// the exchange stays inside the wire protocol; a local unix socket
+    /// is already protected by file permissions and skips this.
+    fn authenticate(&mut self) -> io::Result<()> {
+        let path = kr_path(AGENT_SECRET_FILENAME);
+        let mut secret = fs::read(&path).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("cannot read agent shared secret {}: {}", path.display(), e),
+            )
+        })?;
+        while secret.last() == Some(&b'\n') || secret.last() == Some(&b'\r') {
+            secret.pop();
+        }
+        let mut request = Vec::new();
+        request.push(SSH_AGENTC_EXTENSION);
+        write_bytes(&mut request, AUTH_EXTENSION.as_bytes());
+        write_bytes(&mut request, &secret);
+        zeroize::wipe(&mut secret);
+        let sent = self.send_message(&request);
+        zeroize::wipe(&mut request);
+        sent?;
+        if self.read_message()?.first() == Some(&SSH_AGENT_SUCCESS) {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "agent rejected the shared secret",
+            ))
+        }
+    }
+
+    /// Sends SSH_AGENTC_REQUEST_IDENTITIES and parses the answer.
+    pub fn request_identities(&mut self) -> io::Result<Vec<Identity>> {
+        self.send_message(&[SSH_AGENTC_REQUEST_IDENTITIES])?;
+        let response = self.read_message()?;
+        if response.first() != Some(&SSH_AGENT_IDENTITIES_ANSWER) {
+            return Err(protocol_error("expected SSH_AGENT_IDENTITIES_ANSWER"));
+        }
+        let (count, mut offset) = read_u32_at(&response, 1)
+            .ok_or_else(|| protocol_error("truncated identities answer"))?;
+        let mut identities = Vec::new();
+        for _ in 0..count {
+            let (key_blob, next) = read_bytes_at(&response, offset)
+                .ok_or_else(|| protocol_error("truncated key blob"))?;
+            let (comment, next) = read_string_at(&response, next)
+                .ok_or_else(|| protocol_error("truncated key comment"))?;
+            identities.push(Identity {
+                key_blob: key_blob,
+                comment: comment,
+            });
+            offset = next;
+        }
+        Ok(identities)
+    }
+
+    /// Sends caller metadata for the next request on this connection as an
+    /// agent protocol extension. Agents without the extension reply with
+    /// SSH_AGENT_FAILURE, which is fine — the metadata is best-effort.
+    pub fn send_metadata(&mut self, metadata: &SignMetadata) -> io::Result<()> {
+        let payload = serde_json::to_string(metadata)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let mut request = Vec::new();
+        request.push(SSH_AGENTC_EXTENSION);
+        write_bytes(&mut request, METADATA_EXTENSION.as_bytes());
+        write_bytes(&mut request, payload.as_bytes());
+        self.send_message(&request)?;
+        // Consume the reply regardless of whether the agent understood us.
+        self.read_message()?;
+        Ok(())
+    }
+
+    /// Sends SSH_AGENTC_SIGN_REQUEST for `data` with the key identified by
+    /// `key_blob`, returning the raw signature bytes with the wire
+    /// "signature format / signature blob" envelope removed.
+    pub fn sign(&mut self, key_blob: &[u8], data: &[u8], flags: u32) -> io::Result<Vec<u8>> {
+        let mut request = Vec::with_capacity(1 + 8 + key_blob.len() + data.len() + 4);
+        request.push(SSH_AGENTC_SIGN_REQUEST);
+        write_bytes(&mut request, key_blob);
+        write_bytes(&mut request, data);
+        write_u32(&mut request, flags);
+        let sent = self.send_message(&request);
+        zeroize::wipe(&mut request);
+        sent?;
+
+        let signature = {
+            let response = self.read_message()?;
+            match response.first() {
+                Some(&SSH_AGENT_SIGN_RESPONSE) => {}
+                Some(&SSH_AGENT_FAILURE) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        "agent refused to sign (request may have been rejected on the phone)",
+                    ));
+                }
+                _ => return Err(protocol_error("expected SSH_AGENT_SIGN_RESPONSE")),
+            }
+            let (signature, _) = read_bytes_at(response, 1)
+                .ok_or_else(|| protocol_error("truncated signature response"))?;
+            signature
+        };
+        // The copy we parsed from is no longer needed.
+        self.scratch.clear();
+        // signature := string format || string blob
+        let parsed = read_string_at(&signature, 0).and_then(|(format, next)| {
+            read_bytes_at(&signature, next).map(|(blob, _)| (format, blob))
+        });
+        let mut signature = signature;
+        let result = match parsed {
+            Some((format, blob)) => Ok(signature_bytes(&format, blob)),
+            None => Err(protocol_error("malformed signature envelope")),
+        };
+        zeroize::wipe(&mut signature);
+        result
+    }
+
+    fn send_message(&mut self, payload: &[u8]) -> io::Result<()> {
+        let mut framed = Vec::with_capacity(4 + payload.len());
+        write_u32(&mut framed, payload.len() as u32);
+        framed.extend_from_slice(payload);
+        let result = self.stream.write_all(&framed);
+        // The frame may embed the data being signed.
+        zeroize::wipe(&mut framed);
+        result
+    }
+
+    fn read_message(&mut self) -> io::Result<&[u8]> {
+        let mut len_buf = [0u8; 4];
+        self.stream.read_exact(&mut len_buf)?;
+        let len = u32_from_be(&len_buf) as usize;
+        match self.scratch.fill(len) {
+            Some(payload) => self.stream.read_exact(payload)?,
+            None => return Err(protocol_error("agent message too large")),
+        }
+        Ok(self.scratch.contents())
+    }
+}
+
+/// A typed signing request routed through the agent socket.
+#[derive(Clone, Debug)]
+pub struct SignRequest {
+    pub key_blob: Vec<u8>,
+    pub data: Vec<u8>,
+    pub flags: u32,
+    pub metadata: Option<SignMetadata>,
+}
+
+impl Drop for SignRequest {
+    fn drop(&mut self) {
+        // The payload can embed TLS handshake secrets and the like.
+        zeroize::wipe(&mut self.data);
+    }
+}
+
+/// The identities the agent currently exposes.
+#[derive(Clone, Debug, Default)]
+pub struct IdentityList {
+    pub identities: Vec<Identity>,
+}
+
+/// Agent failures, separated so callers can distinguish a rejection on
+/// the phone from a dead socket (worth a reconnect) or a garbled reply.
+#[derive(Debug)]
+pub enum Error {
+    /// The agent answered SSH_AGENT_FAILURE: the request was refused.
+    Refused,
+    /// The socket is gone or unreadable; reconnecting may help.
+    Transport(io::Error),
+    /// The agent answered something that is not the protocol.
+    Protocol(io::Error),
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Refused => io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "agent refused the request",
+            ),
+            Error::Transport(e) | Error::Protocol(e) => e,
+        }
+    }
+}
+
+fn classify(e: io::Error) -> Error {
+    match e.kind() {
+        io::ErrorKind::PermissionDenied => Error::Refused,
+        io::ErrorKind::InvalidData => Error::Protocol(e),
+        _ => Error::Transport(e),
+    }
+}
+
+/// A reusable agent client: keeps one connection open across calls and
+/// retries once on transport errors, which covers krd restarting
+/// between requests. All sign/find paths in the shim go through this.
+pub struct AgentClient {
+    conn: Option<AgentConn>,
+}
+
+impl AgentClient {
+    pub fn new() -> AgentClient {
+        AgentClient { conn: None }
+    }
+
+    fn conn(&mut self) -> io::Result<&mut AgentConn> {
+        if self.conn.is_none() {
+            self.conn = Some(AgentConn::connect()?);
+        }
+        Ok(self.conn.as_mut().unwrap())
+    }
+
+    fn retry<T, F>(&mut self, mut call: F) -> Result<T, Error>
+    where
+        F: FnMut(&mut AgentConn) -> io::Result<T>,
+    {
+        let first = self.conn().and_then(|conn| call(conn));
+        match first {
+            Ok(value) => Ok(value),
+            Err(e) => match classify(e) {
+                Error::Transport(_) => {
+                    // stale socket, most likely a krd restart; reconnect
+                    // and try once more
+                    self.conn = None;
+                    self.conn().and_then(|conn| call(conn)).map_err(classify)
+                }
+                other => Err(other),
+            },
+        }
+    }
+
+    pub fn identities(&mut self) -> Result<IdentityList, Error> {
+        self.retry(|conn| conn.request_identities())
+            .map(|identities| IdentityList {
+                identities: identities,
+            })
+    }
+
+    pub fn sign(&mut self, request: &SignRequest) -> Result<Vec<u8>, Error> {
+        self.retry(|conn| {
+            if let Some(ref metadata) = request.metadata {
+                // Best-effort: the prompt just stays generic without it.
+                if let Err(e) = conn.send_metadata(metadata) {
+                    notice!("could not send caller metadata: {}", e);
+                }
+            }
+            conn.sign(&request.key_blob, &request.data, request.flags)
+        })
+    }
+}
+
+/// Converts an SSH wire signature blob into the raw signature bytes PKCS#11
+/// callers expect: RSA and Ed25519 blobs pass through unchanged, ECDSA
+/// (r, s) mpints become fixed-width `r || s`.
+fn signature_bytes(format: &str, blob: Vec<u8>) -> Vec<u8> {
+    if !format.starts_with("ecdsa-") {
+        return blob;
+    }
+    let mut blob = blob;
+    let parsed = read_bytes_at(&blob, 0).and_then(|(r, next)| {
+        read_bytes_at(&blob, next).map(|(s, _)| (r, s))
+    });
+    let (r, s) = match parsed {
+        Some(rs) => rs,
+        None => return blob,
+    };
+    zeroize::wipe(&mut blob);
+    let r = strip_mpint_padding(&r);
+    let s = strip_mpint_padding(&s);
+    let width = ::std::cmp::max(r.len(), s.len());
+    let mut out = vec![0u8; 2 * width];
+    out[width - r.len()..width].copy_from_slice(r);
+    out[2 * width - s.len()..].copy_from_slice(s);
+    out
+}
+
+pub fn strip_mpint_padding(bytes: &[u8]) -> &[u8] {
+    let mut start = 0;
+    while start < bytes.len().saturating_sub(1) && bytes[start] == 0 {
+        start += 1;
+    }
+    &bytes[start..]
+}
+
+fn protocol_error(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&[
+        (value >> 24) as u8,
+        (value >> 16) as u8,
+        (value >> 8) as u8,
+        value as u8,
+    ]);
+}
+
+pub fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_u32(out, bytes.len() as u32);
+    out.extend_from_slice(bytes);
+}
+
+fn u32_from_be(buf: &[u8; 4]) -> u32 {
+    ((buf[0] as u32) << 24) | ((buf[1] as u32) << 16) | ((buf[2] as u32) << 8) | (buf[3] as u32)
+}
+
+pub fn read_u32_at(buf: &[u8], offset: usize) -> Option<(u32, usize)> {
+    if buf.len() < offset + 4 {
+        return None;
+    }
+    let mut word = [0u8; 4];
+    word.copy_from_slice(&buf[offset..offset + 4]);
+    Some((u32_from_be(&word), offset + 4))
+}
+
+pub fn read_bytes_at(buf: &[u8], offset: usize) -> Option<(Vec<u8>, usize)> {
+    let (len, start) = read_u32_at(buf, offset)?;
+    let end = start.checked_add(len as usize)?;
+    if buf.len() < end {
+        return None;
+    }
+    Some((buf[start..end].to_vec(), end))
+}
+
+pub fn read_string_at(buf: &[u8], offset: usize) -> Option<(String, usize)> {
+    let (bytes, next) = read_bytes_at(buf, offset)?;
+    String::from_utf8(bytes).ok().map(|s| (s, next))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_parsing() {
+        assert_eq!(
+            Endpoint::parse("unix:///tmp/agent.sock"),
+            Some(Endpoint::Unix(PathBuf::from("/tmp/agent.sock")))
+        );
+        assert_eq!(
+            Endpoint::parse("tcp://krd.internal:9023"),
+            Some(Endpoint::Tcp("krd.internal:9023".to_owned()))
+        );
+        // bare paths keep working for existing override scripts
+        assert_eq!(
+            Endpoint::parse("/tmp/agent.sock"),
+            Some(Endpoint::Unix(PathBuf::from("/tmp/agent.sock")))
+        );
+        assert_eq!(Endpoint::parse(""), None);
+        assert_eq!(Endpoint::parse("unix://"), None);
+        assert_eq!(Endpoint::parse("tcp://no-port"), None);
+        assert_eq!(Endpoint::parse("ftp://x:1"), None);
+    }
+}
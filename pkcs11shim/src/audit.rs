@@ -0,0 +1,102 @@
+//! Append-only audit log of key operations.
+//!
+//! Every C_Sign and C_Login attempt is recorded as one JSON line in
+//! `~/.kr/pkcs11-audit.log` so security teams can review what used the
+//! Krypton key through PKCS#11. The log rotates once to `.1` when it
+//! exceeds `MAX_LOG_SIZE`. Audit failures are logged but never fail the
+//! operation being audited.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::digest;
+
+use caller::CallerInfo;
+use pkcs11::{CK_MECHANISM_TYPE, CK_RV, CK_USER_TYPE, CKR_OK};
+use pkcs11shim::kr_path;
+use spy;
+
+pub const AUDIT_LOG_FILENAME: &'static str = "pkcs11-audit.log";
+
+/// Rotate once the log grows past this many bytes.
+pub const MAX_LOG_SIZE: u64 = 1024 * 1024;
+
+/// Records a C_Sign attempt. `data` is hashed, never logged raw.
+pub fn record_sign(mechanism: CK_MECHANISM_TYPE, data: &[u8], result: CK_RV) {
+    let digest_hex = hex(digest::digest(&digest::SHA256, data).as_ref());
+    let caller = CallerInfo::current();
+    append(&format!(
+        "{{\"time\":{},\"event\":\"sign\",\"process\":\"{}\",\"pid\":{},\"mechanism\":{},\"digest_sha256\":\"{}\",\"result\":{},\"result_name\":\"{}\",\"ok\":{}}}",
+        unix_time(),
+        escape(caller.name()),
+        caller.pid,
+        mechanism,
+        digest_hex,
+        result,
+        spy::ckr_name(result),
+        result == CKR_OK
+    ));
+}
+
+/// Records a C_Login attempt.
+pub fn record_login(user_type: CK_USER_TYPE, result: CK_RV) {
+    let caller = CallerInfo::current();
+    append(&format!(
+        "{{\"time\":{},\"event\":\"login\",\"process\":\"{}\",\"pid\":{},\"user_type\":{},\"result\":{},\"result_name\":\"{}\",\"ok\":{}}}",
+        unix_time(),
+        escape(caller.name()),
+        caller.pid,
+        user_type,
+        result,
+        spy::ckr_name(result),
+        result == CKR_OK
+    ));
+}
+
+fn append(line: &str) {
+    if let Err(e) = try_append(line) {
+        error!("audit: could not write {}: {}", AUDIT_LOG_FILENAME, e);
+    }
+}
+
+fn try_append(line: &str) -> io::Result<()> {
+    let path = kr_path(AUDIT_LOG_FILENAME);
+    if let Ok(metadata) = fs::metadata(&path) {
+        if metadata.len() >= MAX_LOG_SIZE {
+            let mut rotated = path.clone();
+            rotated.set_extension("log.1");
+            fs::rename(&path, &rotated)?;
+        }
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", line)
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        out.push_str(&format!("{:02x}", byte));
+    }
+    out
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
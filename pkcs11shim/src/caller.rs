@@ -0,0 +1,78 @@
+//! Identity of the host process the shim is loaded into.
+//!
+//! The shim runs inside arbitrary applications; knowing which one is asking
+//! for a signature lets the phone show "Firefox wants to sign" instead of a
+//! generic prompt, and gives the audit log a meaningful process field.
+
+use std::env;
+use std::path::Path;
+
+use libc;
+
+/// The calling application's executable path and pid.
+#[derive(Clone, Debug)]
+pub struct CallerInfo {
+    pub path: String,
+    pub pid: i32,
+}
+
+impl CallerInfo {
+    pub fn current() -> CallerInfo {
+        CallerInfo {
+            path: executable_path().unwrap_or_else(|| "unknown".to_owned()),
+            pid: unsafe { libc::getpid() },
+        }
+    }
+
+    /// The executable's base name, e.g. "firefox".
+    pub fn name(&self) -> &str {
+        Path::new(&self.path)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(&self.path)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn executable_path() -> Option<String> {
+    use std::fs;
+    fs::read_link("/proc/self/exe")
+        .ok()
+        .and_then(|path| path.to_str().map(|s| s.to_owned()))
+        .or_else(fallback_path)
+}
+
+#[cfg(target_os = "macos")]
+fn executable_path() -> Option<String> {
+    use libc::{c_int, c_void};
+    extern "C" {
+        // from libproc
+        fn proc_pidpath(pid: c_int, buffer: *mut c_void, buffersize: u32) -> c_int;
+    }
+    let mut buffer = [0u8; 4096];
+    let len = unsafe {
+        proc_pidpath(
+            libc::getpid(),
+            buffer.as_mut_ptr() as *mut c_void,
+            buffer.len() as u32,
+        )
+    };
+    if len > 0 {
+        String::from_utf8(buffer[..len as usize].to_vec())
+            .ok()
+            .or_else(fallback_path)
+    } else {
+        fallback_path()
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn executable_path() -> Option<String> {
+    fallback_path()
+}
+
+fn fallback_path() -> Option<String> {
+    env::current_exe()
+        .ok()
+        .and_then(|path| path.to_str().map(|s| s.to_owned()))
+}
@@ -0,0 +1,113 @@
+//! Optional per-user configuration from `~/.kr/pkcs11.toml`.
+//!
+//! With several Krypton identities paired, applications pick a key by
+//! putting a `CKA_LABEL`/`CKA_ID` in their `C_FindObjects` template. The
+//! `[labels]` table maps such an application-supplied label to a pattern
+//! matched against identity comments, so different TLS clients can be
+//! routed to different keys:
+//!
+//! ```toml
+//! [labels]
+//! github = "alice@github"
+//! work = "*@corp.example.com"
+//! ```
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use toml;
+
+use pkcs11shim::kr_path;
+
+pub const CONFIG_FILE: &'static str = "pkcs11.toml";
+
+#[derive(Deserialize, Default)]
+pub struct Config {
+    /// Application label -> identity comment pattern (`*` is a wildcard).
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+}
+
+lazy_static! {
+    pub static ref CONFIG: Config = load();
+}
+
+fn load() -> Config {
+    let path = kr_path(CONFIG_FILE);
+    let mut contents = String::new();
+    match File::open(&path).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => {}
+        // Absent config is the common case and means no routing.
+        Err(_) => return Config::default(),
+    }
+    match toml::from_str(&contents) {
+        Ok(config) => config,
+        Err(e) => {
+            warning!("could not parse {}: {}", path.display(), e);
+            Config::default()
+        }
+    }
+}
+
+impl Config {
+    /// The pattern identities must match for an application-supplied
+    /// label: the configured routing entry if there is one, else the
+    /// label itself taken literally.
+    pub fn pattern_for_label<'a>(&'a self, label: &'a str) -> &'a str {
+        match self.labels.get(label) {
+            Some(pattern) => pattern,
+            None => label,
+        }
+    }
+}
+
+/// Glob-style matching where `*` matches any (possibly empty) substring.
+pub fn matches(pattern: &str, value: &str) -> bool {
+    matches_bytes(pattern.as_bytes(), value.as_bytes())
+}
+
+fn matches_bytes(pattern: &[u8], value: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => value.is_empty(),
+        Some((&b'*', rest)) => (0..value.len() + 1).any(|i| matches_bytes(rest, &value[i..])),
+        Some((&c, rest)) => match value.split_first() {
+            Some((&d, value_rest)) => c == d && matches_bytes(rest, value_rest),
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_matching() {
+        assert!(matches("alice@github", "alice@github"));
+        assert!(matches("*@corp.example.com", "alice@corp.example.com"));
+        assert!(matches("*", ""));
+        assert!(matches("a*c*", "abcd"));
+        assert!(!matches("alice@github", "alice@gitlab"));
+        assert!(!matches("*@corp.example.com", "alice@example.com"));
+    }
+
+    #[test]
+    fn labels_route_through_config() {
+        let mut config = Config::default();
+        config
+            .labels
+            .insert("github".to_owned(), "alice@github".to_owned());
+        assert_eq!(config.pattern_for_label("github"), "alice@github");
+        // Unconfigured labels match identity comments literally.
+        assert_eq!(config.pattern_for_label("work"), "work");
+    }
+
+    #[test]
+    fn parses_labels_table() {
+        let config: Config = toml::from_str("[labels]\ngithub = \"alice@github\"\n").unwrap();
+        assert_eq!(config.labels.get("github").unwrap(), "alice@github");
+        let empty: Config = toml::from_str("").unwrap();
+        assert!(empty.labels.is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+//! The shim's logging facade.
+//!
+//! The `notice!`/`warning!`/`error!` macros used to expand to statements
+//! that only compiled if `logger` and `std::io::{stderr, Write}` were
+//! imported at the call site. They now delegate here through absolute
+//! paths, so call sites need no imports and every level maps to one
+//! severity in one place. Each message goes to syslog (through the
+//! reconnecting facade), the host's stderr, and the `KR_PKCS11_SPY`
+//! trace when one is configured.
+
+use std::io::{self, Write};
+
+use syslog;
+
+use pkcs11_unused::logger;
+use spy;
+
+/// The shim's logging levels, in increasing severity.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Notice,
+    Warning,
+    Error,
+}
+
+/// Whether `level` is compiled in: the `log-level-error` feature keeps
+/// only errors, `log-level-warning` keeps warnings and errors, the
+/// default keeps everything. `cfg!` makes this a constant, so gated-out
+/// call sites never even format their message.
+pub fn enabled(level: Level) -> bool {
+    if cfg!(feature = "log-level-error") {
+        level >= Level::Error
+    } else if cfg!(feature = "log-level-warning") {
+        level >= Level::Warning
+    } else {
+        true
+    }
+}
+
+/// Records one message everywhere the shim logs. Failures are absorbed:
+/// the shim must never take down the host application over a logging
+/// problem.
+pub fn log(level: Level, message: &str) {
+    let severity = match level {
+        Level::Notice => syslog::Severity::LOG_NOTICE,
+        Level::Warning => syslog::Severity::LOG_WARNING,
+        Level::Error => syslog::Severity::LOG_ERR,
+    };
+    let _ = logger.send(severity, message);
+    let _ = writeln!(io::stderr(), "kr-pkcs11: {}", message);
+    if spy::enabled() {
+        spy::trace(message);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_build_keeps_every_level() {
+        if cfg!(any(feature = "log-level-warning", feature = "log-level-error")) {
+            return;
+        }
+        assert!(enabled(Level::Notice));
+        assert!(enabled(Level::Warning));
+        assert!(enabled(Level::Error));
+    }
+
+    #[test]
+    fn errors_are_never_gated_out() {
+        assert!(enabled(Level::Error));
+    }
+}
@@ -0,0 +1,144 @@
+//! kr-pkcs11: a PKCS#11 module backed by the paired Krypton phone.
+//!
+//! Applications load this library and talk standard Cryptoki; the shim
+//! forwards signing operations to krd over the ssh-agent protocol, which
+//! routes them to the phone for approval. Only `C_GetFunctionList` is
+//! exported by name, as the spec prescribes.
+
+// The Cryptoki ABI fixes every entry point's signature as a safe
+// `extern "C" fn` taking raw host pointers; marking them `unsafe` would
+// change the function-list types. Each pointer is null/alignment-checked
+// before use instead.
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+
+extern crate base64;
+extern crate krlogging;
+#[macro_use]
+extern crate lazy_static;
+extern crate libc;
+extern crate ring;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate syslog;
+extern crate toml;
+extern crate untrusted;
+extern crate users;
+
+#[macro_use]
+mod macros;
+
+pub mod agent;
+pub mod audit;
+pub mod caller;
+pub mod config;
+pub mod kr_log;
+pub mod logger;
+pub mod pairing;
+pub mod pkcs11;
+pub mod pkcs11shim;
+pub mod pkcs11_unused;
+pub mod rsa;
+pub mod soft;
+pub mod spy;
+pub mod stderr_tee;
+pub mod utils;
+pub mod zeroize;
+
+use pkcs11::*;
+use pkcs11shim::*;
+use pkcs11_unused::*;
+
+// The notification relay lives in the shared krlogging crate (without
+// stdout detection, which a PKCS#11 module must not do); re-exporting
+// keeps its `Init`/`Deinit` symbols in this cdylib too.
+pub use krlogging::{Deinit, Init};
+
+static FUNCTION_LIST: CK_FUNCTION_LIST = CK_FUNCTION_LIST {
+    version: CK_VERSION { major: 2, minor: 20 },
+    C_Initialize: Some(CK_C_Initialize),
+    C_Finalize: Some(CK_C_Finalize),
+    C_GetInfo: Some(CK_C_GetInfo),
+    C_GetFunctionList: Some(C_GetFunctionList),
+    C_GetSlotList: Some(CK_C_GetSlotList),
+    C_GetSlotInfo: Some(CK_C_GetSlotInfo),
+    C_GetTokenInfo: Some(CK_C_GetTokenInfo),
+    C_GetMechanismList: Some(CK_C_GetMechanismList),
+    C_GetMechanismInfo: Some(CK_C_GetMechanismInfo),
+    C_InitToken: Some(CK_C_InitToken),
+    C_InitPIN: Some(CK_C_InitPIN),
+    C_SetPIN: Some(CK_C_SetPIN),
+    C_OpenSession: Some(CK_C_OpenSession),
+    C_CloseSession: Some(CK_C_CloseSession),
+    C_CloseAllSessions: Some(CK_C_CloseAllSessions),
+    C_GetSessionInfo: Some(CK_C_GetSessionInfo),
+    C_GetOperationState: Some(CK_C_GetOperationState),
+    C_SetOperationState: Some(CK_C_SetOperationState),
+    C_Login: Some(CK_C_Login),
+    C_Logout: Some(CK_C_Logout),
+    C_CreateObject: Some(CK_C_CreateObject),
+    C_CopyObject: Some(CK_C_CopyObject),
+    C_DestroyObject: Some(CK_C_DestroyObject),
+    C_GetObjectSize: Some(CK_C_GetObjectSize),
+    C_GetAttributeValue: Some(CK_C_GetAttributeValue),
+    C_SetAttributeValue: Some(CK_C_SetAttributeValue),
+    C_FindObjectsInit: Some(CK_C_FindObjectsInit),
+    C_FindObjects: Some(CK_C_FindObjects),
+    C_FindObjectsFinal: Some(CK_C_FindObjectsFinal),
+    C_EncryptInit: Some(CK_C_EncryptInit),
+    C_Encrypt: Some(CK_C_Encrypt),
+    C_EncryptUpdate: Some(CK_C_EncryptUpdate),
+    C_EncryptFinal: Some(CK_C_EncryptFinal),
+    C_DecryptInit: Some(CK_C_DecryptInit),
+    C_Decrypt: Some(CK_C_Decrypt),
+    C_DecryptUpdate: Some(CK_C_DecryptUpdate),
+    C_DecryptFinal: Some(CK_C_DecryptFinal),
+    C_DigestInit: Some(CK_C_DigestInit),
+    C_Digest: Some(CK_C_Digest),
+    C_DigestUpdate: Some(CK_C_DigestUpdate),
+    C_DigestKey: Some(CK_C_DigestKey),
+    C_DigestFinal: Some(CK_C_DigestFinal),
+    C_SignInit: Some(CK_C_SignInit),
+    C_Sign: Some(CK_C_Sign),
+    C_SignUpdate: Some(CK_C_SignUpdate),
+    C_SignFinal: Some(CK_C_SignFinal),
+    C_SignRecoverInit: Some(CK_C_SignRecoverInit),
+    C_SignRecover: Some(CK_C_SignRecover),
+    C_VerifyInit: Some(CK_C_VerifyInit),
+    C_Verify: Some(CK_C_Verify),
+    C_VerifyUpdate: Some(CK_C_VerifyUpdate),
+    C_VerifyFinal: Some(CK_C_VerifyFinal),
+    C_VerifyRecoverInit: Some(CK_C_VerifyRecoverInit),
+    C_VerifyRecover: Some(CK_C_VerifyRecover),
+    C_DigestEncryptUpdate: Some(CK_C_DigestEncryptUpdate),
+    C_DecryptDigestUpdate: Some(CK_C_DecryptDigestUpdate),
+    C_SignEncryptUpdate: Some(CK_C_SignEncryptUpdate),
+    C_DecryptVerifyUpdate: Some(CK_C_DecryptVerifyUpdate),
+    C_GenerateKey: Some(CK_C_GenerateKey),
+    C_GenerateKeyPair: Some(CK_C_GenerateKeyPair),
+    C_WrapKey: Some(CK_C_WrapKey),
+    C_UnwrapKey: Some(CK_C_UnwrapKey),
+    C_DeriveKey: Some(CK_C_DeriveKey),
+    C_SeedRandom: Some(CK_C_SeedRandom),
+    C_GenerateRandom: Some(CK_C_GenerateRandom),
+    C_GetFunctionStatus: Some(CK_C_GetFunctionStatus),
+    C_CancelFunction: Some(CK_C_CancelFunction),
+    C_WaitForSlotEvent: Some(CK_C_WaitForSlotEvent),
+};
+
+#[no_mangle]
+pub extern "C" fn C_GetFunctionList(ppFunctionList: CK_FUNCTION_LIST_PTR_PTR) -> CK_RV {
+    unsafe {
+        *ppFunctionList = &FUNCTION_LIST as *const CK_FUNCTION_LIST as CK_FUNCTION_LIST_PTR;
+    }
+    CKR_OK
+}
+
+/// Returns a static NUL-terminated build string, e.g.
+/// "kr-pkcs11 1.0.0 (a1b2c3d)", so support can correlate user reports
+/// with the build actually installed.
+#[no_mangle]
+pub extern "C" fn KR_GetBuildInfo() -> *const libc::c_char {
+    BUILD_INFO.as_ptr() as *const libc::c_char
+}
@@ -0,0 +1,177 @@
+//! A reconnecting facade over the shared syslog logger.
+//!
+//! The lazy_static logger used to connect exactly once, so if syslog
+//! came up after the host application (common when both start at boot)
+//! logging was lost for the life of the process. This facade retries
+//! failed connections with backoff and keeps the most recent messages
+//! queued while disconnected, flushing them once a connection succeeds.
+//! As everywhere in the shim, logging failures are absorbed, never
+//! surfaced to the host.
+
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use syslog;
+
+/// Messages kept while disconnected; the oldest are dropped first once
+/// the queue is full.
+const PENDING_CAPACITY: usize = 64;
+
+const INITIAL_BACKOFF_SECS: u64 = 1;
+const MAX_BACKOFF_SECS: u64 = 60;
+
+/// How the facade (re)connects; kept as a plain fn so the lazy_static
+/// holding the facade stays `Sync` for free.
+type Connect = fn() -> io::Result<Arc<syslog::Logger>>;
+
+pub struct ReconnectingLogger {
+    connect: Connect,
+    state: Mutex<State>,
+}
+
+struct State {
+    logger: Option<Arc<syslog::Logger>>,
+    pending: VecDeque<(syslog::Severity, String)>,
+    next_attempt: Instant,
+    backoff_secs: u64,
+}
+
+impl ReconnectingLogger {
+    pub fn new(connect: Connect) -> ReconnectingLogger {
+        ReconnectingLogger {
+            connect: connect,
+            state: Mutex::new(State {
+                logger: None,
+                pending: VecDeque::new(),
+                next_attempt: Instant::now(),
+                backoff_secs: INITIAL_BACKOFF_SECS,
+            }),
+        }
+    }
+
+    pub fn notice(&self, message: &str) -> io::Result<()> {
+        self.send(syslog::Severity::LOG_NOTICE, message)
+    }
+
+    pub fn warn(&self, message: &str) -> io::Result<()> {
+        self.send(syslog::Severity::LOG_WARNING, message)
+    }
+
+    pub fn err(&self, message: &str) -> io::Result<()> {
+        self.send(syslog::Severity::LOG_ERR, message)
+    }
+
+    pub fn send(&self, severity: syslog::Severity, message: &str) -> io::Result<()> {
+        // a thread that panicked mid-log must not silence every thread
+        // after it, so a poisoned lock is taken anyway
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        self.ensure_connected(&mut state);
+        let logger = match state.logger.clone() {
+            Some(logger) => logger,
+            None => {
+                queue(&mut state, severity, message);
+                return Err(io::Error::new(
+                    io::ErrorKind::NotConnected,
+                    "syslog not reachable; message queued",
+                ));
+            }
+        };
+        match logger.send(severity, message) {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                // the connection went away under us: queue this message
+                // and start over with a fresh backoff
+                self.disconnect(&mut state);
+                queue(&mut state, severity, message);
+                Err(e)
+            }
+        }
+    }
+
+    fn ensure_connected(&self, state: &mut State) {
+        if state.logger.is_some() || Instant::now() < state.next_attempt {
+            return;
+        }
+        let logger = match (self.connect)() {
+            Ok(logger) => logger,
+            Err(_) => {
+                self.schedule_retry(state);
+                return;
+            }
+        };
+        // drain what queued up while disconnected, oldest first, before
+        // letting new messages through
+        while let Some((severity, message)) = state.pending.pop_front() {
+            if logger.send(severity, &message).is_err() {
+                state.pending.push_front((severity, message));
+                self.schedule_retry(state);
+                return;
+            }
+        }
+        state.backoff_secs = INITIAL_BACKOFF_SECS;
+        state.logger = Some(logger);
+    }
+
+    fn disconnect(&self, state: &mut State) {
+        state.logger = None;
+        state.backoff_secs = INITIAL_BACKOFF_SECS;
+        state.next_attempt = Instant::now() + Duration::from_secs(INITIAL_BACKOFF_SECS);
+    }
+
+    fn schedule_retry(&self, state: &mut State) {
+        state.next_attempt = Instant::now() + Duration::from_secs(state.backoff_secs);
+        state.backoff_secs = (state.backoff_secs * 2).min(MAX_BACKOFF_SECS);
+    }
+}
+
+fn queue(state: &mut State, severity: syslog::Severity, message: &str) {
+    if state.pending.len() == PENDING_CAPACITY {
+        state.pending.pop_front();
+    }
+    state.pending.push_back((severity, message.to_owned()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unreachable_syslog() -> io::Result<Arc<syslog::Logger>> {
+        Err(io::Error::new(io::ErrorKind::NotFound, "no syslog here"))
+    }
+
+    fn null_syslog() -> io::Result<Arc<syslog::Logger>> {
+        syslog::null(syslog::Facility::LOG_USER).map(Arc::from)
+    }
+
+    #[test]
+    fn failures_never_panic_and_queue_stays_bounded() {
+        let logger = ReconnectingLogger::new(unreachable_syslog);
+        for i in 0..1_000 {
+            assert!(logger.notice(&format!("message {}", i)).is_err());
+        }
+        let state = logger.state.lock().unwrap();
+        assert!(state.pending.len() <= PENDING_CAPACITY);
+        assert!(state.logger.is_none());
+    }
+
+    #[test]
+    fn queued_messages_flush_on_connect() {
+        let logger = ReconnectingLogger::new(null_syslog);
+        logger.state.lock().unwrap().logger = None;
+        logger
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .push_back((syslog::Severity::LOG_NOTICE, "early".to_owned()));
+        assert!(logger.notice("later").is_ok());
+        let state = logger.state.lock().unwrap();
+        assert!(state.pending.is_empty());
+        assert!(state.logger.is_some());
+    }
+}
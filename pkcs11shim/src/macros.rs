@@ -0,0 +1,41 @@
+//! Logging macros for the shim.
+//!
+//! Thin wrappers over the `kr_log` facade: hygienic (no imports needed
+//! at the call site), gated at compile time through the `log-level-*`
+//! features, and mirrored to the `KR_PKCS11_SPY` trace when one is
+//! configured. Failures to log are ignored: the shim must never take
+//! down the host application over a logging problem.
+
+macro_rules! notice {
+    ($($arg:tt)*) => {{
+        if ::kr_log::enabled(::kr_log::Level::Notice) {
+            ::kr_log::log(::kr_log::Level::Notice, &format!($($arg)*));
+        }
+    }};
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        if ::kr_log::enabled(::kr_log::Level::Warning) {
+            ::kr_log::log(::kr_log::Level::Warning, &format!($($arg)*));
+        }
+    }};
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        if ::kr_log::enabled(::kr_log::Level::Error) {
+            ::kr_log::log(::kr_log::Level::Error, &format!($($arg)*));
+        }
+    }};
+}
+
+/// Records a decoded call in the `KR_PKCS11_SPY` trace when tracing is
+/// enabled; the format is paid for only when it is.
+macro_rules! spy {
+    ($($arg:tt)*) => {{
+        if ::spy::enabled() {
+            ::spy::trace(&format!($($arg)*));
+        }
+    }};
+}
@@ -0,0 +1,46 @@
+//! Pairing-state detection.
+//!
+//! krd persists the phone pairing as `~/.kr/pairing.json`; `kr unpair`
+//! deletes it. The shim checks for it on each operation so that a token is
+//! only reported while a phone is actually paired, instead of pretending
+//! one is always present.
+
+use std::fs;
+
+use serde_json;
+
+use pkcs11shim::kr_path;
+use soft;
+
+/// Written by krd; see src/common/persistance in the Go tree.
+pub const PAIRING_FILENAME: &'static str = "pairing.json";
+
+/// Whether a phone is currently paired. The soft token counts as paired:
+/// its key is always available.
+pub fn paired() -> bool {
+    if soft::enabled() {
+        return true;
+    }
+    match fs::metadata(kr_path(PAIRING_FILENAME)) {
+        Ok(metadata) => metadata.is_file() && metadata.len() > 0,
+        Err(_) => false,
+    }
+}
+
+/// Whether the paired device asks the user to approve every request. krd
+/// mirrors the phone's policy into pairing.json as
+/// `require_manual_approval`; absent (older krd) means auto-approval may
+/// be in effect.
+pub fn requires_manual_approval() -> bool {
+    if soft::enabled() {
+        return false;
+    }
+    let contents = match fs::read_to_string(kr_path(PAIRING_FILENAME)) {
+        Ok(contents) => contents,
+        Err(_) => return false,
+    };
+    serde_json::from_str::<serde_json::Value>(&contents)
+        .ok()
+        .and_then(|pairing| pairing["require_manual_approval"].as_bool())
+        .unwrap_or(false)
+}
@@ -0,0 +1,509 @@
+//! PKCS#11 v2.20 types and constants.
+//!
+//! Only the subset of Cryptoki that the shim actually touches is defined
+//! here; the layouts follow the official headers (`pkcs11t.h`) with
+//! `CK_ULONG` mapped to the platform `unsigned long` as the spec requires
+//! on Unix platforms.
+
+#![allow(non_camel_case_types, non_snake_case, dead_code)]
+
+use libc::c_void;
+
+pub type CK_BYTE = u8;
+pub type CK_CHAR = CK_BYTE;
+pub type CK_UTF8CHAR = CK_BYTE;
+pub type CK_BBOOL = CK_BYTE;
+pub type CK_ULONG = usize;
+pub type CK_LONG = isize;
+pub type CK_FLAGS = CK_ULONG;
+pub type CK_RV = CK_ULONG;
+pub type CK_SLOT_ID = CK_ULONG;
+pub type CK_SESSION_HANDLE = CK_ULONG;
+pub type CK_OBJECT_HANDLE = CK_ULONG;
+pub type CK_OBJECT_CLASS = CK_ULONG;
+pub type CK_KEY_TYPE = CK_ULONG;
+pub type CK_ATTRIBUTE_TYPE = CK_ULONG;
+pub type CK_MECHANISM_TYPE = CK_ULONG;
+pub type CK_USER_TYPE = CK_ULONG;
+pub type CK_STATE = CK_ULONG;
+pub type CK_NOTIFICATION = CK_ULONG;
+
+pub type CK_VOID_PTR = *mut c_void;
+pub type CK_BYTE_PTR = *mut CK_BYTE;
+pub type CK_UTF8CHAR_PTR = *mut CK_UTF8CHAR;
+pub type CK_ULONG_PTR = *mut CK_ULONG;
+pub type CK_SLOT_ID_PTR = *mut CK_SLOT_ID;
+pub type CK_SESSION_HANDLE_PTR = *mut CK_SESSION_HANDLE;
+pub type CK_OBJECT_HANDLE_PTR = *mut CK_OBJECT_HANDLE;
+pub type CK_MECHANISM_TYPE_PTR = *mut CK_MECHANISM_TYPE;
+pub type CK_INFO_PTR = *mut CK_INFO;
+pub type CK_SLOT_INFO_PTR = *mut CK_SLOT_INFO;
+pub type CK_TOKEN_INFO_PTR = *mut CK_TOKEN_INFO;
+pub type CK_SESSION_INFO_PTR = *mut CK_SESSION_INFO;
+pub type CK_ATTRIBUTE_PTR = *mut CK_ATTRIBUTE;
+pub type CK_MECHANISM_PTR = *mut CK_MECHANISM;
+pub type CK_MECHANISM_INFO_PTR = *mut CK_MECHANISM_INFO;
+pub type CK_FUNCTION_LIST_PTR = *mut CK_FUNCTION_LIST;
+pub type CK_FUNCTION_LIST_PTR_PTR = *mut CK_FUNCTION_LIST_PTR;
+
+pub const CK_TRUE: CK_BBOOL = 1;
+pub const CK_FALSE: CK_BBOOL = 0;
+
+pub const CK_UNAVAILABLE_INFORMATION: CK_ULONG = !0;
+pub const CK_INVALID_HANDLE: CK_OBJECT_HANDLE = 0;
+
+// Return values
+pub const CKR_OK: CK_RV = 0x0000;
+pub const CKR_CANCEL: CK_RV = 0x0001;
+pub const CKR_HOST_MEMORY: CK_RV = 0x0002;
+pub const CKR_SLOT_ID_INVALID: CK_RV = 0x0003;
+pub const CKR_GENERAL_ERROR: CK_RV = 0x0005;
+pub const CKR_FUNCTION_FAILED: CK_RV = 0x0006;
+pub const CKR_ARGUMENTS_BAD: CK_RV = 0x0007;
+pub const CKR_NO_EVENT: CK_RV = 0x0008;
+pub const CKR_ATTRIBUTE_SENSITIVE: CK_RV = 0x0011;
+pub const CKR_ATTRIBUTE_TYPE_INVALID: CK_RV = 0x0012;
+pub const CKR_ATTRIBUTE_VALUE_INVALID: CK_RV = 0x0013;
+pub const CKR_DATA_INVALID: CK_RV = 0x0020;
+pub const CKR_DATA_LEN_RANGE: CK_RV = 0x0021;
+pub const CKR_DEVICE_ERROR: CK_RV = 0x0030;
+pub const CKR_DEVICE_MEMORY: CK_RV = 0x0031;
+pub const CKR_DEVICE_REMOVED: CK_RV = 0x0032;
+pub const CKR_FUNCTION_CANCELED: CK_RV = 0x0050;
+pub const CKR_FUNCTION_NOT_SUPPORTED: CK_RV = 0x0054;
+pub const CKR_KEY_HANDLE_INVALID: CK_RV = 0x0060;
+pub const CKR_KEY_TYPE_INCONSISTENT: CK_RV = 0x0063;
+pub const CKR_KEY_FUNCTION_NOT_PERMITTED: CK_RV = 0x0068;
+pub const CKR_MECHANISM_INVALID: CK_RV = 0x0070;
+pub const CKR_MECHANISM_PARAM_INVALID: CK_RV = 0x0071;
+pub const CKR_OBJECT_HANDLE_INVALID: CK_RV = 0x0082;
+pub const CKR_OPERATION_ACTIVE: CK_RV = 0x0090;
+pub const CKR_OPERATION_NOT_INITIALIZED: CK_RV = 0x0091;
+pub const CKR_PIN_INCORRECT: CK_RV = 0x00a0;
+pub const CKR_SESSION_CLOSED: CK_RV = 0x00b0;
+pub const CKR_SESSION_COUNT: CK_RV = 0x00b1;
+pub const CKR_SESSION_HANDLE_INVALID: CK_RV = 0x00b3;
+pub const CKR_SESSION_PARALLEL_NOT_SUPPORTED: CK_RV = 0x00b4;
+pub const CKR_SESSION_READ_ONLY: CK_RV = 0x00b5;
+pub const CKR_SESSION_READ_ONLY_EXISTS: CK_RV = 0x00b7;
+pub const CKR_SIGNATURE_INVALID: CK_RV = 0x00c0;
+pub const CKR_SIGNATURE_LEN_RANGE: CK_RV = 0x00c1;
+pub const CKR_TEMPLATE_INCOMPLETE: CK_RV = 0x00d0;
+pub const CKR_TEMPLATE_INCONSISTENT: CK_RV = 0x00d1;
+pub const CKR_TOKEN_NOT_PRESENT: CK_RV = 0x00e0;
+pub const CKR_TOKEN_NOT_RECOGNIZED: CK_RV = 0x00e1;
+pub const CKR_TOKEN_WRITE_PROTECTED: CK_RV = 0x00e2;
+pub const CKR_USER_ALREADY_LOGGED_IN: CK_RV = 0x0100;
+pub const CKR_USER_NOT_LOGGED_IN: CK_RV = 0x0101;
+pub const CKR_USER_PIN_NOT_INITIALIZED: CK_RV = 0x0102;
+pub const CKR_USER_TYPE_INVALID: CK_RV = 0x0103;
+pub const CKR_BUFFER_TOO_SMALL: CK_RV = 0x0150;
+pub const CKR_SAVED_STATE_INVALID: CK_RV = 0x0160;
+pub const CKR_STATE_UNSAVEABLE: CK_RV = 0x0180;
+pub const CKR_CRYPTOKI_NOT_INITIALIZED: CK_RV = 0x0190;
+pub const CKR_CRYPTOKI_ALREADY_INITIALIZED: CK_RV = 0x0191;
+
+// Slot flags
+pub const CKF_TOKEN_PRESENT: CK_FLAGS = 0x0001;
+pub const CKF_REMOVABLE_DEVICE: CK_FLAGS = 0x0002;
+pub const CKF_HW_SLOT: CK_FLAGS = 0x0004;
+
+// Token flags
+pub const CKF_RNG: CK_FLAGS = 0x0001;
+pub const CKF_WRITE_PROTECTED: CK_FLAGS = 0x0002;
+pub const CKF_LOGIN_REQUIRED: CK_FLAGS = 0x0004;
+pub const CKF_USER_PIN_INITIALIZED: CK_FLAGS = 0x0008;
+pub const CKF_PROTECTED_AUTHENTICATION_PATH: CK_FLAGS = 0x0100;
+pub const CKF_TOKEN_INITIALIZED: CK_FLAGS = 0x0400;
+
+// Session flags
+pub const CKF_RW_SESSION: CK_FLAGS = 0x0002;
+pub const CKF_SERIAL_SESSION: CK_FLAGS = 0x0004;
+
+// Mechanism flags
+pub const CKF_HW: CK_FLAGS = 0x0001;
+pub const CKF_DIGEST: CK_FLAGS = 0x0400;
+pub const CKF_SIGN: CK_FLAGS = 0x0800;
+pub const CKF_VERIFY: CK_FLAGS = 0x2000;
+
+// C_WaitForSlotEvent flags
+pub const CKF_DONT_BLOCK: CK_FLAGS = 0x0001;
+
+// C_Initialize flags
+pub const CKF_LIBRARY_CANT_CREATE_OS_THREADS: CK_FLAGS = 0x0001;
+pub const CKF_OS_LOCKING_OK: CK_FLAGS = 0x0002;
+
+// Session states
+pub const CKS_RO_PUBLIC_SESSION: CK_STATE = 0;
+pub const CKS_RO_USER_FUNCTIONS: CK_STATE = 1;
+pub const CKS_RW_PUBLIC_SESSION: CK_STATE = 2;
+pub const CKS_RW_USER_FUNCTIONS: CK_STATE = 3;
+
+// User types
+pub const CKU_SO: CK_USER_TYPE = 0;
+pub const CKU_USER: CK_USER_TYPE = 1;
+pub const CKU_CONTEXT_SPECIFIC: CK_USER_TYPE = 2;
+
+// Object classes
+pub const CKO_DATA: CK_OBJECT_CLASS = 0x0000;
+pub const CKO_CERTIFICATE: CK_OBJECT_CLASS = 0x0001;
+pub const CKO_PUBLIC_KEY: CK_OBJECT_CLASS = 0x0002;
+pub const CKO_PRIVATE_KEY: CK_OBJECT_CLASS = 0x0003;
+
+// Key types
+pub const CKK_RSA: CK_KEY_TYPE = 0x0000;
+pub const CKK_EC: CK_KEY_TYPE = 0x0003;
+
+// Attribute types
+pub const CKA_CLASS: CK_ATTRIBUTE_TYPE = 0x0000;
+pub const CKA_TOKEN: CK_ATTRIBUTE_TYPE = 0x0001;
+pub const CKA_PRIVATE: CK_ATTRIBUTE_TYPE = 0x0002;
+pub const CKA_LABEL: CK_ATTRIBUTE_TYPE = 0x0003;
+pub const CKA_VALUE: CK_ATTRIBUTE_TYPE = 0x0011;
+pub const CKA_KEY_TYPE: CK_ATTRIBUTE_TYPE = 0x0100;
+pub const CKA_ID: CK_ATTRIBUTE_TYPE = 0x0102;
+pub const CKA_SENSITIVE: CK_ATTRIBUTE_TYPE = 0x0103;
+pub const CKA_SIGN: CK_ATTRIBUTE_TYPE = 0x0108;
+pub const CKA_VERIFY: CK_ATTRIBUTE_TYPE = 0x010a;
+pub const CKA_MODULUS: CK_ATTRIBUTE_TYPE = 0x0120;
+pub const CKA_MODULUS_BITS: CK_ATTRIBUTE_TYPE = 0x0121;
+pub const CKA_PUBLIC_EXPONENT: CK_ATTRIBUTE_TYPE = 0x0122;
+pub const CKA_EXTRACTABLE: CK_ATTRIBUTE_TYPE = 0x0162;
+pub const CKA_EC_PARAMS: CK_ATTRIBUTE_TYPE = 0x0180;
+pub const CKA_EC_POINT: CK_ATTRIBUTE_TYPE = 0x0181;
+pub const CKA_ALWAYS_AUTHENTICATE: CK_ATTRIBUTE_TYPE = 0x0202;
+
+// Mechanism types
+pub const CKM_RSA_PKCS: CK_MECHANISM_TYPE = 0x0001;
+pub const CKM_SHA1_RSA_PKCS: CK_MECHANISM_TYPE = 0x0006;
+pub const CKM_SHA256_RSA_PKCS: CK_MECHANISM_TYPE = 0x0040;
+pub const CKM_SHA384_RSA_PKCS: CK_MECHANISM_TYPE = 0x0041;
+pub const CKM_SHA512_RSA_PKCS: CK_MECHANISM_TYPE = 0x0042;
+pub const CKM_SHA_1: CK_MECHANISM_TYPE = 0x0220;
+pub const CKM_SHA256: CK_MECHANISM_TYPE = 0x0250;
+pub const CKM_SHA384: CK_MECHANISM_TYPE = 0x0260;
+pub const CKM_SHA512: CK_MECHANISM_TYPE = 0x0270;
+pub const CKM_ECDSA: CK_MECHANISM_TYPE = 0x1041;
+pub const CKM_ECDSA_SHA1: CK_MECHANISM_TYPE = 0x1042;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug)]
+pub struct CK_VERSION {
+    pub major: CK_BYTE,
+    pub minor: CK_BYTE,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_INFO {
+    pub cryptokiVersion: CK_VERSION,
+    pub manufacturerID: [CK_UTF8CHAR; 32],
+    pub flags: CK_FLAGS,
+    pub libraryDescription: [CK_UTF8CHAR; 32],
+    pub libraryVersion: CK_VERSION,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_SLOT_INFO {
+    pub slotDescription: [CK_UTF8CHAR; 64],
+    pub manufacturerID: [CK_UTF8CHAR; 32],
+    pub flags: CK_FLAGS,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_TOKEN_INFO {
+    pub label: [CK_UTF8CHAR; 32],
+    pub manufacturerID: [CK_UTF8CHAR; 32],
+    pub model: [CK_UTF8CHAR; 16],
+    pub serialNumber: [CK_CHAR; 16],
+    pub flags: CK_FLAGS,
+    pub ulMaxSessionCount: CK_ULONG,
+    pub ulSessionCount: CK_ULONG,
+    pub ulMaxRwSessionCount: CK_ULONG,
+    pub ulRwSessionCount: CK_ULONG,
+    pub ulMaxPinLen: CK_ULONG,
+    pub ulMinPinLen: CK_ULONG,
+    pub ulTotalPublicMemory: CK_ULONG,
+    pub ulFreePublicMemory: CK_ULONG,
+    pub ulTotalPrivateMemory: CK_ULONG,
+    pub ulFreePrivateMemory: CK_ULONG,
+    pub hardwareVersion: CK_VERSION,
+    pub firmwareVersion: CK_VERSION,
+    pub utcTime: [CK_CHAR; 16],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_SESSION_INFO {
+    pub slotID: CK_SLOT_ID,
+    pub state: CK_STATE,
+    pub flags: CK_FLAGS,
+    pub ulDeviceError: CK_ULONG,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_ATTRIBUTE {
+    pub attrType: CK_ATTRIBUTE_TYPE,
+    pub pValue: CK_VOID_PTR,
+    pub ulValueLen: CK_ULONG,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_MECHANISM {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub pParameter: CK_VOID_PTR,
+    pub ulParameterLen: CK_ULONG,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CK_MECHANISM_INFO {
+    pub ulMinKeySize: CK_ULONG,
+    pub ulMaxKeySize: CK_ULONG,
+    pub flags: CK_FLAGS,
+}
+
+pub type CK_NOTIFY =
+    Option<extern "C" fn(CK_SESSION_HANDLE, CK_NOTIFICATION, CK_VOID_PTR) -> CK_RV>;
+
+pub type CK_C_INITIALIZE_ARGS_PTR = *mut CK_C_INITIALIZE_ARGS;
+
+#[repr(C)]
+pub struct CK_C_INITIALIZE_ARGS {
+    pub CreateMutex: CK_VOID_PTR,
+    pub DestroyMutex: CK_VOID_PTR,
+    pub LockMutex: CK_VOID_PTR,
+    pub UnlockMutex: CK_VOID_PTR,
+    pub flags: CK_FLAGS,
+    pub pReserved: CK_VOID_PTR,
+}
+
+/// The table of entry points handed to applications by `C_GetFunctionList`.
+#[repr(C)]
+pub struct CK_FUNCTION_LIST {
+    pub version: CK_VERSION,
+    pub C_Initialize: Option<extern "C" fn(CK_VOID_PTR) -> CK_RV>,
+    pub C_Finalize: Option<extern "C" fn(CK_VOID_PTR) -> CK_RV>,
+    pub C_GetInfo: Option<extern "C" fn(CK_INFO_PTR) -> CK_RV>,
+    pub C_GetFunctionList: Option<extern "C" fn(CK_FUNCTION_LIST_PTR_PTR) -> CK_RV>,
+    pub C_GetSlotList:
+        Option<extern "C" fn(CK_BBOOL, CK_SLOT_ID_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_GetSlotInfo: Option<extern "C" fn(CK_SLOT_ID, CK_SLOT_INFO_PTR) -> CK_RV>,
+    pub C_GetTokenInfo: Option<extern "C" fn(CK_SLOT_ID, CK_TOKEN_INFO_PTR) -> CK_RV>,
+    pub C_GetMechanismList:
+        Option<extern "C" fn(CK_SLOT_ID, CK_MECHANISM_TYPE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_GetMechanismInfo:
+        Option<extern "C" fn(CK_SLOT_ID, CK_MECHANISM_TYPE, CK_MECHANISM_INFO_PTR) -> CK_RV>,
+    pub C_InitToken:
+        Option<extern "C" fn(CK_SLOT_ID, CK_UTF8CHAR_PTR, CK_ULONG, CK_UTF8CHAR_PTR) -> CK_RV>,
+    pub C_InitPIN: Option<extern "C" fn(CK_SESSION_HANDLE, CK_UTF8CHAR_PTR, CK_ULONG) -> CK_RV>,
+    pub C_SetPIN: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_UTF8CHAR_PTR,
+            CK_ULONG,
+            CK_UTF8CHAR_PTR,
+            CK_ULONG,
+        ) -> CK_RV,
+    >,
+    pub C_OpenSession: Option<
+        extern "C" fn(
+            CK_SLOT_ID,
+            CK_FLAGS,
+            CK_VOID_PTR,
+            CK_NOTIFY,
+            CK_SESSION_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_CloseSession: Option<extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CloseAllSessions: Option<extern "C" fn(CK_SLOT_ID) -> CK_RV>,
+    pub C_GetSessionInfo:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_SESSION_INFO_PTR) -> CK_RV>,
+    pub C_GetOperationState:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_SetOperationState: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_BYTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE,
+            CK_OBJECT_HANDLE,
+        ) -> CK_RV,
+    >,
+    pub C_Login: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_USER_TYPE, CK_UTF8CHAR_PTR, CK_ULONG) -> CK_RV,
+    >,
+    pub C_Logout: Option<extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CreateObject: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_ATTRIBUTE_PTR, CK_ULONG, CK_OBJECT_HANDLE_PTR)
+            -> CK_RV,
+    >,
+    pub C_CopyObject: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_OBJECT_HANDLE,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_DestroyObject: Option<extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_GetObjectSize:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, CK_ULONG_PTR) -> CK_RV>,
+    pub C_GetAttributeValue: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, CK_ATTRIBUTE_PTR, CK_ULONG) -> CK_RV,
+    >,
+    pub C_SetAttributeValue: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE, CK_ATTRIBUTE_PTR, CK_ULONG) -> CK_RV,
+    >,
+    pub C_FindObjectsInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_ATTRIBUTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_FindObjects: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE_PTR, CK_ULONG, CK_ULONG_PTR) -> CK_RV,
+    >,
+    pub C_FindObjectsFinal: Option<extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_EncryptInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_Encrypt: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_EncryptUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_EncryptFinal:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_DecryptInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_Decrypt: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DecryptUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DecryptFinal:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_DigestInit: Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR) -> CK_RV>,
+    pub C_Digest: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DigestUpdate: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_DigestKey: Option<extern "C" fn(CK_SESSION_HANDLE, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_DigestFinal:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_SignInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_Sign: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_SignUpdate: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_SignFinal: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG_PTR) -> CK_RV>,
+    pub C_SignRecoverInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_SignRecover: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_VerifyInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_Verify: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG) -> CK_RV,
+    >,
+    pub C_VerifyUpdate: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_VerifyFinal: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_VerifyRecoverInit:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_MECHANISM_PTR, CK_OBJECT_HANDLE) -> CK_RV>,
+    pub C_VerifyRecover: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DigestEncryptUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DecryptDigestUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_SignEncryptUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_DecryptVerifyUpdate: Option<
+        extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG, CK_BYTE_PTR, CK_ULONG_PTR)
+            -> CK_RV,
+    >,
+    pub C_GenerateKey: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_MECHANISM_PTR,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_GenerateKeyPair: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_MECHANISM_PTR,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE_PTR,
+            CK_OBJECT_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_WrapKey: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_MECHANISM_PTR,
+            CK_OBJECT_HANDLE,
+            CK_OBJECT_HANDLE,
+            CK_BYTE_PTR,
+            CK_ULONG_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_UnwrapKey: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_MECHANISM_PTR,
+            CK_OBJECT_HANDLE,
+            CK_BYTE_PTR,
+            CK_ULONG,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_DeriveKey: Option<
+        extern "C" fn(
+            CK_SESSION_HANDLE,
+            CK_MECHANISM_PTR,
+            CK_OBJECT_HANDLE,
+            CK_ATTRIBUTE_PTR,
+            CK_ULONG,
+            CK_OBJECT_HANDLE_PTR,
+        ) -> CK_RV,
+    >,
+    pub C_SeedRandom: Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_GenerateRandom:
+        Option<extern "C" fn(CK_SESSION_HANDLE, CK_BYTE_PTR, CK_ULONG) -> CK_RV>,
+    pub C_GetFunctionStatus: Option<extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_CancelFunction: Option<extern "C" fn(CK_SESSION_HANDLE) -> CK_RV>,
+    pub C_WaitForSlotEvent:
+        Option<extern "C" fn(CK_FLAGS, CK_SLOT_ID_PTR, CK_VOID_PTR) -> CK_RV>,
+}
+
+unsafe impl Sync for CK_FUNCTION_LIST {}
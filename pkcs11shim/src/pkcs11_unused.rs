@@ -0,0 +1,422 @@
+//! Entry points the Krypton token does not support.
+//!
+//! Most of these log the call and return `CKR_FUNCTION_NOT_SUPPORTED`:
+//! the token cannot encrypt, manage objects, or produce randomness — it
+//! only signs, and that lives in `pkcs11shim`. Mutating calls instead
+//! report the token's write-protection (`CKR_TOKEN_WRITE_PROTECTED`, or
+//! `CKR_SESSION_READ_ONLY` for read-only sessions), since some tools
+//! treat NOT_SUPPORTED as a fatal module bug.
+
+use std::io;
+use std::sync::Arc;
+
+use syslog;
+
+use logger::ReconnectingLogger;
+use pkcs11::*;
+use pkcs11shim::read_only_error;
+
+lazy_static! {
+    /// The process-wide logger. The facade retries the connection with
+    /// backoff, so a syslog that comes up after the host application
+    /// still gets our messages (including the queued backlog).
+    pub static ref logger: ReconnectingLogger = ReconnectingLogger::new(connect_syslog);
+}
+
+/// One syslog connection attempt, used by the facade above.
+pub fn connect_syslog() -> io::Result<Arc<syslog::Logger>> {
+    // connect_shared: every dlopen of the shim in one process reuses the
+    // same syslog socket instead of opening its own
+    syslog::Builder::new()
+        .facility(syslog::Facility::LOG_USER)
+        .app_name("kr-pkcs11")
+        .connect_shared()
+        .or_else(|_| {
+            syslog::Builder::new()
+                .facility(syslog::Facility::LOG_USER)
+                .app_name("kr-pkcs11")
+                .udp(
+                    "127.0.0.1:0".parse().unwrap(),
+                    "127.0.0.1:514".parse().unwrap(),
+                )
+                .connect_shared()
+        })
+}
+
+pub extern "C" fn CK_C_InitToken(
+    _slotID: CK_SLOT_ID,
+    _pPin: CK_UTF8CHAR_PTR,
+    _ulPinLen: CK_ULONG,
+    _pLabel: CK_UTF8CHAR_PTR,
+) -> CK_RV {
+    notice!("C_InitToken");
+    CKR_TOKEN_WRITE_PROTECTED
+}
+
+pub extern "C" fn CK_C_InitPIN(
+    hSession: CK_SESSION_HANDLE,
+    _pPin: CK_UTF8CHAR_PTR,
+    _ulPinLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_InitPIN");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_SetPIN(
+    hSession: CK_SESSION_HANDLE,
+    _pOldPin: CK_UTF8CHAR_PTR,
+    _ulOldLen: CK_ULONG,
+    _pNewPin: CK_UTF8CHAR_PTR,
+    _ulNewLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_SetPIN");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_CreateObject(
+    hSession: CK_SESSION_HANDLE,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulCount: CK_ULONG,
+    _phObject: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_CreateObject");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_CopyObject(
+    hSession: CK_SESSION_HANDLE,
+    _hObject: CK_OBJECT_HANDLE,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulCount: CK_ULONG,
+    _phNewObject: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_CopyObject");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_DestroyObject(
+    hSession: CK_SESSION_HANDLE,
+    _hObject: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_DestroyObject");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_GetObjectSize(
+    _hSession: CK_SESSION_HANDLE,
+    _hObject: CK_OBJECT_HANDLE,
+    _pulSize: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_GetObjectSize");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SetAttributeValue(
+    hSession: CK_SESSION_HANDLE,
+    _hObject: CK_OBJECT_HANDLE,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulCount: CK_ULONG,
+) -> CK_RV {
+    notice!("C_SetAttributeValue");
+    read_only_error(hSession)
+}
+
+pub extern "C" fn CK_C_EncryptInit(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_EncryptInit");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_Encrypt(
+    _hSession: CK_SESSION_HANDLE,
+    _pData: CK_BYTE_PTR,
+    _ulDataLen: CK_ULONG,
+    _pEncryptedData: CK_BYTE_PTR,
+    _pulEncryptedDataLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_Encrypt");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_EncryptUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pPart: CK_BYTE_PTR,
+    _ulPartLen: CK_ULONG,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _pulEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_EncryptUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_EncryptFinal(
+    _hSession: CK_SESSION_HANDLE,
+    _pLastEncryptedPart: CK_BYTE_PTR,
+    _pulLastEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_EncryptFinal");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DecryptInit(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_DecryptInit");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_Decrypt(
+    _hSession: CK_SESSION_HANDLE,
+    _pEncryptedData: CK_BYTE_PTR,
+    _ulEncryptedDataLen: CK_ULONG,
+    _pData: CK_BYTE_PTR,
+    _pulDataLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_Decrypt");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DecryptUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _ulEncryptedPartLen: CK_ULONG,
+    _pPart: CK_BYTE_PTR,
+    _pulPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_DecryptUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DecryptFinal(
+    _hSession: CK_SESSION_HANDLE,
+    _pLastPart: CK_BYTE_PTR,
+    _pulLastPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_DecryptFinal");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DigestKey(
+    _hSession: CK_SESSION_HANDLE,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_DigestKey");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SignUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pPart: CK_BYTE_PTR,
+    _ulPartLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_SignUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SignFinal(
+    _hSession: CK_SESSION_HANDLE,
+    _pSignature: CK_BYTE_PTR,
+    _pulSignatureLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_SignFinal");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SignRecoverInit(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_SignRecoverInit");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SignRecover(
+    _hSession: CK_SESSION_HANDLE,
+    _pData: CK_BYTE_PTR,
+    _ulDataLen: CK_ULONG,
+    _pSignature: CK_BYTE_PTR,
+    _pulSignatureLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_SignRecover");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_VerifyUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pPart: CK_BYTE_PTR,
+    _ulPartLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_VerifyUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_VerifyFinal(
+    _hSession: CK_SESSION_HANDLE,
+    _pSignature: CK_BYTE_PTR,
+    _ulSignatureLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_VerifyFinal");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_VerifyRecoverInit(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    notice!("C_VerifyRecoverInit");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_VerifyRecover(
+    _hSession: CK_SESSION_HANDLE,
+    _pSignature: CK_BYTE_PTR,
+    _ulSignatureLen: CK_ULONG,
+    _pData: CK_BYTE_PTR,
+    _pulDataLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_VerifyRecover");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DigestEncryptUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pPart: CK_BYTE_PTR,
+    _ulPartLen: CK_ULONG,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _pulEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_DigestEncryptUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DecryptDigestUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _ulEncryptedPartLen: CK_ULONG,
+    _pPart: CK_BYTE_PTR,
+    _pulPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_DecryptDigestUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SignEncryptUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pPart: CK_BYTE_PTR,
+    _ulPartLen: CK_ULONG,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _pulEncryptedPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_SignEncryptUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DecryptVerifyUpdate(
+    _hSession: CK_SESSION_HANDLE,
+    _pEncryptedPart: CK_BYTE_PTR,
+    _ulEncryptedPartLen: CK_ULONG,
+    _pPart: CK_BYTE_PTR,
+    _pulPartLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_DecryptVerifyUpdate");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_GenerateKey(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulCount: CK_ULONG,
+    _phKey: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_GenerateKey");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_GenerateKeyPair(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _pPublicKeyTemplate: CK_ATTRIBUTE_PTR,
+    _ulPublicKeyAttributeCount: CK_ULONG,
+    _pPrivateKeyTemplate: CK_ATTRIBUTE_PTR,
+    _ulPrivateKeyAttributeCount: CK_ULONG,
+    _phPublicKey: CK_OBJECT_HANDLE_PTR,
+    _phPrivateKey: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_GenerateKeyPair");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_WrapKey(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hWrappingKey: CK_OBJECT_HANDLE,
+    _hKey: CK_OBJECT_HANDLE,
+    _pWrappedKey: CK_BYTE_PTR,
+    _pulWrappedKeyLen: CK_ULONG_PTR,
+) -> CK_RV {
+    notice!("C_WrapKey");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_UnwrapKey(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hUnwrappingKey: CK_OBJECT_HANDLE,
+    _pWrappedKey: CK_BYTE_PTR,
+    _ulWrappedKeyLen: CK_ULONG,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulAttributeCount: CK_ULONG,
+    _phKey: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_UnwrapKey");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_DeriveKey(
+    _hSession: CK_SESSION_HANDLE,
+    _pMechanism: CK_MECHANISM_PTR,
+    _hBaseKey: CK_OBJECT_HANDLE,
+    _pTemplate: CK_ATTRIBUTE_PTR,
+    _ulAttributeCount: CK_ULONG,
+    _phKey: CK_OBJECT_HANDLE_PTR,
+) -> CK_RV {
+    notice!("C_DeriveKey");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_SeedRandom(
+    _hSession: CK_SESSION_HANDLE,
+    _pSeed: CK_BYTE_PTR,
+    _ulSeedLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_SeedRandom");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_GenerateRandom(
+    _hSession: CK_SESSION_HANDLE,
+    _pRandomData: CK_BYTE_PTR,
+    _ulRandomLen: CK_ULONG,
+) -> CK_RV {
+    notice!("C_GenerateRandom");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_GetFunctionStatus(_hSession: CK_SESSION_HANDLE) -> CK_RV {
+    notice!("C_GetFunctionStatus");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
+pub extern "C" fn CK_C_CancelFunction(_hSession: CK_SESSION_HANDLE) -> CK_RV {
+    notice!("C_CancelFunction");
+    CKR_FUNCTION_NOT_SUPPORTED
+}
+
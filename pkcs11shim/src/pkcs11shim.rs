@@ -0,0 +1,1905 @@
+//! The implemented PKCS#11 entry points.
+//!
+//! The shim exposes a single slot whose token is the key held by the paired
+//! Krypton phone. Signing is delegated to krd over the ssh-agent protocol
+//! (see `agent`); everything the spec requires but the token cannot do lives
+//! in `pkcs11_unused` and returns `CKR_FUNCTION_NOT_SUPPORTED`.
+
+use std::collections::HashMap;
+use std::env;
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_BOOL_INIT, ATOMIC_USIZE_INIT};
+use std::thread;
+use std::time::Duration;
+
+use krlogging::flags;
+use libc;
+use ring::digest;
+use ring::signature;
+use untrusted::Input;
+use users;
+
+use agent::{self, AgentClient, Identity};
+use audit;
+use caller::CallerInfo;
+use config;
+use pairing;
+use pkcs11::*;
+use rsa;
+use soft;
+use spy;
+use stderr_tee;
+use utils;
+use zeroize;
+
+/// The one slot the shim exposes.
+pub const KRYPTON_SLOT_ID: CK_SLOT_ID = 0;
+
+/// Set to "1" to have `C_Initialize` export `SSH_AUTH_SOCK` for child
+/// processes. Off by default because mutating the environment is only
+/// sound while the host process is single-threaded.
+pub const EXPORT_SSH_AUTH_SOCK_VAR: &'static str = "KR_PKCS11_EXPORT_SSH_AUTH_SOCK";
+
+pub static INITIALIZED: AtomicBool = ATOMIC_BOOL_INIT;
+static NEXT_SESSION_HANDLE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Set in the child after a fork. The parent's sessions and agent socket
+/// are unusable there, so every call except `C_Initialize` fails with
+/// `CKR_CRYPTOKI_NOT_INITIALIZED` until the child reinitializes, per the
+/// PKCS#11 usage guide.
+static FORKED: AtomicBool = ATOMIC_BOOL_INIT;
+static ATFORK_REGISTERED: AtomicBool = ATOMIC_BOOL_INIT;
+
+unsafe extern "C" fn child_after_fork() {
+    // Only atomics here: the child may hold no locks and must not allocate.
+    FORKED.store(true, Ordering::SeqCst);
+    INITIALIZED.store(false, Ordering::SeqCst);
+}
+
+fn forked() -> bool {
+    FORKED.load(Ordering::SeqCst)
+}
+
+/// Signing mechanisms advertised by `C_GetMechanismList`; these go to the
+/// phone.
+pub const MECHANISMS: &'static [CK_MECHANISM_TYPE] = &[
+    CKM_RSA_PKCS,
+    CKM_SHA1_RSA_PKCS,
+    CKM_SHA256_RSA_PKCS,
+    CKM_SHA512_RSA_PKCS,
+    CKM_ECDSA,
+];
+
+/// Digest mechanisms, computed locally: some applications (notably Java
+/// PKCS#11 providers) insist on digesting through the token before signing.
+pub const DIGEST_MECHANISMS: &'static [CK_MECHANISM_TYPE] =
+    &[CKM_SHA_1, CKM_SHA256, CKM_SHA384, CKM_SHA512];
+
+pub struct Session {
+    pub slot_id: CK_SLOT_ID,
+    pub flags: CK_FLAGS,
+    pub find_operation: Option<FindOperation>,
+    pub sign_operation: Option<SignOperation>,
+    pub digest_operation: Option<DigestOperation>,
+    pub verify_operation: Option<VerifyOperation>,
+    /// Set by `C_Login(CKU_CONTEXT_SPECIFIC)`, consumed by the next
+    /// `C_Sign`; see `CKA_ALWAYS_AUTHENTICATE`.
+    pub context_authenticated: bool,
+}
+
+pub struct FindOperation {
+    pub remaining: Vec<CK_OBJECT_HANDLE>,
+}
+
+pub struct SignOperation {
+    pub key: CK_OBJECT_HANDLE,
+    pub mechanism: CK_MECHANISM_TYPE,
+}
+
+pub struct VerifyOperation {
+    pub key: CK_OBJECT_HANDLE,
+    pub mechanism: CK_MECHANISM_TYPE,
+}
+
+pub struct DigestOperation {
+    pub mechanism: CK_MECHANISM_TYPE,
+    pub algorithm: &'static digest::Algorithm,
+    pub context: digest::Context,
+}
+
+/// Maps a digest mechanism to its local implementation.
+fn digest_algorithm(mechanism: CK_MECHANISM_TYPE) -> Option<&'static digest::Algorithm> {
+    match mechanism {
+        CKM_SHA_1 => Some(&digest::SHA1),
+        CKM_SHA256 => Some(&digest::SHA256),
+        CKM_SHA384 => Some(&digest::SHA384),
+        CKM_SHA512 => Some(&digest::SHA512),
+        _ => None,
+    }
+}
+
+lazy_static! {
+    pub static ref SESSIONS: Mutex<HashMap<CK_SESSION_HANDLE, Session>> =
+        Mutex::new(HashMap::new());
+
+    /// One agent connection shared by every PKCS#11 call.
+    pub static ref AGENT: Mutex<AgentClient> = Mutex::new(AgentClient::new());
+
+    // The info structs are immutable apart from their flags, and NSS
+    // fetches them hundreds of times at startup: build them once and copy
+    // them out, patching the flags per call.
+    static ref INFO: CK_INFO = build_info();
+    static ref SLOT_INFO: CK_SLOT_INFO = build_slot_info();
+    static ref TOKEN_INFO: CK_TOKEN_INFO = build_token_info();
+}
+
+fn build_info() -> CK_INFO {
+    CK_INFO {
+        cryptokiVersion: CK_VERSION { major: 2, minor: 20 },
+        manufacturerID: str_to_char32("KryptCo Inc."),
+        flags: 0,
+        libraryDescription: str_to_char32("Krypton PKCS11 middleware"),
+        libraryVersion: library_version(),
+    }
+}
+
+fn build_slot_info() -> CK_SLOT_INFO {
+    CK_SLOT_INFO {
+        slotDescription: str_to_char64("Krypton phone slot"),
+        manufacturerID: str_to_char32("KryptCo Inc."),
+        flags: CKF_REMOVABLE_DEVICE,
+        hardwareVersion: CK_VERSION { major: 1, minor: 0 },
+        firmwareVersion: CK_VERSION { major: 1, minor: 0 },
+    }
+}
+
+fn build_token_info() -> CK_TOKEN_INFO {
+    CK_TOKEN_INFO {
+        label: str_to_char32("Krypton"),
+        manufacturerID: str_to_char32("KryptCo Inc."),
+        model: str_to_char16("Krypton"),
+        serialNumber: str_to_char16("1"),
+        flags: CKF_PROTECTED_AUTHENTICATION_PATH | CKF_WRITE_PROTECTED,
+        ulMaxSessionCount: CK_UNAVAILABLE_INFORMATION,
+        ulSessionCount: CK_UNAVAILABLE_INFORMATION,
+        ulMaxRwSessionCount: CK_UNAVAILABLE_INFORMATION,
+        ulRwSessionCount: CK_UNAVAILABLE_INFORMATION,
+        ulMaxPinLen: 0,
+        ulMinPinLen: 0,
+        ulTotalPublicMemory: CK_UNAVAILABLE_INFORMATION,
+        ulFreePublicMemory: CK_UNAVAILABLE_INFORMATION,
+        ulTotalPrivateMemory: CK_UNAVAILABLE_INFORMATION,
+        ulFreePrivateMemory: CK_UNAVAILABLE_INFORMATION,
+        hardwareVersion: CK_VERSION { major: 1, minor: 0 },
+        firmwareVersion: CK_VERSION { major: 1, minor: 0 },
+        utcTime: [b' '; 16],
+    }
+}
+
+/// Returns the path of `file_name` inside the invoking user's `~/.kr`.
+///
+/// Under sudo, `HOME` points at root's home while the pairing lives with the
+/// invoking user; `users::Identity` resolves `SUDO_USER`'s home first.
+pub fn kr_path(file_name: &str) -> PathBuf {
+    users::Identity::current()
+        .home_dir
+        .unwrap_or_else(|| PathBuf::from("/"))
+        .join(".kr")
+        .join(file_name)
+}
+
+// Object handles: identity `i` from the agent is exposed as a private key
+// object with handle `2i + 1` and a public key object with handle `2i + 2`.
+
+pub fn private_key_handle(identity_index: usize) -> CK_OBJECT_HANDLE {
+    identity_index * 2 + 1
+}
+
+pub fn public_key_handle(identity_index: usize) -> CK_OBJECT_HANDLE {
+    identity_index * 2 + 2
+}
+
+pub fn object_class(handle: CK_OBJECT_HANDLE) -> CK_OBJECT_CLASS {
+    if (handle - 1) % 2 == 0 {
+        CKO_PRIVATE_KEY
+    } else {
+        CKO_PUBLIC_KEY
+    }
+}
+
+pub fn identity_index(handle: CK_OBJECT_HANDLE) -> usize {
+    (handle - 1) / 2
+}
+
+/// The uniform answer for calls that would modify the token: the key
+/// material lives on the phone and the token is permanently
+/// write-protected. A read-only session reports its own state first,
+/// matching the order of checks the spec prescribes.
+pub fn read_only_error(session: CK_SESSION_HANDLE) -> CK_RV {
+    match SESSIONS.lock().unwrap().get(&session) {
+        Some(s) if s.flags & CKF_RW_SESSION == 0 => CKR_SESSION_READ_ONLY,
+        Some(_) => CKR_TOKEN_WRITE_PROTECTED,
+        None => CKR_SESSION_HANDLE_INVALID,
+    }
+}
+
+/// Lists key identities from the active backend: the local soft token when
+/// `KR_PKCS11_SOFT=1`, otherwise krd over the agent socket.
+pub fn backend_identities() -> ::std::io::Result<Vec<Identity>> {
+    if soft::enabled() {
+        return soft::identities();
+    }
+    let list = AGENT.lock().unwrap().identities()?;
+    Ok(list.identities)
+}
+
+/// Signs `data` with the active backend.
+fn backend_sign(
+    identity: &Identity,
+    data: &[u8],
+    mechanism: CK_MECHANISM_TYPE,
+) -> ::std::io::Result<Vec<u8>> {
+    if soft::enabled() {
+        return soft::sign(data, mechanism);
+    }
+    let flags = match mechanism {
+        CKM_SHA256_RSA_PKCS => agent::SSH_AGENT_RSA_SHA2_256,
+        CKM_SHA512_RSA_PKCS => agent::SSH_AGENT_RSA_SHA2_512,
+        _ => 0,
+    };
+    let caller = CallerInfo::current();
+    let request = agent::SignRequest {
+        key_blob: identity.key_blob.clone(),
+        data: data.to_vec(),
+        flags: flags,
+        metadata: Some(agent::SignMetadata {
+            caller_path: caller.path.clone(),
+            caller_pid: caller.pid,
+        }),
+    };
+    let signature = AGENT.lock().unwrap().sign(&request)?;
+    Ok(signature)
+}
+
+fn identity_for_handle(handle: CK_OBJECT_HANDLE) -> Result<Identity, CK_RV> {
+    let identities = backend_identities().map_err(|_| CKR_DEVICE_ERROR)?;
+    identities
+        .into_iter()
+        .nth(identity_index(handle))
+        .ok_or(CKR_OBJECT_HANDLE_INVALID)
+}
+
+// Fixed-width, space-padded string fields for the CK_*_INFO structs.
+
+pub fn str_to_char16(s: &str) -> [CK_UTF8CHAR; 16] {
+    let mut out = [b' '; 16];
+    copy_padded(s, &mut out);
+    out
+}
+
+pub fn str_to_char32(s: &str) -> [CK_UTF8CHAR; 32] {
+    let mut out = [b' '; 32];
+    copy_padded(s, &mut out);
+    out
+}
+
+pub fn str_to_char64(s: &str) -> [CK_UTF8CHAR; 64] {
+    let mut out = [b' '; 64];
+    copy_padded(s, &mut out);
+    out
+}
+
+fn copy_padded(s: &str, out: &mut [CK_UTF8CHAR]) {
+    for (dst, src) in out.iter_mut().zip(s.bytes()) {
+        *dst = src;
+    }
+}
+
+fn ulong_bytes(value: &CK_ULONG) -> &[u8] {
+    unsafe { slice::from_raw_parts(value as *const CK_ULONG as *const u8, size_of::<CK_ULONG>()) }
+}
+
+/// Fills a caller-provided attribute per the C_GetAttributeValue conventions:
+/// a null `pValue` is a length query, a short buffer is an error.
+unsafe fn write_attribute(attribute: &mut CK_ATTRIBUTE, value: &[u8]) -> CK_RV {
+    if attribute.pValue.is_null() {
+        attribute.ulValueLen = value.len();
+        return CKR_OK;
+    }
+    if attribute.ulValueLen < value.len() {
+        attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+        return CKR_BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(value.as_ptr(), attribute.pValue as *mut u8, value.len());
+    attribute.ulValueLen = value.len();
+    CKR_OK
+}
+
+/// Standard two-call convention for list outputs: null buffer means "tell me
+/// the count", short buffer means CKR_BUFFER_TOO_SMALL.
+unsafe fn write_ulong_list(
+    values: &[CK_ULONG],
+    out: CK_ULONG_PTR,
+    out_count: CK_ULONG_PTR,
+) -> CK_RV {
+    // the count pointer is mandatory in both calls of the convention
+    if utils::checked_out(out_count).is_none() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if out.is_null() {
+        *out_count = values.len();
+        return CKR_OK;
+    }
+    if *out_count < values.len() {
+        *out_count = values.len();
+        return CKR_BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(values.as_ptr(), out, values.len());
+    *out_count = values.len();
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Initialize(_pInitArgs: CK_VOID_PTR) -> CK_RV {
+    if INITIALIZED.swap(true, Ordering::SeqCst) {
+        return CKR_CRYPTOKI_ALREADY_INITIALIZED;
+    }
+    if !ATFORK_REGISTERED.swap(true, Ordering::SeqCst) {
+        unsafe {
+            libc::pthread_atfork(None, None, Some(child_after_fork));
+        }
+    }
+    if FORKED.swap(false, Ordering::SeqCst) {
+        // Reinitializing in a forked child: drop everything inherited from
+        // the parent. If another thread held these locks at fork time the
+        // child is beyond saving, but hosts fork from a quiescent state.
+        SESSIONS.lock().unwrap().clear();
+        *AGENT.lock().unwrap() = AgentClient::new();
+    }
+    // Resolve the agent endpoint now, while the host is still setting
+    // the module up, so later calls never have to consult the environment.
+    ::lazy_static::initialize(&agent::AGENT_ENDPOINT);
+    // Pointing SSH_AUTH_SOCK at krd lets child processes (git, ssh) pick
+    // up the Krypton agent too, but `env::set_var` is unsound once the
+    // host has spawned threads, so it is opt-in: only export when the
+    // user asked for it and accepts that C_Initialize must be called
+    // before any other thread touches the environment.
+    if env::var(EXPORT_SSH_AUTH_SOCK_VAR).map(|v| v == "1").unwrap_or(false)
+        && !flags::skip_agent_redirect()
+    {
+        // only a unix endpoint can be handed to children this way; ssh
+        // has no SSH_AUTH_SOCK spelling for a TCP agent
+        if let agent::Endpoint::Unix(ref path) = *agent::AGENT_ENDPOINT {
+            env::set_var("SSH_AUTH_SOCK", path);
+        }
+    }
+    // OpenSSH and NSS print noisy "no keys"-style diagnostics through our
+    // stderr; filter those out while still forwarding genuine errors.
+    stderr_tee::install();
+    notice!("C_Initialize");
+    // A header marks where this load's calls start in an appended trace.
+    spy!(
+        "C_Initialize: {} tracing enabled",
+        BUILD_INFO.trim_right_matches('\0')
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Finalize(_pReserved: CK_VOID_PTR) -> CK_RV {
+    notice!("C_Finalize");
+    if !INITIALIZED.swap(false, Ordering::SeqCst) {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    SESSIONS.lock().unwrap().clear();
+    CKR_OK
+}
+
+/// `libraryVersion` derived from CARGO_PKG_VERSION by build.rs.
+pub fn library_version() -> CK_VERSION {
+    CK_VERSION {
+        major: env!("KR_PKCS11_VERSION_MAJOR").parse().unwrap_or(1),
+        minor: env!("KR_PKCS11_VERSION_MINOR").parse().unwrap_or(0),
+    }
+}
+
+/// Static, NUL-terminated build string returned by `KR_GetBuildInfo`.
+pub const BUILD_INFO: &'static str = concat!(
+    "kr-pkcs11 ",
+    env!("CARGO_PKG_VERSION"),
+    " (",
+    env!("KR_PKCS11_GIT_HASH"),
+    ")\0"
+);
+
+pub extern "C" fn CK_C_GetInfo(pInfo: CK_INFO_PTR) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetInfo");
+    let info = match unsafe { utils::checked_out(pInfo) } {
+        Some(info) => info,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    *info = *INFO;
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_GetSlotList(
+    _tokenPresent: CK_BBOOL,
+    pSlotList: CK_SLOT_ID_PTR,
+    pulCount: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetSlotList");
+    unsafe { write_ulong_list(&[KRYPTON_SLOT_ID], pSlotList, pulCount) }
+}
+
+// Token-presence tracking. krd stopping is the smartcard-removal
+// analogue: consumers that handle insertion/removal events recover
+// automatically once the daemon is back.
+
+const TOKEN_ABSENT: usize = 1;
+const TOKEN_PRESENT: usize = 2;
+
+/// 0 (never probed), TOKEN_ABSENT or TOKEN_PRESENT.
+static TOKEN_STATE: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// Set on an absent → present transition until a `C_WaitForSlotEvent`
+/// caller consumes the insertion event.
+static PENDING_SLOT_EVENT: AtomicBool = ATOMIC_BOOL_INIT;
+
+/// Probes the backend (a quick connect to the krd socket) and records
+/// presence transitions for `C_WaitForSlotEvent`.
+pub fn probe_token_present() -> bool {
+    let present = soft::enabled() || agent::AgentConn::connect().is_ok();
+    let new_state = if present { TOKEN_PRESENT } else { TOKEN_ABSENT };
+    let old_state = TOKEN_STATE.swap(new_state, Ordering::SeqCst);
+    if present && old_state == TOKEN_ABSENT {
+        PENDING_SLOT_EVENT.store(true, Ordering::SeqCst);
+    }
+    present
+}
+
+pub extern "C" fn CK_C_GetSlotInfo(slotID: CK_SLOT_ID, pInfo: CK_SLOT_INFO_PTR) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetSlotInfo");
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    let info = match unsafe { utils::checked_out(pInfo) } {
+        Some(info) => info,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    *info = *SLOT_INFO;
+    if probe_token_present() {
+        info.flags |= CKF_TOKEN_PRESENT;
+    }
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_GetTokenInfo(slotID: CK_SLOT_ID, pInfo: CK_TOKEN_INFO_PTR) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetTokenInfo");
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    let info = match unsafe { utils::checked_out(pInfo) } {
+        Some(info) => info,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    if !probe_token_present() {
+        return CKR_TOKEN_NOT_PRESENT;
+    }
+    *info = *TOKEN_INFO;
+    // An unpaired phone is an uninitialized token: leave only the
+    // authentication-path flag so consumers re-check after `kr pair`.
+    // Either way the token is write-protected — keys live on the phone.
+    if pairing::paired() {
+        info.flags |= CKF_TOKEN_INITIALIZED | CKF_USER_PIN_INITIALIZED;
+    }
+    CKR_OK
+}
+
+/// Blocks (or polls, with CKF_DONT_BLOCK) until the krd "token" is
+/// re-inserted, i.e. the daemon came back after a stop.
+pub extern "C" fn CK_C_WaitForSlotEvent(
+    flags: CK_FLAGS,
+    pSlot: CK_SLOT_ID_PTR,
+    _pReserved: CK_VOID_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_WaitForSlotEvent");
+    let out = match unsafe { utils::checked_out(pSlot) } {
+        Some(out) => out,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    loop {
+        probe_token_present();
+        if PENDING_SLOT_EVENT.swap(false, Ordering::SeqCst) {
+            *out = KRYPTON_SLOT_ID;
+            return CKR_OK;
+        }
+        if flags & CKF_DONT_BLOCK != 0 {
+            return CKR_NO_EVENT;
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+pub extern "C" fn CK_C_GetMechanismList(
+    slotID: CK_SLOT_ID,
+    pMechanismList: CK_MECHANISM_TYPE_PTR,
+    pulCount: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetMechanismList");
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    let mut mechanisms = MECHANISMS.to_vec();
+    mechanisms.extend_from_slice(DIGEST_MECHANISMS);
+    unsafe { write_ulong_list(&mechanisms, pMechanismList, pulCount) }
+}
+
+pub extern "C" fn CK_C_GetMechanismInfo(
+    slotID: CK_SLOT_ID,
+    mechType: CK_MECHANISM_TYPE,
+    pInfo: CK_MECHANISM_INFO_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetMechanismInfo {}", mechType);
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    let info = match unsafe { utils::checked_out(pInfo) } {
+        Some(info) => info,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    match mechType {
+        CKM_RSA_PKCS | CKM_SHA1_RSA_PKCS | CKM_SHA256_RSA_PKCS | CKM_SHA512_RSA_PKCS => {
+            info.ulMinKeySize = 2048;
+            info.ulMaxKeySize = 4096;
+            info.flags = CKF_HW | CKF_SIGN;
+        }
+        CKM_ECDSA => {
+            info.ulMinKeySize = 256;
+            info.ulMaxKeySize = 256;
+            info.flags = CKF_HW | CKF_SIGN;
+        }
+        CKM_SHA_1 | CKM_SHA256 | CKM_SHA384 | CKM_SHA512 => {
+            // Digests are computed locally, so no CKF_HW.
+            info.ulMinKeySize = 0;
+            info.ulMaxKeySize = 0;
+            info.flags = CKF_DIGEST;
+        }
+        _ => {
+            notice!("C_GetMechanismInfo: unknown mechanism {}", mechType);
+            return CKR_MECHANISM_INVALID;
+        }
+    }
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_OpenSession(
+    slotID: CK_SLOT_ID,
+    flags: CK_FLAGS,
+    _pApplication: CK_VOID_PTR,
+    _Notify: CK_NOTIFY,
+    phSession: CK_SESSION_HANDLE_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_OpenSession");
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    if flags & CKF_SERIAL_SESSION == 0 {
+        return CKR_SESSION_PARALLEL_NOT_SUPPORTED;
+    }
+    // validate before creating the session so a bad pointer does not
+    // leak an entry in the session table
+    let out = match unsafe { utils::checked_out(phSession) } {
+        Some(out) => out,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    let handle = NEXT_SESSION_HANDLE.fetch_add(1, Ordering::SeqCst) + 1;
+    SESSIONS.lock().unwrap().insert(
+        handle,
+        Session {
+            slot_id: slotID,
+            flags: flags,
+            find_operation: None,
+            sign_operation: None,
+            digest_operation: None,
+            verify_operation: None,
+            context_authenticated: false,
+        },
+    );
+    *out = handle;
+    spy!(
+        "C_OpenSession(slot={}, flags={}) -> session {}",
+        slotID,
+        spy::ckf_names(flags, spy::SESSION_FLAG_NAMES),
+        handle
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_CloseSession(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_CloseSession");
+    // Dropping the session aborts any in-flight find/sign/digest operation
+    // bound to it.
+    match SESSIONS.lock().unwrap().remove(&hSession) {
+        Some(_) => CKR_OK,
+        None => CKR_SESSION_HANDLE_INVALID,
+    }
+}
+
+pub extern "C" fn CK_C_CloseAllSessions(slotID: CK_SLOT_ID) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_CloseAllSessions");
+    if slotID != KRYPTON_SLOT_ID {
+        return CKR_SLOT_ID_INVALID;
+    }
+    SESSIONS
+        .lock()
+        .unwrap()
+        .retain(|_, session| session.slot_id != slotID);
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_GetSessionInfo(
+    hSession: CK_SESSION_HANDLE,
+    pInfo: CK_SESSION_INFO_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetSessionInfo");
+    let sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    let info = match unsafe { utils::checked_out(pInfo) } {
+        Some(info) => info,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    info.slotID = session.slot_id;
+    info.state = if session.flags & CKF_RW_SESSION != 0 {
+        CKS_RW_USER_FUNCTIONS
+    } else {
+        CKS_RO_USER_FUNCTIONS
+    };
+    info.flags = session.flags;
+    info.ulDeviceError = 0;
+    CKR_OK
+}
+
+/// Version tag at the front of the opaque operation-state blob; bump when
+/// the layout changes so stale checkpoints are rejected instead of
+/// misparsed.
+const OPERATION_STATE_VERSION: u8 = 1;
+
+fn serialize_ulong(out: &mut Vec<u8>, value: CK_ULONG) {
+    let mut value = value as u64;
+    for _ in 0..8 {
+        out.push(value as u8);
+        value >>= 8;
+    }
+}
+
+fn deserialize_ulong(bytes: &[u8]) -> CK_ULONG {
+    let mut value = 0u64;
+    for (shift, byte) in bytes.iter().take(8).enumerate() {
+        value |= (*byte as u64) << (shift * 8);
+    }
+    value as CK_ULONG
+}
+
+pub extern "C" fn CK_C_GetOperationState(
+    hSession: CK_SESSION_HANDLE,
+    pOperationState: CK_BYTE_PTR,
+    pulOperationStateLen: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetOperationState");
+    let out_len = match unsafe { utils::checked_out(pulOperationStateLen) } {
+        Some(out_len) => out_len,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    let sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    let mut state = vec![OPERATION_STATE_VERSION];
+    match session.sign_operation.as_ref() {
+        Some(op) => {
+            state.push(1);
+            serialize_ulong(&mut state, op.key);
+            serialize_ulong(&mut state, op.mechanism);
+        }
+        None => state.push(0),
+    }
+    if pOperationState.is_null() {
+        *out_len = state.len();
+        return CKR_OK;
+    }
+    if *out_len < state.len() {
+        *out_len = state.len();
+        return CKR_BUFFER_TOO_SMALL;
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(state.as_ptr(), pOperationState, state.len());
+    }
+    *out_len = state.len();
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_SetOperationState(
+    hSession: CK_SESSION_HANDLE,
+    pOperationState: CK_BYTE_PTR,
+    ulOperationStateLen: CK_ULONG,
+    _hEncryptionKey: CK_OBJECT_HANDLE,
+    hAuthenticationKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_SetOperationState");
+    if pOperationState.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let state =
+        unsafe { slice::from_raw_parts(pOperationState as *const u8, ulOperationStateLen) };
+    if state.len() < 2 || state[0] != OPERATION_STATE_VERSION {
+        return CKR_SAVED_STATE_INVALID;
+    }
+    let restored = match state[1] {
+        0 => None,
+        1 => {
+            if state.len() < 2 + 16 {
+                return CKR_SAVED_STATE_INVALID;
+            }
+            let saved_key = deserialize_ulong(&state[2..10]);
+            let key = if hAuthenticationKey != CK_INVALID_HANDLE {
+                hAuthenticationKey
+            } else {
+                saved_key
+            };
+            // The blob is host-supplied: a zero handle would underflow
+            // object_class, which assumes handles start at 1.
+            if key == CK_INVALID_HANDLE {
+                return CKR_SAVED_STATE_INVALID;
+            }
+            if object_class(key) != CKO_PRIVATE_KEY {
+                return CKR_KEY_HANDLE_INVALID;
+            }
+            let mechanism = deserialize_ulong(&state[10..18]);
+            if !MECHANISMS.contains(&mechanism) {
+                return CKR_SAVED_STATE_INVALID;
+            }
+            Some(SignOperation {
+                key: key,
+                mechanism: mechanism,
+            })
+        }
+        _ => return CKR_SAVED_STATE_INVALID,
+    };
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    session.sign_operation = restored;
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Login(
+    hSession: CK_SESSION_HANDLE,
+    userType: CK_USER_TYPE,
+    _pPin: CK_UTF8CHAR_PTR,
+    _ulPinLen: CK_ULONG,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_Login");
+    let mut sessions = SESSIONS.lock().unwrap();
+    let rv = match sessions.get_mut(&hSession) {
+        None => CKR_SESSION_HANDLE_INVALID,
+        Some(_) if userType != CKU_USER && userType != CKU_CONTEXT_SPECIFIC => {
+            CKR_USER_TYPE_INVALID
+        }
+        Some(session) => {
+            // Authentication happens out of band on the paired phone
+            // (CKF_PROTECTED_AUTHENTICATION_PATH), so there is no PIN to
+            // check; a context-specific login arms the next signature,
+            // whose agent round-trip triggers the fresh phone approval.
+            if userType == CKU_CONTEXT_SPECIFIC {
+                session.context_authenticated = true;
+            }
+            CKR_OK
+        }
+    };
+    drop(sessions);
+    audit::record_login(userType, rv);
+    rv
+}
+
+pub extern "C" fn CK_C_Logout(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_Logout");
+    if !SESSIONS.lock().unwrap().contains_key(&hSession) {
+        return CKR_SESSION_HANDLE_INVALID;
+    }
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_FindObjectsInit(
+    hSession: CK_SESSION_HANDLE,
+    pTemplate: CK_ATTRIBUTE_PTR,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_FindObjectsInit");
+    spy!(
+        "C_FindObjectsInit(session={}, template={})",
+        hSession,
+        spy::template_summary(pTemplate, ulCount)
+    );
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    if session.find_operation.is_some() {
+        return CKR_OPERATION_ACTIVE;
+    }
+
+    let class_filter = unsafe { template_class(pTemplate, ulCount) };
+    let label_filter = unsafe { template_label(pTemplate, ulCount) };
+    let identities = match backend_identities() {
+        Ok(identities) => identities,
+        Err(e) => {
+            error!("C_FindObjectsInit: backend error: {}", e);
+            return CKR_DEVICE_ERROR;
+        }
+    };
+
+    let mut handles = Vec::new();
+    for (index, identity) in identities.iter().enumerate() {
+        if let Some(ref label) = label_filter {
+            // ~/.kr/pkcs11.toml can route an application label to a
+            // different identity; otherwise labels match comments
+            // literally.
+            let pattern = config::CONFIG.pattern_for_label(label);
+            if !config::matches(pattern, &identity.comment) {
+                continue;
+            }
+        }
+        if class_filter.map_or(true, |class| class == CKO_PRIVATE_KEY) {
+            handles.push(private_key_handle(index));
+        }
+        if class_filter.map_or(true, |class| class == CKO_PUBLIC_KEY) {
+            handles.push(public_key_handle(index));
+        }
+    }
+    session.find_operation = Some(FindOperation { remaining: handles });
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_FindObjects(
+    hSession: CK_SESSION_HANDLE,
+    phObject: CK_OBJECT_HANDLE_PTR,
+    ulMaxObjectCount: CK_ULONG,
+    pulObjectCount: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_FindObjects");
+    let out_count = match unsafe { utils::checked_out(pulObjectCount) } {
+        Some(out_count) => out_count,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    if ulMaxObjectCount > 0 && unsafe { utils::checked_out(phObject) }.is_none() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    let find = match session.find_operation.as_mut() {
+        Some(find) => find,
+        None => return CKR_OPERATION_NOT_INITIALIZED,
+    };
+    let count = ::std::cmp::min(ulMaxObjectCount, find.remaining.len());
+    unsafe {
+        for (i, handle) in find.remaining.drain(..count).enumerate() {
+            *phObject.offset(i as isize) = handle;
+        }
+    }
+    *out_count = count;
+    spy!(
+        "C_FindObjects(session={}, max={}) -> {} object(s)",
+        hSession,
+        ulMaxObjectCount,
+        count
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_FindObjectsFinal(hSession: CK_SESSION_HANDLE) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_FindObjectsFinal");
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    if session.find_operation.take().is_none() {
+        return CKR_OPERATION_NOT_INITIALIZED;
+    }
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_GetAttributeValue(
+    hSession: CK_SESSION_HANDLE,
+    hObject: CK_OBJECT_HANDLE,
+    pTemplate: CK_ATTRIBUTE_PTR,
+    ulCount: CK_ULONG,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_GetAttributeValue");
+    spy!(
+        "C_GetAttributeValue(session={}, object={}, template={})",
+        hSession,
+        hObject,
+        spy::template_summary(pTemplate, ulCount)
+    );
+    if !SESSIONS.lock().unwrap().contains_key(&hSession) {
+        return CKR_SESSION_HANDLE_INVALID;
+    }
+    if hObject == CK_INVALID_HANDLE {
+        return CKR_OBJECT_HANDLE_INVALID;
+    }
+    let identity = match identity_for_handle(hObject) {
+        Ok(identity) => identity,
+        Err(rv) => return rv,
+    };
+    let class = object_class(hObject);
+    let key_type: CK_ULONG = match identity.key_format().as_ref().map(|s| &s[..]) {
+        Some(format) if format.starts_with("ecdsa-") => CKK_EC,
+        Some("ssh-ed25519") => CKK_EC,
+        _ => CKK_RSA,
+    };
+
+    let mut rv = CKR_OK;
+    for i in 0..ulCount {
+        let attribute = unsafe { &mut *pTemplate.offset(i as isize) };
+        let attribute_rv = unsafe {
+            match attribute.attrType {
+                CKA_CLASS => write_attribute(attribute, ulong_bytes(&class)),
+                CKA_KEY_TYPE => write_attribute(attribute, ulong_bytes(&key_type)),
+                CKA_TOKEN => write_attribute(attribute, &[CK_TRUE]),
+                CKA_PRIVATE => write_attribute(
+                    attribute,
+                    &[if class == CKO_PRIVATE_KEY { CK_TRUE } else { CK_FALSE }],
+                ),
+                CKA_LABEL | CKA_ID => write_attribute(attribute, identity.comment.as_bytes()),
+                CKA_SIGN => write_attribute(
+                    attribute,
+                    &[if class == CKO_PRIVATE_KEY { CK_TRUE } else { CK_FALSE }],
+                ),
+                CKA_VERIFY => write_attribute(
+                    attribute,
+                    &[if class == CKO_PUBLIC_KEY { CK_TRUE } else { CK_FALSE }],
+                ),
+                CKA_SENSITIVE => write_attribute(attribute, &[CK_TRUE]),
+                CKA_EXTRACTABLE => write_attribute(attribute, &[CK_FALSE]),
+                // With the "ask me every time" policy active on the phone,
+                // well-behaved applications must re-authenticate before
+                // each signature.
+                CKA_ALWAYS_AUTHENTICATE => write_attribute(
+                    attribute,
+                    &[if class == CKO_PRIVATE_KEY && pairing::requires_manual_approval() {
+                        CK_TRUE
+                    } else {
+                        CK_FALSE
+                    }],
+                ),
+                CKA_VALUE => {
+                    if class == CKO_PUBLIC_KEY {
+                        write_attribute(attribute, &identity.key_blob)
+                    } else {
+                        attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                        CKR_ATTRIBUTE_SENSITIVE
+                    }
+                }
+                _ => {
+                    attribute.ulValueLen = CK_UNAVAILABLE_INFORMATION;
+                    CKR_ATTRIBUTE_TYPE_INVALID
+                }
+            }
+        };
+        if attribute_rv != CKR_OK {
+            rv = attribute_rv;
+        }
+    }
+    rv
+}
+
+pub extern "C" fn CK_C_DigestInit(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: CK_MECHANISM_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_DigestInit");
+    if pMechanism.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    if session.digest_operation.is_some() {
+        return CKR_OPERATION_ACTIVE;
+    }
+    let mechanism = unsafe { (*pMechanism).mechanism };
+    let algorithm = match digest_algorithm(mechanism) {
+        Some(algorithm) => algorithm,
+        None => return CKR_MECHANISM_INVALID,
+    };
+    session.digest_operation = Some(DigestOperation {
+        mechanism: mechanism,
+        algorithm: algorithm,
+        context: digest::Context::new(algorithm),
+    });
+    spy!(
+        "C_DigestInit(session={}, mechanism={})",
+        hSession,
+        spy::mechanism_name(mechanism)
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Digest(
+    hSession: CK_SESSION_HANDLE,
+    pData: CK_BYTE_PTR,
+    ulDataLen: CK_ULONG,
+    pDigest: CK_BYTE_PTR,
+    pulDigestLen: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_Digest");
+    let out_len = match unsafe { utils::checked_out(pulDigestLen) } {
+        Some(out_len) => out_len,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    if pData.is_null() && ulDataLen > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let data = unsafe { slice::from_raw_parts(pData as *const u8, ulDataLen) };
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    let output_len = match session.digest_operation.as_ref() {
+        Some(op) => op.algorithm.output_len,
+        None => return CKR_OPERATION_NOT_INITIALIZED,
+    };
+    // Length query and short buffer leave the operation active so the
+    // caller can retry with a large enough buffer.
+    if pDigest.is_null() {
+        *out_len = output_len;
+        return CKR_OK;
+    }
+    if *out_len < output_len {
+        *out_len = output_len;
+        return CKR_BUFFER_TOO_SMALL;
+    }
+    let mut op = session.digest_operation.take().unwrap();
+    op.context.update(data);
+    let value = op.context.finish();
+    let value = value.as_ref();
+    unsafe {
+        ptr::copy_nonoverlapping(value.as_ptr(), pDigest, value.len());
+    }
+    *out_len = value.len();
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_DigestUpdate(
+    hSession: CK_SESSION_HANDLE,
+    pPart: CK_BYTE_PTR,
+    ulPartLen: CK_ULONG,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_DigestUpdate");
+    if pPart.is_null() && ulPartLen > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let part = unsafe { slice::from_raw_parts(pPart as *const u8, ulPartLen) };
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    match session.digest_operation.as_mut() {
+        Some(op) => {
+            op.context.update(part);
+            CKR_OK
+        }
+        None => CKR_OPERATION_NOT_INITIALIZED,
+    }
+}
+
+pub extern "C" fn CK_C_DigestFinal(
+    hSession: CK_SESSION_HANDLE,
+    pDigest: CK_BYTE_PTR,
+    pulDigestLen: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_DigestFinal");
+    let out_len = match unsafe { utils::checked_out(pulDigestLen) } {
+        Some(out_len) => out_len,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    let output_len = match session.digest_operation.as_ref() {
+        Some(op) => op.algorithm.output_len,
+        None => return CKR_OPERATION_NOT_INITIALIZED,
+    };
+    if pDigest.is_null() {
+        *out_len = output_len;
+        return CKR_OK;
+    }
+    if *out_len < output_len {
+        *out_len = output_len;
+        return CKR_BUFFER_TOO_SMALL;
+    }
+    let op = session.digest_operation.take().unwrap();
+    let value = op.context.finish();
+    let value = value.as_ref();
+    unsafe {
+        ptr::copy_nonoverlapping(value.as_ptr(), pDigest, value.len());
+    }
+    *out_len = value.len();
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_SignInit(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: CK_MECHANISM_PTR,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_SignInit");
+    if pMechanism.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if !pairing::paired() {
+        return CKR_DEVICE_REMOVED;
+    }
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    if session.sign_operation.is_some() {
+        return CKR_OPERATION_ACTIVE;
+    }
+    if hKey == CK_INVALID_HANDLE || object_class(hKey) != CKO_PRIVATE_KEY {
+        return CKR_KEY_HANDLE_INVALID;
+    }
+    let mechanism = unsafe { (*pMechanism).mechanism };
+    if !MECHANISMS.contains(&mechanism) {
+        return CKR_MECHANISM_INVALID;
+    }
+    session.sign_operation = Some(SignOperation {
+        key: hKey,
+        mechanism: mechanism,
+    });
+    spy!(
+        "C_SignInit(session={}, mechanism={}, key={})",
+        hSession,
+        spy::mechanism_name(mechanism),
+        hKey
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Sign(
+    hSession: CK_SESSION_HANDLE,
+    pData: CK_BYTE_PTR,
+    ulDataLen: CK_ULONG,
+    pSignature: CK_BYTE_PTR,
+    pulSignatureLen: CK_ULONG_PTR,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_Sign");
+    let out_len = match unsafe { utils::checked_out(pulSignatureLen) } {
+        Some(out_len) => out_len,
+        None => return CKR_ARGUMENTS_BAD,
+    };
+    if pData.is_null() && ulDataLen > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if !pairing::paired() {
+        return CKR_DEVICE_REMOVED;
+    }
+    let (key, mechanism) = {
+        let sessions = SESSIONS.lock().unwrap();
+        let session = match sessions.get(&hSession) {
+            Some(session) => session,
+            None => return CKR_SESSION_HANDLE_INVALID,
+        };
+        let (key, mechanism) = match session.sign_operation.as_ref() {
+            Some(op) => (op.key, op.mechanism),
+            None => return CKR_OPERATION_NOT_INITIALIZED,
+        };
+        if pairing::requires_manual_approval() && !session.context_authenticated {
+            return CKR_USER_NOT_LOGGED_IN;
+        }
+        (key, mechanism)
+    };
+
+    if pSignature.is_null() {
+        // Length query: we do not know the exact signature size before the
+        // phone signs, so report the largest signature we can produce.
+        *out_len = 512;
+        return CKR_OK;
+    }
+
+    let data = unsafe { slice::from_raw_parts(pData, ulDataLen) };
+    let identity = match identity_for_handle(key) {
+        Ok(identity) => identity,
+        Err(rv) => {
+            audit::record_sign(mechanism, data, rv);
+            clear_sign_operation(hSession);
+            return rv;
+        }
+    };
+    let mut signature = match backend_sign(&identity, data, mechanism) {
+        Ok(signature) => signature,
+        Err(e) => {
+            error!("C_Sign: backend error: {}", e);
+            audit::record_sign(mechanism, data, CKR_FUNCTION_CANCELED);
+            clear_sign_operation(hSession);
+            return CKR_FUNCTION_CANCELED;
+        }
+    };
+    audit::record_sign(mechanism, data, CKR_OK);
+
+    let rv = if *out_len < signature.len() {
+        *out_len = signature.len();
+        CKR_BUFFER_TOO_SMALL
+    } else {
+        unsafe {
+            ptr::copy_nonoverlapping(signature.as_ptr(), pSignature, signature.len());
+        }
+        *out_len = signature.len();
+        CKR_OK
+    };
+    spy!(
+        "C_Sign(session={}, dataLen={}) -> sigLen {} = {}",
+        hSession,
+        ulDataLen,
+        signature.len(),
+        spy::ckr_name(rv)
+    );
+    // Scrub our copy; the caller's buffer is theirs to manage.
+    zeroize::wipe(&mut signature);
+    if rv != CKR_OK {
+        return rv;
+    }
+    clear_sign_operation(hSession);
+    CKR_OK
+}
+
+fn clear_sign_operation(hSession: CK_SESSION_HANDLE) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&hSession) {
+        session.sign_operation = None;
+        // A context-specific login is good for exactly one signature.
+        session.context_authenticated = false;
+    }
+}
+
+pub extern "C" fn CK_C_VerifyInit(
+    hSession: CK_SESSION_HANDLE,
+    pMechanism: CK_MECHANISM_PTR,
+    hKey: CK_OBJECT_HANDLE,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_VerifyInit");
+    if pMechanism.is_null() {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let mut sessions = SESSIONS.lock().unwrap();
+    let session = match sessions.get_mut(&hSession) {
+        Some(session) => session,
+        None => return CKR_SESSION_HANDLE_INVALID,
+    };
+    if session.verify_operation.is_some() {
+        return CKR_OPERATION_ACTIVE;
+    }
+    if hKey == CK_INVALID_HANDLE || object_class(hKey) != CKO_PUBLIC_KEY {
+        return CKR_KEY_HANDLE_INVALID;
+    }
+    let mechanism = unsafe { (*pMechanism).mechanism };
+    if !MECHANISMS.contains(&mechanism) {
+        return CKR_MECHANISM_INVALID;
+    }
+    session.verify_operation = Some(VerifyOperation {
+        key: hKey,
+        mechanism: mechanism,
+    });
+    spy!(
+        "C_VerifyInit(session={}, mechanism={}, key={})",
+        hSession,
+        spy::mechanism_name(mechanism),
+        hKey
+    );
+    CKR_OK
+}
+
+pub extern "C" fn CK_C_Verify(
+    hSession: CK_SESSION_HANDLE,
+    pData: CK_BYTE_PTR,
+    ulDataLen: CK_ULONG,
+    pSignature: CK_BYTE_PTR,
+    ulSignatureLen: CK_ULONG,
+) -> CK_RV {
+    if forked() {
+        return CKR_CRYPTOKI_NOT_INITIALIZED;
+    }
+    notice!("C_Verify");
+    if pData.is_null() && ulDataLen > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    if pSignature.is_null() && ulSignatureLen > 0 {
+        return CKR_ARGUMENTS_BAD;
+    }
+    let (key, mechanism) = {
+        let sessions = SESSIONS.lock().unwrap();
+        let session = match sessions.get(&hSession) {
+            Some(session) => session,
+            None => return CKR_SESSION_HANDLE_INVALID,
+        };
+        match session.verify_operation.as_ref() {
+            Some(op) => (op.key, op.mechanism),
+            None => return CKR_OPERATION_NOT_INITIALIZED,
+        }
+    };
+    let data = unsafe { slice::from_raw_parts(pData as *const u8, ulDataLen) };
+    let signature = unsafe { slice::from_raw_parts(pSignature as *const u8, ulSignatureLen) };
+    let rv = match identity_for_handle(key) {
+        Ok(identity) => verify_signature(&identity, data, signature, mechanism),
+        Err(rv) => rv,
+    };
+    // Single-part verification ends the operation either way.
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(&hSession) {
+        session.verify_operation = None;
+    }
+    spy!(
+        "C_Verify(session={}, dataLen={}, sigLen={}) = {}",
+        hSession,
+        ulDataLen,
+        ulSignatureLen,
+        spy::ckr_name(rv)
+    );
+    rv
+}
+
+// DER DigestInfo prefixes for EMSA-PKCS1-v1_5 (RFC 8017 section 9.2).
+const DIGEST_INFO_SHA1: &'static [u8] = &[
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00, 0x04, 0x14,
+];
+const DIGEST_INFO_SHA256: &'static [u8] = &[
+    0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x01,
+    0x05, 0x00, 0x04, 0x20,
+];
+const DIGEST_INFO_SHA512: &'static [u8] = &[
+    0x30, 0x51, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02, 0x03,
+    0x05, 0x00, 0x04, 0x40,
+];
+
+/// Verifies `signature` over `data` against the identity's public key,
+/// entirely locally: applications doing sign-then-verify sanity checks
+/// should not cost a phone round-trip.
+fn verify_signature(
+    identity: &Identity,
+    data: &[u8],
+    signature: &[u8],
+    mechanism: CK_MECHANISM_TYPE,
+) -> CK_RV {
+    let blob = &identity.key_blob;
+    let (format, next) = match agent::read_string_at(blob, 0) {
+        Some(parsed) => parsed,
+        None => return CKR_DEVICE_ERROR,
+    };
+    match mechanism {
+        CKM_RSA_PKCS | CKM_SHA1_RSA_PKCS | CKM_SHA256_RSA_PKCS | CKM_SHA512_RSA_PKCS => {
+            if format != "ssh-rsa" {
+                return CKR_KEY_TYPE_INCONSISTENT;
+            }
+            let parsed = agent::read_bytes_at(blob, next).and_then(|(e, next)| {
+                agent::read_bytes_at(blob, next).map(|(n, _)| (e, n))
+            });
+            let (e, n) = match parsed {
+                Some(parsed) => parsed,
+                None => return CKR_DEVICE_ERROR,
+            };
+            // With CKM_RSA_PKCS the caller hands us the DigestInfo; for the
+            // hashing variants we build it ourselves.
+            let payload = match mechanism {
+                CKM_RSA_PKCS => data.to_vec(),
+                _ => {
+                    let (prefix, algorithm) = match mechanism {
+                        CKM_SHA1_RSA_PKCS => (DIGEST_INFO_SHA1, &digest::SHA1),
+                        CKM_SHA256_RSA_PKCS => (DIGEST_INFO_SHA256, &digest::SHA256),
+                        _ => (DIGEST_INFO_SHA512, &digest::SHA512),
+                    };
+                    let mut payload = prefix.to_vec();
+                    payload.extend_from_slice(digest::digest(algorithm, data).as_ref());
+                    payload
+                }
+            };
+            let n = agent::strip_mpint_padding(&n);
+            if payload.len() + 11 > n.len() {
+                return CKR_DATA_INVALID;
+            }
+            let mut expected = vec![0x00, 0x01];
+            expected.resize(n.len() - payload.len() - 1, 0xff);
+            expected.push(0x00);
+            expected.extend_from_slice(&payload);
+            match rsa::public_op(n, &e, signature) {
+                Some(em) => {
+                    if em == expected {
+                        CKR_OK
+                    } else {
+                        CKR_SIGNATURE_INVALID
+                    }
+                }
+                None => CKR_SIGNATURE_INVALID,
+            }
+        }
+        CKM_ECDSA => {
+            if format != "ecdsa-sha2-nistp256" {
+                return CKR_KEY_TYPE_INCONSISTENT;
+            }
+            let parsed = agent::read_string_at(blob, next).and_then(|(curve, next)| {
+                agent::read_bytes_at(blob, next).map(|(point, _)| (curve, point))
+            });
+            let point = match parsed {
+                Some((ref curve, _)) if curve != "nistp256" => return CKR_DEVICE_ERROR,
+                Some((_, point)) => point,
+                None => return CKR_DEVICE_ERROR,
+            };
+            // `C_Sign` strips leading zeros when fixing the width of
+            // `r || s`; re-pad each half to the 32 bytes ring expects.
+            if signature.is_empty() || signature.len() % 2 != 0 || signature.len() > 64 {
+                return CKR_SIGNATURE_LEN_RANGE;
+            }
+            let half = signature.len() / 2;
+            let mut fixed = vec![0u8; 64];
+            fixed[32 - half..32].copy_from_slice(&signature[..half]);
+            fixed[64 - half..].copy_from_slice(&signature[half..]);
+            // The agent hashes the data with SHA-256 before signing, so
+            // verification hashes the same way; like `C_Sign`, this means
+            // CKM_ECDSA operates on the raw data rather than a digest.
+            match signature::verify(
+                &signature::ECDSA_P256_SHA256_FIXED,
+                Input::from(&point),
+                Input::from(data),
+                Input::from(&fixed),
+            ) {
+                Ok(()) => CKR_OK,
+                Err(_) => CKR_SIGNATURE_INVALID,
+            }
+        }
+        _ => CKR_MECHANISM_INVALID,
+    }
+}
+
+unsafe fn template_label(pTemplate: CK_ATTRIBUTE_PTR, ulCount: CK_ULONG) -> Option<String> {
+    if pTemplate.is_null() {
+        return None;
+    }
+    for i in 0..ulCount {
+        let attribute = &*pTemplate.offset(i as isize);
+        if (attribute.attrType == CKA_LABEL || attribute.attrType == CKA_ID)
+            && !attribute.pValue.is_null()
+        {
+            let bytes = slice::from_raw_parts(attribute.pValue as *const u8, attribute.ulValueLen);
+            return String::from_utf8(bytes.to_vec()).ok();
+        }
+    }
+    None
+}
+
+unsafe fn template_class(
+    pTemplate: CK_ATTRIBUTE_PTR,
+    ulCount: CK_ULONG,
+) -> Option<CK_OBJECT_CLASS> {
+    if pTemplate.is_null() {
+        return None;
+    }
+    for i in 0..ulCount {
+        let attribute = &*pTemplate.offset(i as isize);
+        if attribute.attrType == CKA_CLASS
+            && !attribute.pValue.is_null()
+            && attribute.ulValueLen == size_of::<CK_OBJECT_CLASS>()
+        {
+            return Some(*(attribute.pValue as *const CK_OBJECT_CLASS));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pkcs11_unused;
+
+    fn open_session(flags: CK_FLAGS) -> CK_SESSION_HANDLE {
+        let mut handle: CK_SESSION_HANDLE = 0;
+        assert_eq!(
+            CK_C_OpenSession(
+                KRYPTON_SLOT_ID,
+                CKF_SERIAL_SESSION | flags,
+                ptr::null_mut(),
+                None,
+                &mut handle,
+            ),
+            CKR_OK
+        );
+        handle
+    }
+
+    // The call pkcs11-tool --init-token makes.
+    #[test]
+    fn init_token_reports_write_protection() {
+        assert_eq!(
+            pkcs11_unused::CK_C_InitToken(KRYPTON_SLOT_ID, ptr::null_mut(), 0, ptr::null_mut()),
+            CKR_TOKEN_WRITE_PROTECTED
+        );
+    }
+
+    // The sequence pkcs11-tool --write-object drives: CreateObject on a
+    // read-only session must cite the session, on a r/w session the token.
+    #[test]
+    fn write_object_reports_read_only_policy() {
+        let ro = open_session(0);
+        assert_eq!(
+            pkcs11_unused::CK_C_CreateObject(ro, ptr::null_mut(), 0, ptr::null_mut()),
+            CKR_SESSION_READ_ONLY
+        );
+        let rw = open_session(CKF_RW_SESSION);
+        assert_eq!(
+            pkcs11_unused::CK_C_CreateObject(rw, ptr::null_mut(), 0, ptr::null_mut()),
+            CKR_TOKEN_WRITE_PROTECTED
+        );
+        assert_eq!(
+            pkcs11_unused::CK_C_DestroyObject(rw, 1),
+            CKR_TOKEN_WRITE_PROTECTED
+        );
+        CK_C_CloseSession(ro);
+        CK_C_CloseSession(rw);
+    }
+
+    #[test]
+    fn digest_matches_known_vector() {
+        let session = open_session(0);
+        let mut mechanism = CK_MECHANISM {
+            mechanism: CKM_SHA256,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        assert_eq!(CK_C_DigestInit(session, &mut mechanism), CKR_OK);
+        let mut data = b"abc".to_vec();
+        // Length query first, then the real call.
+        let mut len: CK_ULONG = 0;
+        assert_eq!(
+            CK_C_Digest(session, data.as_mut_ptr(), data.len(), ptr::null_mut(), &mut len),
+            CKR_OK
+        );
+        assert_eq!(len, 32);
+        let mut out = vec![0u8; len];
+        assert_eq!(
+            CK_C_Digest(session, data.as_mut_ptr(), data.len(), out.as_mut_ptr(), &mut len),
+            CKR_OK
+        );
+        assert_eq!(out[..4], [0xba, 0x78, 0x16, 0xbf]);
+        // The operation is finished; a second final must fail.
+        assert_eq!(
+            CK_C_DigestFinal(session, out.as_mut_ptr(), &mut len),
+            CKR_OPERATION_NOT_INITIALIZED
+        );
+        CK_C_CloseSession(session);
+    }
+
+    #[test]
+    fn digest_update_final_matches_single_shot() {
+        let session = open_session(0);
+        let mut mechanism = CK_MECHANISM {
+            mechanism: CKM_SHA_1,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        assert_eq!(CK_C_DigestInit(session, &mut mechanism), CKR_OK);
+        let mut first = b"ab".to_vec();
+        let mut second = b"c".to_vec();
+        assert_eq!(CK_C_DigestUpdate(session, first.as_mut_ptr(), first.len()), CKR_OK);
+        assert_eq!(CK_C_DigestUpdate(session, second.as_mut_ptr(), second.len()), CKR_OK);
+        let mut len: CK_ULONG = 20;
+        let mut out = vec![0u8; 20];
+        assert_eq!(CK_C_DigestFinal(session, out.as_mut_ptr(), &mut len), CKR_OK);
+        assert_eq!(out[..4], [0xa9, 0x99, 0x3e, 0x36]);
+        CK_C_CloseSession(session);
+    }
+
+    #[test]
+    fn verify_requires_public_key_and_init() {
+        let session = open_session(0);
+        let mut mechanism = CK_MECHANISM {
+            mechanism: CKM_SHA256_RSA_PKCS,
+            pParameter: ptr::null_mut(),
+            ulParameterLen: 0,
+        };
+        // Handle 1 is a private key object; verification needs the public
+        // half.
+        assert_eq!(
+            CK_C_VerifyInit(session, &mut mechanism, 1),
+            CKR_KEY_HANDLE_INVALID
+        );
+        assert_eq!(
+            CK_C_Verify(session, ptr::null_mut(), 0, ptr::null_mut(), 0),
+            CKR_OPERATION_NOT_INITIALIZED
+        );
+        CK_C_CloseSession(session);
+    }
+
+    // The cached structs are copied out wholesale, so their layout must
+    // match what a C caller expects: field offsets follow from these sizes
+    // with no surprises in between.
+    #[test]
+    fn cached_info_struct_layout() {
+        fn padded(unpadded: usize, align: usize) -> usize {
+            (unpadded + align - 1) / align * align
+        }
+        assert_eq!(size_of::<CK_VERSION>(), 2);
+        assert_eq!(
+            size_of::<CK_SLOT_INFO>(),
+            padded(
+                64 + 32 + size_of::<CK_FLAGS>() + 2 + 2,
+                ::std::mem::align_of::<CK_SLOT_INFO>()
+            )
+        );
+        assert_eq!(
+            size_of::<CK_TOKEN_INFO>(),
+            padded(
+                32 + 32 + 16 + 16 + 11 * size_of::<CK_ULONG>() + 2 + 2 + 16,
+                ::std::mem::align_of::<CK_TOKEN_INFO>()
+            )
+        );
+        // CK_INFO has interior padding after libraryVersion's predecessor
+        // fields; make sure the copy still lands every field where the
+        // field-by-field writes used to.
+        let mut info: CK_INFO = unsafe { ::std::mem::zeroed() };
+        assert_eq!(CK_C_GetInfo(&mut info), CKR_OK);
+        assert_eq!(info.cryptokiVersion.major, 2);
+        assert_eq!(info.cryptokiVersion.minor, 20);
+        assert_eq!(&info.manufacturerID[..], &str_to_char32("KryptCo Inc.")[..]);
+        assert_eq!(
+            &info.libraryDescription[..],
+            &str_to_char32("Krypton PKCS11 middleware")[..]
+        );
+    }
+
+    #[test]
+    fn mutating_call_on_unknown_session() {
+        assert_eq!(
+            pkcs11_unused::CK_C_SetAttributeValue(!0, 1, ptr::null_mut(), 0),
+            CKR_SESSION_HANDLE_INVALID
+        );
+    }
+
+    // C_GetMechanismList and C_GetMechanismInfo must agree: every
+    // advertised mechanism has real limits, everything else is
+    // CKR_MECHANISM_INVALID rather than CKR_OK over garbage.
+    #[test]
+    fn mechanism_list_and_info_agree() {
+        let mut count: CK_ULONG = 0;
+        assert_eq!(
+            CK_C_GetMechanismList(KRYPTON_SLOT_ID, ptr::null_mut(), &mut count),
+            CKR_OK
+        );
+        let mut mechanisms = vec![0 as CK_MECHANISM_TYPE; count];
+        assert_eq!(
+            CK_C_GetMechanismList(KRYPTON_SLOT_ID, mechanisms.as_mut_ptr(), &mut count),
+            CKR_OK
+        );
+        for &mechanism in &mechanisms {
+            let mut info: CK_MECHANISM_INFO = unsafe { ::std::mem::zeroed() };
+            assert_eq!(
+                CK_C_GetMechanismInfo(KRYPTON_SLOT_ID, mechanism, &mut info),
+                CKR_OK
+            );
+            assert_ne!(info.flags, 0, "mechanism {} reports no capability", mechanism);
+        }
+        for probe in 0..0x2000 {
+            if mechanisms.contains(&probe) {
+                continue;
+            }
+            let mut info: CK_MECHANISM_INFO = unsafe { ::std::mem::zeroed() };
+            assert_eq!(
+                CK_C_GetMechanismInfo(KRYPTON_SLOT_ID, probe, &mut info),
+                CKR_MECHANISM_INVALID
+            );
+        }
+    }
+
+    // Hosts do pass null output pointers (e.g. a miswired p11-kit
+    // config); every entry point that writes through one must fail with
+    // CKR_ARGUMENTS_BAD instead of crashing the host process.
+    #[test]
+    fn null_output_pointers_are_rejected() {
+        assert_eq!(CK_C_GetInfo(ptr::null_mut()), CKR_ARGUMENTS_BAD);
+        assert_eq!(
+            CK_C_GetSlotInfo(KRYPTON_SLOT_ID, ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_GetTokenInfo(KRYPTON_SLOT_ID, ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_GetMechanismInfo(KRYPTON_SLOT_ID, CKM_ECDSA, ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        // The count pointer is mandatory in the two-call convention.
+        assert_eq!(
+            CK_C_GetSlotList(0, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_WaitForSlotEvent(CKF_DONT_BLOCK, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_OpenSession(
+                KRYPTON_SLOT_ID,
+                CKF_SERIAL_SESSION,
+                ptr::null_mut(),
+                None,
+                ptr::null_mut(),
+            ),
+            CKR_ARGUMENTS_BAD
+        );
+        // A bad phSession must not have left a stray session behind.
+        let session = open_session(0);
+        assert_eq!(
+            CK_C_GetSessionInfo(session, ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        // Pointer validation precedes the session and operation checks,
+        // so these reject even without an active operation behind them.
+        assert_eq!(
+            CK_C_Sign(session, ptr::null_mut(), 0, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_FindObjects(session, ptr::null_mut(), 1, ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        // A null handle buffer is only acceptable when no handles could
+        // be written into it.
+        let mut count: CK_ULONG = 0;
+        assert_eq!(
+            CK_C_FindObjects(session, ptr::null_mut(), 1, &mut count),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_Digest(session, ptr::null_mut(), 0, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_DigestFinal(session, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_GetOperationState(session, ptr::null_mut(), ptr::null_mut()),
+            CKR_ARGUMENTS_BAD
+        );
+        // Null mechanism and input pointers are rejected the same way.
+        assert_eq!(CK_C_DigestInit(session, ptr::null_mut()), CKR_ARGUMENTS_BAD);
+        assert_eq!(CK_C_SignInit(session, ptr::null_mut(), 1), CKR_ARGUMENTS_BAD);
+        assert_eq!(CK_C_VerifyInit(session, ptr::null_mut(), 2), CKR_ARGUMENTS_BAD);
+        assert_eq!(
+            CK_C_Verify(session, ptr::null_mut(), 1, ptr::null_mut(), 0),
+            CKR_ARGUMENTS_BAD
+        );
+        assert_eq!(
+            CK_C_SetOperationState(session, ptr::null_mut(), 0, CK_INVALID_HANDLE, CK_INVALID_HANDLE),
+            CKR_ARGUMENTS_BAD
+        );
+        CK_C_CloseSession(session);
+    }
+
+    // The state blob is host-supplied; one encoding key handle 0 must be
+    // rejected as invalid saved state, not trip object_class's handle
+    // arithmetic.
+    #[test]
+    fn restoring_state_with_zero_key_handle_is_rejected() {
+        let session = open_session(0);
+        let mut blob = vec![OPERATION_STATE_VERSION, 1];
+        serialize_ulong(&mut blob, 0); // key handle
+        serialize_ulong(&mut blob, CKM_ECDSA); // mechanism
+        assert_eq!(
+            CK_C_SetOperationState(
+                session,
+                blob.as_mut_ptr(),
+                blob.len(),
+                CK_INVALID_HANDLE,
+                CK_INVALID_HANDLE,
+            ),
+            CKR_SAVED_STATE_INVALID
+        );
+        CK_C_CloseSession(session);
+    }
+}
@@ -0,0 +1,215 @@
+//! The textbook RSA public-key operation.
+//!
+//! `C_Verify` runs locally against the exposed public key. ring's
+//! verification API insists on hashing the message itself, which rules out
+//! the pre-hashed input `CKM_RSA_PKCS` callers hand us, so the public-key
+//! operation is computed here directly and the caller compares the
+//! recovered encoded message. Only public values are involved, so the
+//! usual constant-time concerns for RSA do not apply.
+
+/// Computes `sig^e mod n` and returns the result as a big-endian byte
+/// string padded to the length of `n`, i.e. the PKCS#1 encoded message.
+///
+/// Returns `None` when the inputs are degenerate (zero or even modulus,
+/// signature not below the modulus).
+pub fn public_op(n_be: &[u8], e_be: &[u8], sig_be: &[u8]) -> Option<Vec<u8>> {
+    let n = from_be(n_be);
+    let sig = from_be(sig_be);
+    if n.len() < 1 || n[0] & 1 == 0 {
+        return None;
+    }
+    if compare(&sig, &n) != ::std::cmp::Ordering::Less {
+        return None;
+    }
+    // Left-to-right square-and-multiply over the bits of e; e is public
+    // and small (usually 65537), so this is a handful of multiplications.
+    let mut acc = vec![1u64];
+    let mut started = false;
+    for byte in e_be {
+        for bit in (0..8).rev() {
+            if started {
+                acc = mod_reduce(multiply(&acc, &acc), &n);
+            }
+            if byte >> bit & 1 == 1 {
+                if started {
+                    acc = mod_reduce(multiply(&acc, &sig), &n);
+                } else {
+                    acc = sig.clone();
+                    started = true;
+                }
+            }
+        }
+    }
+    if !started {
+        // e == 0
+        return None;
+    }
+    Some(to_be(&acc, n_be.len()))
+}
+
+// Big integers are little-endian vectors of u64 limbs with no trailing
+// zero limbs.
+
+fn from_be(bytes: &[u8]) -> Vec<u64> {
+    let mut limbs = Vec::with_capacity(bytes.len() / 8 + 1);
+    for chunk in bytes.rchunks(8) {
+        let mut limb = 0u64;
+        for byte in chunk {
+            limb = limb << 8 | *byte as u64;
+        }
+        limbs.push(limb);
+    }
+    trim(&mut limbs);
+    limbs
+}
+
+fn to_be(limbs: &[u64], width: usize) -> Vec<u8> {
+    let mut out = vec![0u8; width];
+    let mut i = width;
+    'outer: for limb in limbs {
+        for shift in 0..8 {
+            if i == 0 {
+                break 'outer;
+            }
+            i -= 1;
+            out[i] = (limb >> (8 * shift)) as u8;
+        }
+    }
+    out
+}
+
+fn trim(limbs: &mut Vec<u64>) {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+}
+
+fn compare(a: &[u64], b: &[u64]) -> ::std::cmp::Ordering {
+    if a.len() != b.len() {
+        return a.len().cmp(&b.len());
+    }
+    for i in (0..a.len()).rev() {
+        if a[i] != b[i] {
+            return a[i].cmp(&b[i]);
+        }
+    }
+    ::std::cmp::Ordering::Equal
+}
+
+fn bit_length(a: &[u64]) -> usize {
+    match a.last() {
+        Some(top) => (a.len() - 1) * 64 + (64 - top.leading_zeros() as usize),
+        None => 0,
+    }
+}
+
+fn multiply(a: &[u64], b: &[u64]) -> Vec<u64> {
+    let mut out = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0u128;
+        for (j, &y) in b.iter().enumerate() {
+            let sum = out[i + j] as u128 + x as u128 * y as u128 + carry;
+            out[i + j] = sum as u64;
+            carry = sum >> 64;
+        }
+        out[i + b.len()] = carry as u64;
+    }
+    trim(&mut out);
+    out
+}
+
+/// Shifts `a` left by `bits` (0..64) whole bits.
+fn shift_left(a: &[u64], limbs: usize, bits: usize) -> Vec<u64> {
+    let mut out = vec![0u64; limbs];
+    out.extend_from_slice(a);
+    if bits > 0 {
+        let mut carry = 0u64;
+        for limb in out.iter_mut() {
+            let next_carry = *limb >> (64 - bits);
+            *limb = *limb << bits | carry;
+            carry = next_carry;
+        }
+        if carry > 0 {
+            out.push(carry);
+        }
+    }
+    out
+}
+
+fn subtract_in_place(a: &mut Vec<u64>, b: &[u64]) {
+    let mut borrow = 0u64;
+    for i in 0..a.len() {
+        let rhs = *b.get(i).unwrap_or(&0) as u128 + borrow as u128;
+        let lhs = a[i] as u128;
+        if lhs >= rhs {
+            a[i] = (lhs - rhs) as u64;
+            borrow = 0;
+        } else {
+            a[i] = ((1u128 << 64) + lhs - rhs) as u64;
+            borrow = 1;
+        }
+    }
+    trim(a);
+}
+
+/// Binary long division: repeatedly subtracts the largest aligned multiple
+/// of `n`. One pass per bit of the excess, each a linear scan — plenty for
+/// the dozen-odd multiplications a verification needs.
+fn mod_reduce(mut a: Vec<u64>, n: &[u64]) -> Vec<u64> {
+    while compare(&a, n) != ::std::cmp::Ordering::Less {
+        let excess = bit_length(&a) - bit_length(n);
+        let shifted = shift_left(n, excess / 64, excess % 64);
+        if compare(&a, &shifted) == ::std::cmp::Ordering::Less {
+            let shifted = shift_left(n, (excess - 1) / 64, (excess - 1) % 64);
+            subtract_in_place(&mut a, &shifted);
+        } else {
+            subtract_in_place(&mut a, &shifted);
+        }
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn textbook_example() {
+        // n = 61 * 53 = 3233, e = 17, 2790^17 mod 3233 = 1452. (2790 is the
+        // textbook ciphertext of 65; the public operation maps it onward,
+        // not back — only d = 2753 recovers 65.)
+        let em = public_op(&[0x0c, 0xa1], &[17], &[0x0a, 0xe6]).unwrap();
+        assert_eq!(em, [0x05, 0xac]);
+    }
+
+    #[test]
+    fn multi_limb_modulus() {
+        let n = [
+            0x90, 0xf0, 0xa3, 0x0c, 0xc9, 0x13, 0x8f, 0xaf, 0x04, 0x21, 0x5d, 0x58, 0xa6, 0xf0,
+            0xaa, 0xdc, 0x22, 0xb8, 0x0b, 0xb4, 0xd8, 0xc8, 0x66, 0xe2, 0x61, 0x02, 0x65, 0x9a,
+            0xf1, 0x91, 0xf3, 0x6b,
+        ];
+        let sig = [
+            0x39, 0x8e, 0x21, 0x90, 0xb9, 0x14, 0xc8, 0x48, 0x6b, 0xae, 0xe5, 0x6d, 0x2d, 0x8f,
+            0x36, 0x79, 0x51, 0xc3, 0x57, 0x11, 0x03, 0x3c, 0xdc, 0x98, 0x00, 0x53, 0x56, 0x7a,
+            0xf4, 0x18, 0xef, 0xff,
+        ];
+        let em = public_op(&n, &[0x01, 0x00, 0x01], &sig).unwrap();
+        let mut expected = vec![0u8; 22];
+        expected.extend_from_slice(&[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0xab, 0xcd, 0xef]);
+        assert_eq!(em, expected);
+    }
+
+    #[test]
+    fn rejects_signature_not_below_modulus() {
+        assert!(public_op(&[0x0c, 0xa1], &[17], &[0x0c, 0xa1]).is_none());
+        assert!(public_op(&[0x0c, 0xa1], &[17], &[0xff, 0xff]).is_none());
+    }
+
+    #[test]
+    fn rejects_even_or_zero_modulus() {
+        assert!(public_op(&[0x10], &[17], &[0x02]).is_none());
+        assert!(public_op(&[], &[17], &[]).is_none());
+        assert!(public_op(&[0x0c, 0xa1], &[], &[0x0a, 0xe6]).is_none());
+    }
+}
@@ -0,0 +1,245 @@
+//! Soft-token fallback backend.
+//!
+//! With `KR_PKCS11_SOFT=1` the shim skips krd entirely and signs in-process
+//! with a local PEM private key (default `~/.kr/soft-key.pem`, override with
+//! `KR_PKCS11_SOFT_KEY`). This exists so CI can drive the full PKCS#11
+//! surface without a paired phone, and so users can verify an application
+//! integration deterministically. It must never be enabled in production:
+//! the whole point of Krypton is that the key is not on this machine.
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ring::rand::SystemRandom;
+use ring::signature::{self, Ed25519KeyPair, RSAKeyPair, RSASigningState};
+use untrusted::Input;
+
+use agent::{write_bytes, write_u32, Identity};
+use pkcs11::*;
+use pkcs11shim::kr_path;
+
+pub const SOFT_MODE_VAR: &'static str = "KR_PKCS11_SOFT";
+pub const SOFT_KEY_PATH_VAR: &'static str = "KR_PKCS11_SOFT_KEY";
+pub const DEFAULT_SOFT_KEY_FILE: &'static str = "soft-key.pem";
+
+/// Whether the soft-token backend is active for this process.
+pub fn enabled() -> bool {
+    env::var(SOFT_MODE_VAR).map(|v| v == "1").unwrap_or(false)
+}
+
+enum SoftKeyPair {
+    // Arc because ring signs through a per-operation RSASigningState that
+    // shares ownership of the key pair.
+    Rsa(Arc<RSAKeyPair>),
+    Ed25519(Ed25519KeyPair),
+}
+
+/// A local private key standing in for the phone.
+pub struct SoftKey {
+    key_pair: SoftKeyPair,
+    public_key_blob: Vec<u8>,
+}
+
+lazy_static! {
+    static ref SOFT_KEY: Option<SoftKey> = match load() {
+        Ok(key) => Some(key),
+        Err(e) => {
+            error!("soft token: could not load key: {}", e);
+            None
+        }
+    };
+}
+
+fn key_path() -> PathBuf {
+    match env::var(SOFT_KEY_PATH_VAR) {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => kr_path(DEFAULT_SOFT_KEY_FILE),
+    }
+}
+
+fn load() -> io::Result<SoftKey> {
+    let mut pem = String::new();
+    File::open(key_path())?.read_to_string(&mut pem)?;
+    let (label, der) = parse_pem(&pem)?;
+    match &label[..] {
+        "RSA PRIVATE KEY" => {
+            let key_pair = RSAKeyPair::from_der(Input::from(&der))
+                .map_err(|_| invalid("unparseable RSA private key"))?;
+            let (n, e) = rsa_public_components(&der)?;
+            Ok(SoftKey {
+                key_pair: SoftKeyPair::Rsa(Arc::new(key_pair)),
+                public_key_blob: ssh_rsa_blob(&e, &n),
+            })
+        }
+        "PRIVATE KEY" => {
+            let key_pair = Ed25519KeyPair::from_pkcs8(Input::from(&der))
+                .map_err(|_| invalid("unparseable PKCS#8 key (only Ed25519 is supported)"))?;
+            let public = key_pair.public_key_bytes().to_vec();
+            Ok(SoftKey {
+                key_pair: SoftKeyPair::Ed25519(key_pair),
+                public_key_blob: ssh_ed25519_blob(&public),
+            })
+        }
+        other => Err(invalid(&format!("unsupported PEM type {:?}", other))),
+    }
+}
+
+/// The soft token exposes exactly one identity, mirroring what krd reports
+/// for the paired phone.
+pub fn identities() -> io::Result<Vec<Identity>> {
+    match *SOFT_KEY {
+        Some(ref key) => Ok(vec![Identity {
+            key_blob: key.public_key_blob.clone(),
+            comment: "krypton soft token".to_owned(),
+        }]),
+        None => Err(invalid("soft token key not loaded")),
+    }
+}
+
+/// Signs `data` under `mechanism` with the local key.
+pub fn sign(data: &[u8], mechanism: CK_MECHANISM_TYPE) -> io::Result<Vec<u8>> {
+    let key = match *SOFT_KEY {
+        Some(ref key) => key,
+        None => return Err(invalid("soft token key not loaded")),
+    };
+    match key.key_pair {
+        SoftKeyPair::Rsa(ref key_pair) => {
+            // ring deliberately does not implement PKCS#1 SHA-1 signing,
+            // so CKM_SHA1_RSA_PKCS only works against the agent backend.
+            let padding = match mechanism {
+                CKM_SHA256_RSA_PKCS => &signature::RSA_PKCS1_SHA256,
+                CKM_SHA512_RSA_PKCS => &signature::RSA_PKCS1_SHA512,
+                _ => {
+                    return Err(invalid(
+                        "soft token only supports SHA-256/SHA-512 RSA mechanisms",
+                    ))
+                }
+            };
+            let mut state = RSASigningState::new(key_pair.clone())
+                .map_err(|_| invalid("could not set up RSA signing"))?;
+            let mut sig = vec![0u8; key_pair.public_modulus_len()];
+            state
+                .sign(padding, &SystemRandom::new(), data, &mut sig)
+                .map_err(|_| invalid("RSA signing failed"))?;
+            Ok(sig)
+        }
+        SoftKeyPair::Ed25519(ref key_pair) => Ok(key_pair.sign(data).as_ref().to_vec()),
+    }
+}
+
+fn parse_pem(pem: &str) -> io::Result<(String, Vec<u8>)> {
+    let mut label = None;
+    let mut body = String::new();
+    for line in pem.lines() {
+        let line = line.trim();
+        if line.starts_with("-----BEGIN ") && line.ends_with("-----") {
+            label = Some(line["-----BEGIN ".len()..line.len() - "-----".len()].to_owned());
+        } else if line.starts_with("-----END ") {
+            break;
+        } else if label.is_some() {
+            body.push_str(line);
+        }
+    }
+    let label = label.ok_or_else(|| invalid("no PEM block found"))?;
+    let der = ::base64::decode(&body).map_err(|_| invalid("bad base64 in PEM body"))?;
+    Ok((label, der))
+}
+
+/// Pulls the modulus and public exponent out of a DER RSAPrivateKey
+/// (RFC 8017 A.1.2: SEQUENCE of version, n, e, d, ...).
+fn rsa_public_components(der: &[u8]) -> io::Result<(Vec<u8>, Vec<u8>)> {
+    let mut reader = DerReader { der: der, pos: 0 };
+    reader.expect_sequence()?;
+    let _version = reader.read_integer()?;
+    let n = reader.read_integer()?;
+    let e = reader.read_integer()?;
+    Ok((n, e))
+}
+
+struct DerReader<'a> {
+    der: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DerReader<'a> {
+    fn byte(&mut self) -> io::Result<u8> {
+        let b = *self.der.get(self.pos).ok_or_else(|| invalid("truncated DER"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn length(&mut self) -> io::Result<usize> {
+        let first = self.byte()?;
+        if first & 0x80 == 0 {
+            return Ok(first as usize);
+        }
+        let mut len = 0usize;
+        for _ in 0..(first & 0x7f) {
+            len = len.checked_shl(8).ok_or_else(|| invalid("DER length overflow"))?;
+            len |= self.byte()? as usize;
+        }
+        Ok(len)
+    }
+
+    fn expect_sequence(&mut self) -> io::Result<()> {
+        if self.byte()? != 0x30 {
+            return Err(invalid("expected DER SEQUENCE"));
+        }
+        self.length()?;
+        Ok(())
+    }
+
+    fn read_integer(&mut self) -> io::Result<Vec<u8>> {
+        if self.byte()? != 0x02 {
+            return Err(invalid("expected DER INTEGER"));
+        }
+        let len = self.length()?;
+        if self.pos + len > self.der.len() {
+            return Err(invalid("truncated DER INTEGER"));
+        }
+        let value = self.der[self.pos..self.pos + len].to_vec();
+        self.pos += len;
+        Ok(value)
+    }
+}
+
+fn ssh_rsa_blob(e: &[u8], n: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    write_string(&mut blob, "ssh-rsa");
+    write_mpint(&mut blob, e);
+    write_mpint(&mut blob, n);
+    blob
+}
+
+fn ssh_ed25519_blob(public: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::new();
+    write_string(&mut blob, "ssh-ed25519");
+    write_bytes(&mut blob, public);
+    blob
+}
+
+fn write_string(out: &mut Vec<u8>, s: &str) {
+    write_bytes(out, s.as_bytes());
+}
+
+fn write_mpint(out: &mut Vec<u8>, bytes: &[u8]) {
+    let mut start = 0;
+    while start < bytes.len() && bytes[start] == 0 {
+        start += 1;
+    }
+    let trimmed = &bytes[start..];
+    if trimmed.first().map_or(false, |&b| b & 0x80 != 0) {
+        write_u32(out, trimmed.len() as u32 + 1);
+        out.push(0);
+        out.extend_from_slice(trimmed);
+    } else {
+        write_bytes(out, trimmed);
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,274 @@
+//! Optional call tracing in the style of OpenSC's pkcs11-spy.
+//!
+//! Setting `KR_PKCS11_SPY=/path/trace.log` appends one line per PKCS#11
+//! call with decoded arguments — mechanism and attribute names, buffer
+//! lengths, return values — so integration problems with third-party
+//! hosts can be diagnosed from the trace alone, without rebuilding the
+//! host or attaching a debugger. Attribute *values* are never written:
+//! a trace left behind on a shared machine must not leak key material.
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::slice;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use libc;
+
+use pkcs11::*;
+
+/// Path of the trace log; tracing is off when unset or empty.
+pub const SPY_VAR: &'static str = "KR_PKCS11_SPY";
+
+lazy_static! {
+    static ref TRACE: Option<Mutex<File>> = open_trace();
+}
+
+fn open_trace() -> Option<Mutex<File>> {
+    let path = match env::var(SPY_VAR) {
+        Ok(ref path) if !path.is_empty() => path.clone(),
+        _ => return None,
+    };
+    // Append so traces survive the host reloading the module; an
+    // unopenable path silently disables tracing rather than failing
+    // C_Initialize over a diagnostic aid.
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .ok()
+        .map(Mutex::new)
+}
+
+pub fn enabled() -> bool {
+    TRACE.is_some()
+}
+
+/// Appends one line to the trace, prefixed with the pid and unix time so
+/// traces from several processes sharing one log stay attributable.
+pub fn trace(message: &str) {
+    if let Some(ref file) = *TRACE {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let pid = unsafe { libc::getpid() };
+        if let Ok(mut file) = file.lock() {
+            let _ = writeln!(
+                file,
+                "[{} {}.{:03}] {}",
+                pid,
+                now.as_secs(),
+                now.subsec_millis(),
+                message
+            );
+        }
+    }
+}
+
+/// The spec name of a return value, e.g. `CKR_BUFFER_TOO_SMALL`.
+/// Returns `"CKR_?"` for codes the shim never produces; `&'static str`
+/// so error-path tests can assert on names without allocating.
+pub fn ckr_name(rv: CK_RV) -> &'static str {
+    match rv {
+        CKR_OK => "CKR_OK",
+        CKR_CANCEL => "CKR_CANCEL",
+        CKR_HOST_MEMORY => "CKR_HOST_MEMORY",
+        CKR_SLOT_ID_INVALID => "CKR_SLOT_ID_INVALID",
+        CKR_GENERAL_ERROR => "CKR_GENERAL_ERROR",
+        CKR_FUNCTION_FAILED => "CKR_FUNCTION_FAILED",
+        CKR_ARGUMENTS_BAD => "CKR_ARGUMENTS_BAD",
+        CKR_NO_EVENT => "CKR_NO_EVENT",
+        CKR_ATTRIBUTE_SENSITIVE => "CKR_ATTRIBUTE_SENSITIVE",
+        CKR_ATTRIBUTE_TYPE_INVALID => "CKR_ATTRIBUTE_TYPE_INVALID",
+        CKR_ATTRIBUTE_VALUE_INVALID => "CKR_ATTRIBUTE_VALUE_INVALID",
+        CKR_DATA_INVALID => "CKR_DATA_INVALID",
+        CKR_DATA_LEN_RANGE => "CKR_DATA_LEN_RANGE",
+        CKR_DEVICE_ERROR => "CKR_DEVICE_ERROR",
+        CKR_DEVICE_MEMORY => "CKR_DEVICE_MEMORY",
+        CKR_DEVICE_REMOVED => "CKR_DEVICE_REMOVED",
+        CKR_FUNCTION_CANCELED => "CKR_FUNCTION_CANCELED",
+        CKR_FUNCTION_NOT_SUPPORTED => "CKR_FUNCTION_NOT_SUPPORTED",
+        CKR_KEY_HANDLE_INVALID => "CKR_KEY_HANDLE_INVALID",
+        CKR_KEY_TYPE_INCONSISTENT => "CKR_KEY_TYPE_INCONSISTENT",
+        CKR_KEY_FUNCTION_NOT_PERMITTED => "CKR_KEY_FUNCTION_NOT_PERMITTED",
+        CKR_MECHANISM_INVALID => "CKR_MECHANISM_INVALID",
+        CKR_MECHANISM_PARAM_INVALID => "CKR_MECHANISM_PARAM_INVALID",
+        CKR_OBJECT_HANDLE_INVALID => "CKR_OBJECT_HANDLE_INVALID",
+        CKR_OPERATION_ACTIVE => "CKR_OPERATION_ACTIVE",
+        CKR_OPERATION_NOT_INITIALIZED => "CKR_OPERATION_NOT_INITIALIZED",
+        CKR_PIN_INCORRECT => "CKR_PIN_INCORRECT",
+        CKR_SESSION_CLOSED => "CKR_SESSION_CLOSED",
+        CKR_SESSION_COUNT => "CKR_SESSION_COUNT",
+        CKR_SESSION_HANDLE_INVALID => "CKR_SESSION_HANDLE_INVALID",
+        CKR_SESSION_PARALLEL_NOT_SUPPORTED => "CKR_SESSION_PARALLEL_NOT_SUPPORTED",
+        CKR_SESSION_READ_ONLY => "CKR_SESSION_READ_ONLY",
+        CKR_SESSION_READ_ONLY_EXISTS => "CKR_SESSION_READ_ONLY_EXISTS",
+        CKR_SIGNATURE_INVALID => "CKR_SIGNATURE_INVALID",
+        CKR_SIGNATURE_LEN_RANGE => "CKR_SIGNATURE_LEN_RANGE",
+        CKR_TEMPLATE_INCOMPLETE => "CKR_TEMPLATE_INCOMPLETE",
+        CKR_TEMPLATE_INCONSISTENT => "CKR_TEMPLATE_INCONSISTENT",
+        CKR_TOKEN_NOT_PRESENT => "CKR_TOKEN_NOT_PRESENT",
+        CKR_TOKEN_NOT_RECOGNIZED => "CKR_TOKEN_NOT_RECOGNIZED",
+        CKR_TOKEN_WRITE_PROTECTED => "CKR_TOKEN_WRITE_PROTECTED",
+        CKR_USER_ALREADY_LOGGED_IN => "CKR_USER_ALREADY_LOGGED_IN",
+        CKR_USER_NOT_LOGGED_IN => "CKR_USER_NOT_LOGGED_IN",
+        CKR_USER_PIN_NOT_INITIALIZED => "CKR_USER_PIN_NOT_INITIALIZED",
+        CKR_USER_TYPE_INVALID => "CKR_USER_TYPE_INVALID",
+        CKR_BUFFER_TOO_SMALL => "CKR_BUFFER_TOO_SMALL",
+        CKR_SAVED_STATE_INVALID => "CKR_SAVED_STATE_INVALID",
+        CKR_STATE_UNSAVEABLE => "CKR_STATE_UNSAVEABLE",
+        CKR_CRYPTOKI_NOT_INITIALIZED => "CKR_CRYPTOKI_NOT_INITIALIZED",
+        CKR_CRYPTOKI_ALREADY_INITIALIZED => "CKR_CRYPTOKI_ALREADY_INITIALIZED",
+        _ => "CKR_?",
+    }
+}
+
+/// Renders a flag word against a name table, e.g. `CKF_SERIAL_SESSION |
+/// CKF_RW_SESSION`; bits without a name are kept as a hex residue so
+/// nothing disappears from the trace.
+pub fn ckf_names(flags: CK_FLAGS, names: &[(CK_FLAGS, &'static str)]) -> String {
+    if flags == 0 {
+        return "0".to_owned();
+    }
+    let mut parts = Vec::new();
+    let mut rest = flags;
+    for &(bit, name) in names {
+        if rest & bit != 0 {
+            parts.push(name.to_owned());
+            rest &= !bit;
+        }
+    }
+    if rest != 0 {
+        parts.push(format!("{:#x}", rest));
+    }
+    parts.join(" | ")
+}
+
+/// `CK_SESSION_INFO`/`C_OpenSession` flag names.
+pub const SESSION_FLAG_NAMES: &'static [(CK_FLAGS, &'static str)] = &[
+    (CKF_RW_SESSION, "CKF_RW_SESSION"),
+    (CKF_SERIAL_SESSION, "CKF_SERIAL_SESSION"),
+];
+
+/// `CK_MECHANISM_INFO` flag names.
+pub const MECHANISM_FLAG_NAMES: &'static [(CK_FLAGS, &'static str)] = &[
+    (CKF_HW, "CKF_HW"),
+    (CKF_DIGEST, "CKF_DIGEST"),
+    (CKF_SIGN, "CKF_SIGN"),
+    (CKF_VERIFY, "CKF_VERIFY"),
+];
+
+pub fn mechanism_name(mechanism: CK_MECHANISM_TYPE) -> String {
+    let name = match mechanism {
+        CKM_RSA_PKCS => "CKM_RSA_PKCS",
+        CKM_SHA1_RSA_PKCS => "CKM_SHA1_RSA_PKCS",
+        CKM_SHA256_RSA_PKCS => "CKM_SHA256_RSA_PKCS",
+        CKM_SHA384_RSA_PKCS => "CKM_SHA384_RSA_PKCS",
+        CKM_SHA512_RSA_PKCS => "CKM_SHA512_RSA_PKCS",
+        CKM_SHA_1 => "CKM_SHA_1",
+        CKM_SHA256 => "CKM_SHA256",
+        CKM_SHA384 => "CKM_SHA384",
+        CKM_SHA512 => "CKM_SHA512",
+        CKM_ECDSA => "CKM_ECDSA",
+        CKM_ECDSA_SHA1 => "CKM_ECDSA_SHA1",
+        _ => return format!("CKM_{:#x}", mechanism),
+    };
+    name.to_owned()
+}
+
+pub fn attribute_name(attribute: CK_ATTRIBUTE_TYPE) -> String {
+    let name = match attribute {
+        CKA_CLASS => "CKA_CLASS",
+        CKA_TOKEN => "CKA_TOKEN",
+        CKA_PRIVATE => "CKA_PRIVATE",
+        CKA_LABEL => "CKA_LABEL",
+        CKA_VALUE => "CKA_VALUE",
+        CKA_KEY_TYPE => "CKA_KEY_TYPE",
+        CKA_ID => "CKA_ID",
+        CKA_SENSITIVE => "CKA_SENSITIVE",
+        CKA_SIGN => "CKA_SIGN",
+        CKA_VERIFY => "CKA_VERIFY",
+        CKA_MODULUS => "CKA_MODULUS",
+        CKA_MODULUS_BITS => "CKA_MODULUS_BITS",
+        CKA_PUBLIC_EXPONENT => "CKA_PUBLIC_EXPONENT",
+        CKA_EXTRACTABLE => "CKA_EXTRACTABLE",
+        CKA_EC_PARAMS => "CKA_EC_PARAMS",
+        CKA_EC_POINT => "CKA_EC_POINT",
+        CKA_ALWAYS_AUTHENTICATE => "CKA_ALWAYS_AUTHENTICATE",
+        _ => return format!("CKA_{:#x}", attribute),
+    };
+    name.to_owned()
+}
+
+/// Renders a template as `CKA_CLASS[8], CKA_ID[20]` — attribute types and
+/// declared lengths only, never the pointed-to values.
+pub fn template_summary(pTemplate: CK_ATTRIBUTE_PTR, ulCount: CK_ULONG) -> String {
+    if pTemplate.is_null() || ulCount == 0 {
+        return "<empty>".to_owned();
+    }
+    let attributes = unsafe { slice::from_raw_parts(pTemplate, ulCount) };
+    let parts: Vec<String> = attributes
+        .iter()
+        .map(|attribute| {
+            format!(
+                "{}[{}]",
+                attribute_name(attribute.attrType),
+                attribute.ulValueLen
+            )
+        })
+        .collect();
+    parts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn decodes_known_and_unknown_values() {
+        assert_eq!(mechanism_name(CKM_ECDSA), "CKM_ECDSA");
+        assert_eq!(mechanism_name(0x9999), "CKM_0x9999");
+        assert_eq!(attribute_name(CKA_LABEL), "CKA_LABEL");
+        assert_eq!(attribute_name(0x8000_0001), "CKA_0x80000001");
+    }
+
+    #[test]
+    fn names_return_values_and_flags() {
+        assert_eq!(ckr_name(CKR_OK), "CKR_OK");
+        assert_eq!(ckr_name(CKR_BUFFER_TOO_SMALL), "CKR_BUFFER_TOO_SMALL");
+        assert_eq!(ckr_name(0xdead), "CKR_?");
+        assert_eq!(
+            ckf_names(CKF_SERIAL_SESSION | CKF_RW_SESSION, SESSION_FLAG_NAMES),
+            "CKF_RW_SESSION | CKF_SERIAL_SESSION"
+        );
+        // unnamed bits survive as a hex residue
+        assert_eq!(
+            ckf_names(CKF_SIGN | 0x8000_0000, MECHANISM_FLAG_NAMES),
+            "CKF_SIGN | 0x80000000"
+        );
+        assert_eq!(ckf_names(0, SESSION_FLAG_NAMES), "0");
+    }
+
+    #[test]
+    fn summarizes_templates_without_values() {
+        assert_eq!(template_summary(ptr::null_mut(), 0), "<empty>");
+        let mut secret = b"super secret".to_vec();
+        let mut template = [
+            CK_ATTRIBUTE {
+                attrType: CKA_CLASS,
+                pValue: ptr::null_mut(),
+                ulValueLen: 8,
+            },
+            CK_ATTRIBUTE {
+                attrType: CKA_VALUE,
+                pValue: secret.as_mut_ptr() as CK_VOID_PTR,
+                ulValueLen: secret.len(),
+            },
+        ];
+        let summary = template_summary(template.as_mut_ptr(), template.len());
+        assert_eq!(summary, "CKA_CLASS[8], CKA_VALUE[12]");
+        assert!(!summary.contains("secret"));
+    }
+}
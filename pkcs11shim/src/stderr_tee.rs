@@ -0,0 +1,89 @@
+//! Filtering stderr tee.
+//!
+//! OpenSSH and NSS print noisy "no keys" diagnostics through the host
+//! process's stderr while probing the token. Swallowing stderr wholesale
+//! (the old behavior) also hides genuine errors, so instead we interpose a
+//! pipe on STDERR_FILENO and pump it through a filter thread: known noise
+//! lines are dropped, everything else is forwarded to the saved stderr and
+//! to syslog.
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicIsize, Ordering, ATOMIC_ISIZE_INIT};
+use std::thread;
+
+use krlogging::flags;
+use libc;
+
+use pkcs11_unused::logger;
+
+/// The real stderr fd saved before the pipe was interposed, or -1.
+static SAVED_STDERR: AtomicIsize = ATOMIC_ISIZE_INIT;
+
+/// Substrings identifying OpenSSH's harmless key-probing chatter.
+const NOISE_PATTERNS: &'static [&'static str] = &[
+    "sign_and_send_pubkey: signing failed",
+    "no such identity:",
+];
+
+/// The saved (pre-tee) stderr fd, if the tee is installed.
+pub fn saved_stderr() -> Option<RawFd> {
+    match SAVED_STDERR.load(Ordering::SeqCst) {
+        fd if fd > 0 => Some(fd as RawFd),
+        _ => None,
+    }
+}
+
+fn is_noise(line: &str) -> bool {
+    NOISE_PATTERNS.iter().any(|pattern| line.contains(pattern))
+}
+
+/// Interposes the filter pipe on STDERR_FILENO. Call once, from
+/// C_Initialize. With `KR_DEBUG` the tee stays out of the way entirely;
+/// with `KR_NO_STDERR` it forwards to syslog only.
+pub fn install() {
+    if flags::debug() {
+        return;
+    }
+    let silence = flags::no_stderr();
+    let (read_fd, saved_fd) = unsafe {
+        let saved = libc::dup(libc::STDERR_FILENO);
+        if saved < 0 {
+            return;
+        }
+        libc::fcntl(saved, libc::F_SETFD, libc::FD_CLOEXEC);
+        let mut pipe_fds = [0 as libc::c_int; 2];
+        if libc::pipe(pipe_fds.as_mut_ptr()) != 0 {
+            libc::close(saved);
+            return;
+        }
+        if libc::dup2(pipe_fds[1], libc::STDERR_FILENO) < 0 {
+            libc::close(pipe_fds[0]);
+            libc::close(pipe_fds[1]);
+            libc::close(saved);
+            return;
+        }
+        libc::close(pipe_fds[1]);
+        SAVED_STDERR.store(saved as isize, Ordering::SeqCst);
+        (pipe_fds[0], saved)
+    };
+
+    thread::spawn(move || {
+        let reader = BufReader::new(unsafe { File::from_raw_fd(read_fd) });
+        let mut real_stderr = unsafe { File::from_raw_fd(libc::dup(saved_fd)) };
+        for line in reader.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if is_noise(&line) {
+                continue;
+            }
+            if !silence {
+                let _ = writeln!(real_stderr, "{}", line);
+            }
+            let _ = logger.notice(&line);
+        }
+    });
+}
@@ -0,0 +1,37 @@
+//! Small argument-validation helpers shared by the entry points.
+
+use std::mem;
+
+/// Validates an output pointer handed in by the host — non-null and
+/// aligned for `T` — and turns it into a mutable reference.
+///
+/// PKCS#11 makes a bad pointer the caller's problem, but a segfault
+/// happens inside kr-pkcs11 and gets reported against us; failing the
+/// call with `CKR_ARGUMENTS_BAD` instead makes the consumer's bug
+/// visible as their own. Unsafe because only the caller can vouch that
+/// a non-null, aligned pointer actually refers to writable memory
+/// holding a `T`.
+pub unsafe fn checked_out<'a, T>(ptr: *mut T) -> Option<&'a mut T> {
+    if ptr.is_null() || (ptr as usize) % mem::align_of::<T>() != 0 {
+        return None;
+    }
+    Some(&mut *ptr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn rejects_null_and_misaligned_pointers() {
+        unsafe {
+            assert!(checked_out::<u64>(ptr::null_mut()).is_none());
+            let mut value = 0u64;
+            let ptr = &mut value as *mut u64;
+            assert!(checked_out(ptr).is_some());
+            let misaligned = (ptr as usize + 1) as *mut u64;
+            assert!(checked_out(misaligned).is_none());
+        }
+    }
+}
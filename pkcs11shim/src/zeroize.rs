@@ -0,0 +1,105 @@
+//! Best-effort scrubbing of buffers that held sensitive material.
+//!
+//! Data to be signed, signatures, and whole agent messages pass through
+//! heap buffers that the allocator will happily hand to the next caller.
+//! Security reviews of PKCS#11 middleware expect those buffers wiped
+//! before they are freed, and the long-lived receive buffer locked so it
+//! cannot be paged out to disk.
+
+use std::ptr;
+use std::sync::atomic::{fence, Ordering};
+
+use libc;
+
+/// Overwrites `buf` with zeros through volatile writes so the compiler
+/// cannot elide the wipe as a dead store.
+pub fn wipe(buf: &mut [u8]) {
+    for byte in buf.iter_mut() {
+        unsafe {
+            ptr::write_volatile(byte, 0);
+        }
+    }
+    fence(Ordering::SeqCst);
+}
+
+/// A fixed-capacity buffer that is mlocked for its lifetime and wiped on
+/// drop. Fixed capacity keeps the allocation stable, so the lock obtained
+/// at construction stays valid.
+pub struct LockedBuffer {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl LockedBuffer {
+    pub fn new(capacity: usize) -> LockedBuffer {
+        let data = vec![0u8; capacity];
+        // Best effort: RLIMIT_MEMLOCK may be too small, and a shim must
+        // not fail over it.
+        unsafe {
+            libc::mlock(data.as_ptr() as *const libc::c_void, data.len());
+        }
+        LockedBuffer {
+            data: data,
+            len: 0,
+        }
+    }
+
+    /// Makes the first `len` bytes current and returns them for filling,
+    /// or `None` when `len` exceeds the fixed capacity. Whatever was
+    /// current before is wiped first, so error paths that never reach
+    /// `clear` do not leak into the next message.
+    pub fn fill(&mut self, len: usize) -> Option<&mut [u8]> {
+        self.clear();
+        if len > self.data.len() {
+            return None;
+        }
+        self.len = len;
+        Some(&mut self.data[..len])
+    }
+
+    /// The bytes made current by the last `fill`.
+    pub fn contents(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+
+    /// Wipes the current contents.
+    pub fn clear(&mut self) {
+        let len = self.len;
+        wipe(&mut self.data[..len]);
+        self.len = 0;
+    }
+}
+
+impl Drop for LockedBuffer {
+    fn drop(&mut self) {
+        wipe(&mut self.data);
+        unsafe {
+            libc::munlock(self.data.as_ptr() as *const libc::c_void, self.data.len());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wipe_zeroes_every_byte() {
+        let mut buf = vec![0xaau8; 37];
+        wipe(&mut buf);
+        assert!(buf.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn locked_buffer_round_trip() {
+        let mut buf = LockedBuffer::new(8);
+        {
+            let payload = buf.fill(3).unwrap();
+            payload.copy_from_slice(b"abc");
+        }
+        assert_eq!(buf.contents(), b"abc");
+        buf.clear();
+        assert_eq!(buf.contents(), b"");
+        assert!(buf.fill(9).is_none());
+    }
+}